@@ -18,6 +18,14 @@ pub use gate_descriptor::*;
 pub use port::*;
 
 
+/// 打开中断并停机，直到下一个中断处理完后关闭中断返回
+/// sti 的下一条指令执行完才响应中断，不会在 hlt 之前错过中断
+pub fn wait_for_intr() {
+    unsafe {
+        asm!("sti", "hlt", "cli");
+    }
+}
+
 pub fn outb(value: u8, port: u16) {
     unsafe {
         asm!(
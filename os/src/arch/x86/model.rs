@@ -38,6 +38,29 @@ bitflags! {
         const CD = 1 << 30;
         const PG = 1 << 31;
     }
+
+    pub struct Cr4: u32 {
+        const VME = 1;
+        const PVI = 1 << 1;
+        const TSD = 1 << 2;
+        const DE = 1 << 3;
+        const PSE = 1 << 4;
+        const PAE = 1 << 5;
+        const MCE = 1 << 6;
+        const PGE = 1 << 7;
+        const PCE = 1 << 8;
+        const OSFXSR = 1 << 9;
+        const OSXMMEXCPT = 1 << 10;
+    }
+
+    /// Extended Feature Enable Register，MSR 0xC0000080
+    pub struct Efer: u64 {
+        const SCE = 1;
+        const LME = 1 << 8;
+        const LMA = 1 << 10;
+        /// No-Execute Enable，开启后页表项的 bit 63 表示不可执行（需要 PAE 分页）
+        const NXE = 1 << 11;
+    }
 }
 
 impl Eflags {
@@ -74,6 +97,68 @@ impl Cr0 {
     }
 }
 
+impl Cr4 {
+    pub fn read() -> Self {
+        let mut value: u32 = 0;
+        unsafe {
+            asm!("mov eax, cr4", out("eax") value);
+        }
+        Cr4::from_bits_truncate(value)
+    }
+
+    pub fn write(&self) {
+        let value = self.bits();
+        unsafe {
+            asm!(
+                "mov cr4, eax",
+                in("eax") value
+            );
+        }
+    }
+}
+
+impl Efer {
+    const MSR: u32 = 0xc0000080;
+
+    pub fn read() -> Self {
+        Efer::from_bits_truncate(rdmsr(Self::MSR))
+    }
+
+    pub fn write(&self) {
+        wrmsr(Self::MSR, self.bits());
+    }
+}
+
+pub fn rdmsr(msr: u32) -> u64 {
+    let low: u32;
+    let high: u32;
+    unsafe {
+        asm!("rdmsr", in("ecx") msr, out("eax") low, out("edx") high);
+    }
+    ((high as u64) << 32) | low as u64
+}
+
+pub fn wrmsr(msr: u32, value: u64) {
+    let low = (value & 0xffffffff) as u32;
+    let high = (value >> 32) as u32;
+    unsafe {
+        asm!("wrmsr", in("ecx") msr, in("eax") low, in("edx") high);
+    }
+}
+
+/// 发生缺页异常的线性地址
+pub struct Cr2;
+
+impl Cr2 {
+    pub fn read() -> usize {
+        let mut value: u32 = 0;
+        unsafe {
+            asm!("mov eax, cr2", out("eax") value);
+        }
+        value as usize
+    }
+}
+
 pub struct ESP;
 
 impl ESP {
@@ -1,9 +1,20 @@
 use core::{arch::asm, fmt::Display};
 use bitflags::bitflags;
 
+/// 页表项的位数，PAE 分页的页表项是 64 位
+#[cfg(not(feature = "pae"))]
+pub type PageEntryBits = u32;
+#[cfg(feature = "pae")]
+pub type PageEntryBits = u64;
+
+/// 页表项中物理地址所在的位
+#[cfg(not(feature = "pae"))]
+const PAGE_ENTRY_ADDRESS_MASK: PageEntryBits = 0xfffff000;
+#[cfg(feature = "pae")]
+const PAGE_ENTRY_ADDRESS_MASK: PageEntryBits = 0x000f_ffff_ffff_f000;
 
 bitflags! {
-    pub struct PdeFlags: u32 {
+    pub struct PdeFlags: PageEntryBits {
         /// Present
         const P = 1;
         /// 1: read/write 0: read
@@ -16,15 +27,20 @@ bitflags! {
         const PCD = 1 << 4;
         /// Accessed
         const A = 1 << 5;
-        /// Dirty
+        /// Dirty，只用于 4MiB 页
         const D = 1 << 6;
+        /// Page Size，1: 4MiB 页（需要开启 CR4.PSE）
+        const PS = 1 << 7;
         /// Global
         const G = 1 << 8;
         /// Available
         const AVL = 1 << 9;
+        /// Execute Disable，需要开启 EFER.NXE
+        #[cfg(feature = "pae")]
+        const NX = 1 << 63;
     }
 
-    pub struct PteFlags: u32 {
+    pub struct PteFlags: PageEntryBits {
         /// Present
         const P = 1;
         /// 1: read/write 0: read
@@ -45,6 +61,9 @@ bitflags! {
         const G = 1 << 8;
         /// Available
         const AVL = 1 << 9;
+        /// Execute Disable，需要开启 EFER.NXE
+        #[cfg(feature = "pae")]
+        const NX = 1 << 63;
     }
 
     pub struct PdbrFlag: u32 {
@@ -54,9 +73,9 @@ bitflags! {
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
-pub struct PageDirectoryEntry(pub u32);
+pub struct PageDirectoryEntry(pub PageEntryBits);
 impl PageDirectoryEntry {
-    pub fn new(address: u32, flag: PdeFlags) -> Self {
+    pub fn new(address: PageEntryBits, flag: PdeFlags) -> Self {
         assert!(address & 0xfff == 0);
         Self(address | flag.bits())
     }
@@ -65,16 +84,21 @@ impl PageDirectoryEntry {
         Self(0)
     }
 
-    pub fn address(&self) -> u32 {
-        self.0 & 0xfffff000
+    pub fn address(&self) -> PageEntryBits {
+        self.0 & PAGE_ENTRY_ADDRESS_MASK
     }
 
-    pub fn set_address(&mut self, address: u32) {
+    pub fn set_address(&mut self, address: PageEntryBits) {
         *self = Self::new(address, self.flag());
     }
 
     pub fn flag(&self) -> PdeFlags {
-        PdeFlags::from_bits_truncate(self.0 & 0xfff)
+        PdeFlags::from_bits_truncate(self.0 & !PAGE_ENTRY_ADDRESS_MASK)
+    }
+
+    /// 是否是 4MiB 页的表项
+    pub fn is_large_page(&self) -> bool {
+        self.flag().contains(PdeFlags::P | PdeFlags::PS)
     }
 }
 
@@ -87,9 +111,9 @@ impl Display for PageDirectoryEntry {
 
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
-pub struct PageTableEntry(pub u32);
+pub struct PageTableEntry(pub PageEntryBits);
 impl PageTableEntry {
-    pub fn new(address: u32, flag: PteFlags) -> Self {
+    pub fn new(address: PageEntryBits, flag: PteFlags) -> Self {
         assert!(address & 0xfff == 0);
         Self(address | flag.bits())
     }
@@ -98,17 +122,33 @@ impl PageTableEntry {
         Self(0)
     }
 
-    pub fn address(&self) -> u32 {
-        self.0 & 0xfffff000
+    pub fn address(&self) -> PageEntryBits {
+        self.0 & PAGE_ENTRY_ADDRESS_MASK
     }
 
     pub fn flag(&self) -> PteFlags {
-        PteFlags::from_bits_truncate(self.0 & 0xfff)
+        PteFlags::from_bits_truncate(self.0 & !PAGE_ENTRY_ADDRESS_MASK)
     }
 
     pub fn set_flag(&mut self, flag: PteFlags) {
         *self = Self::new(self.address(), flag);
     }
+
+    /// 页被换出到 swap 区：P 位为 0，AVL 位为 1，高 20 位保存 swap slot
+    pub fn new_swapped(slot: usize) -> Self {
+        assert!(slot < (1 << 20));
+        Self::new((slot << 12) as PageEntryBits, PteFlags::AVL)
+    }
+
+    pub fn is_swapped(&self) -> bool {
+        let flag = self.flag();
+        !flag.contains(PteFlags::P) && flag.contains(PteFlags::AVL)
+    }
+
+    pub fn swap_slot(&self) -> usize {
+        assert!(self.is_swapped());
+        (self.address() >> 12) as usize
+    }
 }
 
 impl Display for PageTableEntry {
@@ -117,6 +157,29 @@ impl Display for PageTableEntry {
     }
 }
 
+/// PAE 分页的 PDPT 表项，只有 P、PWT、PCD 位，其余标志位保留
+#[cfg(feature = "pae")]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct PageDirectoryPointerTableEntry(pub u64);
+#[cfg(feature = "pae")]
+impl PageDirectoryPointerTableEntry {
+    pub fn new(address: u64) -> Self {
+        assert!(address & 0xfff == 0);
+        Self(address | 1)
+    }
+
+    pub fn address(&self) -> u64 {
+        self.0 & PAGE_ENTRY_ADDRESS_MASK
+    }
+}
+
+/// 刷新 address 所在页的 TLB 项，全局页也会被刷新
+pub fn invlpg(address: usize) {
+    unsafe {
+        asm!("invlpg [{}]", in(reg) address);
+    }
+}
+
 pub struct PageDirectoryBaseRegister(pub u32);
 pub type Cr3 = PageDirectoryBaseRegister;
 
@@ -144,3 +207,28 @@ impl PageDirectoryBaseRegister {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swapped_page_table_entry() {
+        let slot: usize = 0x1234;
+        let entry = PageTableEntry::new_swapped(slot);
+        assert_eq!(false, entry.flag().contains(PteFlags::P));
+        assert_eq!(true, entry.is_swapped());
+        assert_eq!(slot, entry.swap_slot());
+        assert_eq!(false, PageTableEntry::empty().is_swapped());
+    }
+
+    #[cfg(feature = "pae")]
+    #[test]
+    fn test_no_execute_page_table_entry() {
+        let entry = PageTableEntry::new(0xfffff000, PteFlags::P | PteFlags::NX);
+        assert_eq!(0xfffff000, entry.address());
+        assert_eq!(true, entry.flag().contains(PteFlags::NX));
+        assert_eq!(1 << 63, entry.0 & (1 << 63));
+    }
+
+}
@@ -0,0 +1,78 @@
+//! 高精度事件定时器（HPET）的主计数器作为时钟源，ACPI 的 HPET 表给出寄存器的物理地址。
+//! 只使用 64 位的主计数器，32 位的主计数器在 14.3MHz 下大约 5 分钟就会回绕。
+
+use crate::intr::apic::find_acpi_table;
+use crate::mm::ioremap::{ioremap, IoMapping};
+use crate::mm::PhysAddr;
+
+use super::ClockSource;
+
+const HPET_SIZE: usize = 0x400;
+/// 寄存器的偏移
+const HPET_CAPABILITIES: usize = 0x00;
+const HPET_PERIOD: usize = 0x04;
+const HPET_CONFIG: usize = 0x10;
+const HPET_MAIN_COUNTER: usize = 0xf0;
+/// 主计数器是 64 位的
+const COUNT_SIZE_CAP: u32 = 1 << 13;
+/// 主计数器开始计数
+const ENABLE_CNF: u32 = 1 << 0;
+/// 计数周期的上限为 100ns，单位为飞秒
+const MAX_PERIOD_FS: u32 = 100_000_000;
+const FSEC_PER_SEC: u64 = 1_000_000_000_000_000;
+
+pub struct Hpet {
+    regs: IoMapping,
+    frequency: u64,
+}
+
+/// HPET 表的表头之后是事件定时器块 ID，偏移 40 处是寄存器的地址（Generic Address Structure）
+pub fn probe() -> Option<Hpet> {
+    let table = find_acpi_table(b"HPET")?;
+    let bytes = table.as_bytes();
+    if bytes.len() < 52 {
+        return None;
+    }
+    // 地址空间 0 表示内存，地址的高 32 位需要为 0
+    let address = u64::from_le_bytes(bytes[44..52].try_into().unwrap());
+    if bytes[40] != 0 || address == 0 || address > u32::MAX as u64 {
+        return None;
+    }
+    let regs = ioremap(PhysAddr(address as usize), HPET_SIZE)?;
+    let period = regs.read_u32(HPET_PERIOD);
+    if period == 0 || period > MAX_PERIOD_FS {
+        return None;
+    }
+    if regs.read_u32(HPET_CAPABILITIES) & COUNT_SIZE_CAP == 0 {
+        info!("hpet: 32-bit main counter is not used");
+        return None;
+    }
+    // 不改变 LegacyReplacement 设置，不清零主计数器
+    regs.write_u32(HPET_CONFIG, regs.read_u32(HPET_CONFIG) | ENABLE_CNF);
+    Some(Hpet { regs, frequency: FSEC_PER_SEC / period as u64 })
+}
+
+impl ClockSource for Hpet {
+    fn name(&self) -> &'static str {
+        "hpet"
+    }
+
+    fn rating(&self) -> usize {
+        250
+    }
+
+    fn frequency(&self) -> u64 {
+        self.frequency
+    }
+
+    /// 分两次读取 64 位的计数器，两次读到的高 32 位不同时说明低 32 位回绕了，重新读取
+    fn read(&self) -> u64 {
+        loop {
+            let high = self.regs.read_u32(HPET_MAIN_COUNTER + 4);
+            let low = self.regs.read_u32(HPET_MAIN_COUNTER);
+            if self.regs.read_u32(HPET_MAIN_COUNTER + 4) == high {
+                return (high as u64) << 32 | low as u64;
+            }
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! Local APIC 定时器作为时钟事件设备，每个 CPU 各有一个，不再需要 BSP 转发时钟中断。
+//! 定时器按总线频率计数，频率用 PIT 校准，所有 CPU 的总线频率相同，只在 BSP 上校准一次。
+
+use crate::intr::{apic, IntrContext, INTR_HANDLER_TABLE};
+
+use super::{pit, ClockEvent, NSEC_PER_SEC};
+
+/// Local APIC 定时器的中断向量
+const LOCAL_TIMER_VECTOR: usize = 0x32;
+
+pub struct LapicTimer {
+    /// 分频后的计数频率，单位为 Hz
+    frequency: u64,
+}
+
+/// 没有启用 APIC 时使用 PIT
+pub fn probe() -> Option<LapicTimer> {
+    if !apic::is_enabled() {
+        return None;
+    }
+    // 单次模式从最大值开始递减，测量期间不会减到 0
+    apic::timer_start(LOCAL_TIMER_VECTOR, u32::MAX, false);
+    let frequency = pit::measure_frequency(|| (u32::MAX - apic::timer_current_count()) as u64);
+    apic::timer_stop();
+    if frequency == 0 {
+        return None;
+    }
+    Some(LapicTimer { frequency })
+}
+
+impl LapicTimer {
+    fn ns_to_count(&self, ns: u64) -> u32 {
+        (ns as u128 * self.frequency as u128 / NSEC_PER_SEC as u128).clamp(1, u32::MAX as u128) as u32
+    }
+}
+
+impl ClockEvent for LapicTimer {
+    fn name(&self) -> &'static str {
+        "lapic"
+    }
+
+    fn rating(&self) -> usize {
+        200
+    }
+
+    fn is_per_cpu(&self) -> bool {
+        true
+    }
+
+    fn enable(&self) {
+        INTR_HANDLER_TABLE.lock()[LOCAL_TIMER_VECTOR] = local_timer_handler;
+    }
+
+    fn set_periodic(&self, hz: usize) {
+        apic::timer_start(LOCAL_TIMER_VECTOR, self.ns_to_count(NSEC_PER_SEC / hz as u64), true);
+    }
+
+    fn set_oneshot(&self, delta_ns: u64) {
+        apic::timer_start(LOCAL_TIMER_VECTOR, self.ns_to_count(delta_ns), false);
+    }
+
+    fn max_delta_ns(&self) -> u64 {
+        u32::MAX as u64 * NSEC_PER_SEC / self.frequency
+    }
+
+    fn shutdown(&self) {
+        apic::timer_stop();
+    }
+}
+
+/// 先发送 EOI，中断返回前可能切换到其他线程
+fn local_timer_handler(intr_context: &mut IntrContext) {
+    apic::send_eoi();
+    super::tick(intr_context);
+}
@@ -0,0 +1,176 @@
+//! 时钟源和时钟事件设备。
+//! 时钟源（ClockSource）是单调递增的计数器，用于读取高精度的时间，依次考虑 TSC、HPET，都不可用时退回时钟中断计数；
+//! 时钟事件设备（ClockEvent）在设定的时间产生中断，驱动调度和定时器，优先使用每个 CPU 各自的 Local APIC 定时器，否则使用 PIT。
+//! 初始化时按照 rating 选择最好的设备。CPU 空闲时停止周期性的时钟中断，见 nohz。
+
+pub mod pit;
+pub mod nohz;
+mod tsc;
+mod hpet;
+mod lapic;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::intr::IntrContext;
+use crate::intr::softirq::{raise_softirq, SoftIrq};
+use crate::schedule::{set_need_resched, tick_current_task, update_idle_clock};
+use crate::smp::cpu_id;
+use crate::timer::{elapsed_time_in_microsecond, update_time, IRQ0_FREQUENCY};
+
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+/// 时钟中断的周期
+pub const NSEC_PER_TICK: u64 = NSEC_PER_SEC / IRQ0_FREQUENCY as u64;
+
+pub trait ClockSource: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// 越大越优先使用
+    fn rating(&self) -> usize;
+    /// 计数器的频率，单位为 Hz
+    fn frequency(&self) -> u64;
+    /// 读取计数器，计数器不会回绕
+    fn read(&self) -> u64;
+}
+
+pub trait ClockEvent: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// 越大越优先使用
+    fn rating(&self) -> usize;
+    /// 每个 CPU 各有一个；否则只有 BSP 收到中断，再通过 IPI 转发给其他 CPU
+    fn is_per_cpu(&self) -> bool;
+    /// 注册中断处理函数，被选中后调用一次
+    fn enable(&self);
+    /// 在当前 CPU 上每秒产生 hz 次中断
+    fn set_periodic(&self, hz: usize);
+    /// 在当前 CPU 上 delta_ns 纳秒后产生一次中断，超过 max_delta_ns 时按 max_delta_ns 设置
+    fn set_oneshot(&self, delta_ns: u64);
+    /// 单次模式一次能设置的最长时间，单位为纳秒
+    fn max_delta_ns(&self) -> u64;
+    /// 在当前 CPU 上停止产生中断
+    fn shutdown(&self);
+}
+
+/// 没有高精度的时钟源时使用时钟中断计数，精度为一个时钟中断周期
+struct Jiffies;
+
+impl ClockSource for Jiffies {
+    fn name(&self) -> &'static str {
+        "jiffies"
+    }
+
+    fn rating(&self) -> usize {
+        1
+    }
+
+    fn frequency(&self) -> u64 {
+        1_000_000
+    }
+
+    fn read(&self) -> u64 {
+        elapsed_time_in_microsecond()
+    }
+}
+
+/// 把时钟源的计数换算成纳秒，ns = (cycles - base_cycles) * mult >> 32
+struct Timekeeper {
+    source: Box<dyn ClockSource>,
+    base_cycles: u64,
+    mult: u64,
+    /// 时钟源不依赖时钟中断
+    high_resolution: bool,
+}
+
+impl Timekeeper {
+    fn new(source: Box<dyn ClockSource>) -> Self {
+        let frequency = source.frequency();
+        assert!(frequency > 0);
+        let mult = ((NSEC_PER_SEC as u128) << 32) / frequency as u128;
+        let base_cycles = source.read();
+        let high_resolution = source.rating() > Jiffies.rating();
+        Self { source, base_cycles, mult: mult.try_into().unwrap(), high_resolution }
+    }
+
+    fn read_ns(&self) -> u64 {
+        let delta = self.source.read().saturating_sub(self.base_cycles);
+        ((delta as u128 * self.mult as u128) >> 32) as u64
+    }
+}
+
+lazy_static! {
+    static ref TIMEKEEPER: Timekeeper = Timekeeper::new(select_clock_source());
+    static ref CLOCK_EVENT: Box<dyn ClockEvent> = select_clock_event();
+}
+
+fn select_clock_source() -> Box<dyn ClockSource> {
+    let mut sources: Vec<Box<dyn ClockSource>> = Vec::new();
+    if let Some(source) = tsc::probe() {
+        sources.push(Box::new(source));
+    }
+    if let Some(source) = hpet::probe() {
+        sources.push(Box::new(source));
+    }
+    sources.push(Box::new(Jiffies));
+    sources.into_iter().max_by_key(|source| source.rating()).unwrap()
+}
+
+fn select_clock_event() -> Box<dyn ClockEvent> {
+    let mut events: Vec<Box<dyn ClockEvent>> = Vec::new();
+    if let Some(event) = lapic::probe() {
+        events.push(Box::new(event));
+    }
+    events.push(Box::new(pit::Pit));
+    let event = events.into_iter().max_by_key(|event| event.rating()).unwrap();
+    event.enable();
+    event
+}
+
+/// 选择时钟源和时钟事件设备，在 BSP 上开始产生周期性的时钟中断
+pub fn init() {
+    let source = &TIMEKEEPER.source;
+    info!("clock: source {} frequency {} Hz", source.name(), source.frequency());
+    let event = &*CLOCK_EVENT;
+    event.set_periodic(IRQ0_FREQUENCY);
+    info!("clock: event {} max one-shot {} us", event.name(), event.max_delta_ns() / 1000);
+}
+
+/// AP 启动后设置自己的时钟事件设备，不是每个 CPU 一个时由 BSP 转发时钟中断
+pub fn init_ap() {
+    if CLOCK_EVENT.is_per_cpu() {
+        CLOCK_EVENT.set_periodic(IRQ0_FREQUENCY);
+    }
+}
+
+/// 时钟源选定以来的纳秒数
+pub fn time_ns() -> u64 {
+    TIMEKEEPER.read_ns()
+}
+
+/// 时钟中断停止时仍然可以读取时间
+pub fn is_high_resolution() -> bool {
+    TIMEKEEPER.high_resolution
+}
+
+/// 当前使用的时钟事件设备
+pub fn clock_event() -> &'static dyn ClockEvent {
+    CLOCK_EVENT.as_ref()
+}
+
+/// 时钟中断在每个 CPU 上的处理：BSP 更新时钟中断计数并检查定时器，所有 CPU 统计当前线程的运行时间，
+/// 线程运行时的单次中断可能经过了多个时钟中断周期；
+/// 空闲时的单次中断只检查定时器和被限流的 Deadline 线程，空闲时间在恢复周期性的时钟中断时补算
+pub fn tick(intr_context: &IntrContext) {
+    if cpu_id() == 0 {
+        update_time();
+        // 在软中断中唤醒定时器到期的线程
+        raise_softirq(SoftIrq::Timer);
+    }
+    if nohz::is_tick_stopped() {
+        update_idle_clock();
+        return;
+    }
+    // 当前线程用完时间片或者有更高优先级的线程就绪时，在中断返回前切换
+    if tick_current_task(intr_context.cs & 0b11 == 0b11, nohz::elapsed_task_ticks()) {
+        set_need_resched();
+    }
+    nohz::reprogram_task_tick();
+}
@@ -0,0 +1,164 @@
+//! 按需要处理的时间设置单次中断，代替周期性的时钟中断（tickless）。
+//! CPU 进入空闲循环时停止时钟中断：定时器和负载均值只由 BSP 处理，
+//! BSP 的单次中断设在最早到期的定时器和下一次计算负载均值中较早的时间；AP 空闲时停止时钟中断，有线程加入时由 IPI 唤醒。
+//! 有被限流的 Deadline 线程时，单次中断不晚于它恢复运行的时间。
+//! 其他 CPU 有多余的就绪线程时用 IPI 唤醒停止了时钟中断的 CPU，由它迁移线程。
+//! 线程运行时单次中断设在它用完时间片、被限流的 Deadline 线程恢复运行或者均衡负载的时间，BSP 还要考虑定时器和负载均值；
+//! 中断时按经过的时钟中断周期数统计时间片和运行时间。同一个调度类中优先级更高的线程就绪时，最晚在下一次单次中断时抢占。
+//! 离开空闲循环前补上停止期间的空闲时间。
+//! 补算时间需要高精度的时钟源；时钟事件设备不是每个 CPU 一个时，BSP 还要转发时钟中断，只在单个 CPU 时使用单次中断。
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::sync::Arc;
+
+use crate::percpu;
+use crate::process::TaskControlBlock;
+use crate::schedule::{account_idle_ticks, current_task, local_task_manager, ticks_until_next_tick, CPU_STAT};
+use crate::smp::{cpu_id, online_cpu_count, online_cpus, send_reschedule};
+use crate::timer::next_timer_expire_ms;
+
+use super::{clock_event, is_high_resolution, time_ns, NSEC_PER_TICK};
+
+percpu! {
+    /// 空闲时已经补算到的时间，None 表示时钟中断没有停止
+    static IDLE_SINCE_NS: Option<u64> = None;
+    /// 线程运行时已经统计到的时间，None 表示使用周期性的时钟中断或者 CPU 空闲
+    static TASK_TICK_SINCE_NS: Option<u64> = None;
+}
+
+/// 停止了时钟中断的空闲 CPU，第 i 位对应 CPU i，有多余的就绪线程时唤醒其中一个迁移线程
+static TICK_STOPPED_CPUS: AtomicUsize = AtomicUsize::new(0);
+
+fn is_enabled() -> bool {
+    is_high_resolution() && (clock_event().is_per_cpu() || online_cpu_count() == 1)
+}
+
+/// 当前 CPU 的时钟中断是否停止，停止期间的时钟中断不统计当前线程的运行时间
+pub fn is_tick_stopped() -> bool {
+    IDLE_SINCE_NS.with(|idle_since| idle_since.is_some())
+}
+
+/// 补上从上次补算到 now 经过的完整的时钟中断周期
+fn account_idle(now: u64) {
+    let ticks = IDLE_SINCE_NS.with_mut(|idle_since| {
+        let since = idle_since.get_or_insert(now);
+        let ticks = now.saturating_sub(*since) / NSEC_PER_TICK;
+        *since += ticks * NSEC_PER_TICK;
+        ticks
+    });
+    if ticks > 0 {
+        account_idle_ticks(ticks);
+    }
+}
+
+/// 设置在 deadline 纳秒时产生单次中断，已经过了 deadline 时尽快产生
+fn set_oneshot_at(deadline: u64) {
+    let event = clock_event();
+    event.set_oneshot(deadline.saturating_sub(time_ns()).min(event.max_delta_ns()));
+}
+
+/// BSP 需要处理定时器和负载均值的时间，base 是负载均值已经统计到的时间；
+/// 已经到期的定时器由时钟中断触发的软中断处理，不需要立即再产生中断，按一个时钟中断周期之后计算
+fn bsp_deadline(base: u64) -> u64 {
+    let load_deadline = base + CPU_STAT.lock().ticks_until_load_update() * NSEC_PER_TICK;
+    let now = time_ns();
+    next_timer_expire_ms().map_or(load_deadline, |expire_ms| {
+        let timer_deadline = expire_ms * 1_000_000;
+        load_deadline.min(if timer_deadline <= now { now + NSEC_PER_TICK } else { timer_deadline })
+    })
+}
+
+/// 空闲循环停机前调用，停止周期性的时钟中断，设置下一次单次中断
+pub fn stop_idle_tick() {
+    if !is_enabled() {
+        return;
+    }
+    TASK_TICK_SINCE_NS.with_mut(|since| *since = None);
+    let now = time_ns();
+    account_idle(now);
+    TICK_STOPPED_CPUS.fetch_or(1 << cpu_id(), Ordering::SeqCst);
+    // 被限流的 Deadline 线程到期时需要唤醒 CPU 让它进入下一个周期，时钟中断数按时间换算
+    let replenish_deadline = local_task_manager().lock().next_deadline_replenish().map(|jiffies| jiffies * NSEC_PER_TICK);
+    let deadline = if cpu_id() == 0 {
+        Some(replenish_deadline.map_or(bsp_deadline(now), |deadline| deadline.min(bsp_deadline(now))))
+    } else {
+        replenish_deadline
+    };
+    match deadline {
+        Some(deadline) => set_oneshot_at(deadline),
+        None => clock_event().shutdown(),
+    }
+}
+
+/// 开始运行线程前调用：离开空闲循环时补上空闲时间，按线程的时间片设置单次中断
+pub fn start_task_tick(task: &Arc<TaskControlBlock>) {
+    if !is_enabled() {
+        return;
+    }
+    let now = time_ns();
+    if is_tick_stopped() {
+        account_idle(now);
+        IDLE_SINCE_NS.with_mut(|idle_since| *idle_since = None);
+        TICK_STOPPED_CPUS.fetch_and(!(1 << cpu_id()), Ordering::SeqCst);
+    }
+    TASK_TICK_SINCE_NS.with_mut(|since| *since = Some(now));
+    program_task_tick(now, task);
+}
+
+/// 线程运行时的时钟中断调用，统计到当前时间
+/// 返回内容：距离上次统计经过的完整的时钟中断周期数，使用周期性的时钟中断时为 1
+pub fn elapsed_task_ticks() -> u64 {
+    let now = time_ns();
+    TASK_TICK_SINCE_NS.with_mut(|since| match since {
+        Some(since) => {
+            let ticks = now.saturating_sub(*since) / NSEC_PER_TICK;
+            *since += ticks * NSEC_PER_TICK;
+            ticks
+        }
+        None => 1,
+    })
+}
+
+/// since 是已经统计到的时间，之后经过线程需要处理的时钟中断数时产生单次中断
+fn program_task_tick(since: u64, task: &Arc<TaskControlBlock>) {
+    let deadline = since.saturating_add(ticks_until_next_tick(task).saturating_mul(NSEC_PER_TICK));
+    if cpu_id() == 0 {
+        set_oneshot_at(deadline.min(bsp_deadline(since)));
+    } else {
+        set_oneshot_at(deadline);
+    }
+}
+
+/// 线程运行时处理完时钟中断、或者加入了定时器后调用，重新设置单次中断
+pub fn reprogram_task_tick() {
+    let Some(since) = TASK_TICK_SINCE_NS.with(|since| *since) else {
+        return;
+    };
+    if let Some(task) = current_task() {
+        program_task_tick(since, &task);
+    }
+}
+
+/// 加入定时器后调用，BSP 不使用周期性的时钟中断，需要按最早到期的定时器重新设置单次中断；
+/// 其他 CPU 用 IPI 通知 BSP，BSP 空闲时在空闲循环中重新设置
+pub fn timer_added() {
+    if !is_enabled() {
+        return;
+    }
+    if cpu_id() == 0 {
+        reprogram_task_tick();
+    } else {
+        send_reschedule(0);
+    }
+}
+
+/// 空闲的 CPU 停止时钟中断后不再定期均衡负载，当前 CPU 有可以迁移的就绪线程时调用，
+/// 唤醒一个停止了时钟中断的 CPU，它在空闲循环中从就绪线程最多的 CPU 迁移线程
+pub fn kick_idle_cpu() {
+    let stopped = TICK_STOPPED_CPUS.load(Ordering::SeqCst);
+    let this_cpu = cpu_id();
+    if let Some(cpu) = online_cpus().find(|&cpu| cpu != this_cpu && stopped & (1 << cpu) != 0) {
+        send_reschedule(cpu);
+    }
+}
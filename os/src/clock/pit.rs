@@ -0,0 +1,99 @@
+//! 8253/8254 可编程间隔定时器（PIT）。
+//! 计数器 0 接 IRQ 0，作为时钟事件设备；计数器 2 的输出可以从 0x61 端口读到，不产生中断，用于校准其他计数器的频率。
+
+use crate::arch::x86::{inb, outb};
+use crate::intr::IntrContext;
+use crate::intr::irq::{request_irq, IrqReturn};
+use crate::smp::send_timer_tick_to_others;
+
+use super::{ClockEvent, NSEC_PER_SEC};
+
+/// PIT 的输入频率，单位为 Hz
+pub const INPUT_FREQUENCY: usize = 1193180;
+const COUNTER0_PORT: u16 = 0x40;
+const COUNTER2_PORT: u16 = 0x42;
+const PIT_CONTROL_PORT: u16 = 0x43;
+/// bit 0 为计数器 2 的门控，bit 1 打开扬声器，bit 5 为计数器 2 的输出
+const PORT_B: u16 = 0x61;
+const TIMER_IRQ: usize = 0;
+/// 计数器是 16 位的
+const MAX_COUNT: usize = 0xffff;
+/// 校准的时长，单位为毫秒
+const CALIBRATE_MS: usize = 10;
+
+/// 控制字：计数器编号、先写低字节再写高字节、工作方式
+fn control_word(counter: u8, mode: u8) -> u8 {
+    counter << 6 | 3 << 4 | mode << 1
+}
+
+/// 用计数器 2 等待 CALIBRATE_MS 毫秒，得到 read 读出的计数器每秒增加多少；
+/// 只在初始化时关中断调用
+pub fn measure_frequency(read: impl Fn() -> u64) -> u64 {
+    let count = INPUT_FREQUENCY * CALIBRATE_MS / 1000;
+    // 打开门控，关闭扬声器
+    outb((inb(PORT_B) & !0x02) | 0x01, PORT_B);
+    // 方式 0：计数到 0 时输出变为高电平
+    outb(control_word(2, 0), PIT_CONTROL_PORT);
+    outb((count & 0xff) as u8, COUNTER2_PORT);
+    outb(((count >> 8) & 0xff) as u8, COUNTER2_PORT);
+    let begin = read();
+    while inb(PORT_B) & 0x20 == 0 {
+        core::hint::spin_loop();
+    }
+    let end = read();
+    (end - begin) * 1000 / CALIBRATE_MS as u64
+}
+
+/// 计数器 0 作为时钟事件设备，中断只投递给 BSP
+pub struct Pit;
+
+impl ClockEvent for Pit {
+    fn name(&self) -> &'static str {
+        "pit"
+    }
+
+    fn rating(&self) -> usize {
+        100
+    }
+
+    fn is_per_cpu(&self) -> bool {
+        false
+    }
+
+    fn enable(&self) {
+        request_irq(TIMER_IRQ, timer_intr_handler, "timer", 0).unwrap();
+    }
+
+    /// 方式 2：分频器，每 INPUT_FREQUENCY / hz 个输入脉冲产生一次中断
+    fn set_periodic(&self, hz: usize) {
+        let count = INPUT_FREQUENCY / hz;
+        assert!(0 < count && count <= MAX_COUNT);
+        outb(control_word(0, 2), PIT_CONTROL_PORT);
+        outb((count & 0xff) as u8, COUNTER0_PORT);
+        outb(((count >> 8) & 0xff) as u8, COUNTER0_PORT);
+    }
+
+    /// 方式 0：计数到 0 时产生一次中断
+    fn set_oneshot(&self, delta_ns: u64) {
+        let count = (delta_ns * INPUT_FREQUENCY as u64 / NSEC_PER_SEC).clamp(1, MAX_COUNT as u64) as usize;
+        outb(control_word(0, 0), PIT_CONTROL_PORT);
+        outb((count & 0xff) as u8, COUNTER0_PORT);
+        outb(((count >> 8) & 0xff) as u8, COUNTER0_PORT);
+    }
+
+    fn max_delta_ns(&self) -> u64 {
+        MAX_COUNT as u64 * NSEC_PER_SEC / INPUT_FREQUENCY as u64
+    }
+
+    /// 写入方式 0 的控制字后不写计数值，计数器停止
+    fn shutdown(&self) {
+        outb(control_word(0, 0), PIT_CONTROL_PORT);
+    }
+}
+
+fn timer_intr_handler(_irq: usize, _dev_data: usize, intr_context: &mut IntrContext) -> IrqReturn {
+    // 其他 CPU 通过 IPI 收到时钟中断
+    send_timer_tick_to_others();
+    super::tick(intr_context);
+    IrqReturn::Handled
+}
@@ -0,0 +1,53 @@
+//! 时间戳计数器（TSC）作为时钟源。
+//! 频率优先从 CPUID 0x15 得到，大多数 QEMU 的 CPU 不提供，此时用 PIT 校准。
+//! 只有不变 TSC（invariant TSC）的频率不受节能状态影响，在不同 CPU 之间也是同步的，否则 HPET 更可靠。
+
+use crate::drivers::{cpuid, get_tsc_frequency, rdtsc};
+
+use super::{pit, ClockSource};
+
+pub struct Tsc {
+    frequency: u64,
+    invariant: bool,
+}
+
+/// CPUID.01H:EDX[bit 4] 表示支持 TSC
+pub fn probe() -> Option<Tsc> {
+    let (_, _, _, edx) = cpuid(1);
+    if edx & (1 << 4) == 0 {
+        return None;
+    }
+    let frequency = get_tsc_frequency().unwrap_or_else(|| pit::measure_frequency(rdtsc));
+    if frequency == 0 {
+        return None;
+    }
+    Some(Tsc { frequency, invariant: is_invariant() })
+}
+
+/// CPUID.80000007H:EDX[bit 8] 表示 TSC 是不变的
+fn is_invariant() -> bool {
+    let (max_extended_leaf, _, _, _) = cpuid(0x80000000);
+    if max_extended_leaf < 0x80000007 {
+        return false;
+    }
+    let (_, _, _, edx) = cpuid(0x80000007);
+    edx & (1 << 8) != 0
+}
+
+impl ClockSource for Tsc {
+    fn name(&self) -> &'static str {
+        "tsc"
+    }
+
+    fn rating(&self) -> usize {
+        if self.invariant { 300 } else { 150 }
+    }
+
+    fn frequency(&self) -> u64 {
+        self.frequency
+    }
+
+    fn read(&self) -> u64 {
+        rdtsc()
+    }
+}
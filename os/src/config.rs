@@ -1,12 +1,62 @@
 pub const MEMORY_PAGE_SIZE: usize = 0x1000;
+// 32 位分页：页目录和页表各 1024 项，页表项 4 字节
+#[cfg(not(feature = "pae"))]
 pub const PTE_SIZE_IN_PAGE: usize = 0x1000 / 4;
+// 页目录项的个数
+#[cfg(not(feature = "pae"))]
+pub const PDE_COUNT: usize = 1024;
+// 页目录占用的页数
+#[cfg(not(feature = "pae"))]
+pub const PDT_DIRECTORY_PAGE_SIZE: usize = 1;
+// 页表根占用的物理页数
+#[cfg(not(feature = "pae"))]
+pub const PDT_PAGE_SIZE: usize = PDT_DIRECTORY_PAGE_SIZE;
+// PSE 大页的大小
+#[cfg(not(feature = "pae"))]
+pub const LARGE_PAGE_SIZE: usize = 0x400000;
+// PAE 分页：PDPT 的 4 项指向 4 个页目录，页目录和页表各 512 项，页表项 8 字节
+// 4 个页目录连续存放，可以看作 2048 项的页目录，页表根再加上 1 页 PDPT
+#[cfg(feature = "pae")]
+pub const PTE_SIZE_IN_PAGE: usize = 0x1000 / 8;
+#[cfg(feature = "pae")]
+pub const PDE_COUNT: usize = 2048;
+#[cfg(feature = "pae")]
+pub const PDT_DIRECTORY_PAGE_SIZE: usize = 4;
+#[cfg(feature = "pae")]
+pub const PDT_PAGE_SIZE: usize = PDT_DIRECTORY_PAGE_SIZE + 1;
+#[cfg(feature = "pae")]
+pub const LARGE_PAGE_SIZE: usize = 0x200000;
+pub const LARGE_PAGE_PAGE_SIZE: usize = LARGE_PAGE_SIZE / MEMORY_PAGE_SIZE;
+// 超过该页数时刷新整个 TLB，否则逐页 invlpg
+pub const TLB_FLUSH_ALL_THRESHOLD: usize = 32;
+// vpn 右移得到页目录项下标
+pub const PDE_INDEX_SHIFT: usize = PTE_SIZE_IN_PAGE.trailing_zeros() as usize;
+// 内核空间的第一个页目录项
+pub const KERNEL_PDE_BEGIN_INDEX: usize = (HIGH_ADDRESS_BASE / MEMORY_PAGE_SIZE) >> PDE_INDEX_SHIFT;
+// 页目录最后几项指向页目录自身（自映射）
+pub const PDT_RECURSIVE_PDE_INDEX: usize = PDE_COUNT - PDT_DIRECTORY_PAGE_SIZE;
+// 通过自映射访问页表页的虚拟地址
+pub const PTE_PAGE_VIRT_ADDRESS: usize = PDT_RECURSIVE_PDE_INDEX << (PDE_INDEX_SHIFT + 12);
 
 // kerenl 开始物理地址
 pub const KERNEL_BEGIN_PHYS_ADDRESS: usize = 0x100000;
 pub const KERNEL_LOAD_DATA_SIZE: usize = 0x800000;
-// page table 开始物理地址
-pub const KERNEL_PDT_PHYS_ADDRESS: usize = KERNEL_BEGIN_PHYS_ADDRESS + KERNEL_LOAD_DATA_SIZE;
-pub const KERNEL_PAGE_TABLE_DATA_SIZE: usize = 0x100000;
+// loader 建立的 page table 开始物理地址
+pub const LOADER_PDT_PHYS_ADDRESS: usize = KERNEL_BEGIN_PHYS_ADDRESS + KERNEL_LOAD_DATA_SIZE;
+pub const LOADER_PAGE_TABLE_DATA_SIZE: usize = 0x100000;
+// 内核 page table 开始物理地址
+#[cfg(not(feature = "pae"))]
+pub const KERNEL_PDT_PHYS_ADDRESS: usize = LOADER_PDT_PHYS_ADDRESS;
+#[cfg(not(feature = "pae"))]
+pub const KERNEL_PAGE_TABLE_DATA_SIZE: usize = LOADER_PAGE_TABLE_DATA_SIZE;
+// 开启 PAE 时，内核在 loader 的页表之后重新建立页表：4 个页目录、PDPT 和内核空间的页表
+#[cfg(feature = "pae")]
+pub const KERNEL_PDT_PHYS_ADDRESS: usize = LOADER_PDT_PHYS_ADDRESS + LOADER_PAGE_TABLE_DATA_SIZE;
+#[cfg(feature = "pae")]
+pub const KERNEL_PAGE_TABLE_DATA_SIZE: usize = 0x300000;
+// 内核空间的页表紧接在页表根之后，第 n 个内核页目录项的页表位于 KERNEL_PTE_PAGE_PHYS_ADDRESS + n * MEMORY_PAGE_SIZE
+// 使用大页的页目录项也保留了页表，拆分大页时使用
+pub const KERNEL_PTE_PAGE_PHYS_ADDRESS: usize = KERNEL_PDT_PHYS_ADDRESS + PDT_PAGE_SIZE * MEMORY_PAGE_SIZE;
 // 内核堆开始物理地址
 pub const KERNEL_HEAP_PHYS_ADDRESS: usize = KERNEL_PDT_PHYS_ADDRESS + KERNEL_PAGE_TABLE_DATA_SIZE;
 pub const KERNEL_HEAP_PAGE_SIZE: usize = 0x500;
@@ -25,18 +75,20 @@ pub const FREE_PHYS_FRAME_BEGIN_ADDRESS: usize = KERNEL_VIRT_FRAME_BITMAP_PHYS_A
 
 // kerenl 开始虚拟地址
 pub const KERNEL_BEGIN_VIRT_ADDRESS: usize = 0xc0100000;
-// 内核堆开始虚拟地址
-pub const KERNEL_HEAP_VIRT_ADDRESS: usize = KERNEL_BEGIN_VIRT_ADDRESS + KERNEL_LOAD_DATA_SIZE;
+// 内核直接映射区域的大小，从物理地址 0 开始，覆盖内核、页表、内核堆和 bitmap，按大页对齐
+pub const KERNEL_DIRECT_MAP_SIZE: usize = FREE_PHYS_FRAME_BEGIN_ADDRESS.div_ceil(LARGE_PAGE_SIZE) * LARGE_PAGE_SIZE;
+// 内核堆开始虚拟地址，位于直接映射区域
+pub const KERNEL_HEAP_VIRT_ADDRESS: usize = HIGH_ADDRESS_BASE + KERNEL_HEAP_PHYS_ADDRESS;
 // 物理帧 bitmap 开始虚拟地址
 pub const PHYS_FRAME_BITMAP_VIRT_ADDRESS: usize = KERNEL_HEAP_VIRT_ADDRESS + KERNEL_HEAP_SIZE;
 // 内核虚拟内存空间 bitmap 开始的物理地址
 pub const KERNEL_VIRT_FRAME_BITMAP_VIRT_ADDRESS: usize = PHYS_FRAME_BITMAP_VIRT_ADDRESS + PHYS_FRAME_BITMAP_SIZE;
-// 可用内核虚拟帧开始地址
-pub const FREE_KERNEL_VIRT_FRAME_BEGIN_ADDRESS: usize = KERNEL_VIRT_FRAME_BITMAP_VIRT_ADDRESS + KERNEL_VIRT_FRAME_BITMAP_SIZE;
+// 可用内核虚拟帧开始地址，直接映射区域可能使用大页，所以从直接映射区域之后开始
+pub const FREE_KERNEL_VIRT_FRAME_BEGIN_ADDRESS: usize = HIGH_ADDRESS_BASE + KERNEL_DIRECT_MAP_SIZE;
 // 可用内核虚拟帧结束地址
-pub const FREE_KERNEL_VIRT_FRAME_END_ADDRESS: usize = 0xffc00000;
+pub const FREE_KERNEL_VIRT_FRAME_END_ADDRESS: usize = PTE_PAGE_VIRT_ADDRESS;
 // page directory table 虚拟地址
-pub const PDT_VIRT_ADDRESS: usize = 0xfffff000;
+pub const PDT_VIRT_ADDRESS: usize = PTE_PAGE_VIRT_ADDRESS + (PDT_RECURSIVE_PDE_INDEX << 12);
 
 pub const KERNEL_ORIGIN_STACK_TOP_VIRT_ADDRESS: usize = 0xc0090000;
 
@@ -49,12 +101,34 @@ pub const THREAD_MAX_ID: usize = 1000 * 8;
 pub const THREAD_ID_BITMAP_SIZE: usize = 1000;
 
 pub const HIGH_ADDRESS_BASE: usize = 0xc0000000;
-pub const KERNEL_STACK_TOP_VIRT_ADDRESS: usize = 0xffc00000;
+pub const KERNEL_STACK_TOP_VIRT_ADDRESS: usize = PTE_PAGE_VIRT_ADDRESS;
 pub const KERNEL_STACK_PAGE_SIZE: usize = 10;
 pub const KERNEL_STACK_SIZE: usize = KERNEL_STACK_PAGE_SIZE << 12;
 pub const USER_STACK_TOP_VIRT_ADDRESS: usize = 0xc0000000;
 pub const USER_STACK_PAGE_SIZE: usize = 0x10;
 pub const USER_STACK_SIZE: usize = USER_STACK_PAGE_SIZE << 12;
+// 用户栈缺页时可以向下增长，每个线程的栈最多增长到该页数
+pub const USER_STACK_MAX_PAGE_SIZE: usize = 0x100;
+pub const USER_STACK_MAX_SIZE: usize = USER_STACK_MAX_PAGE_SIZE << 12;
+// 线程的用户栈从随机化后最低的用户栈顶向下排列到共享内存区域为止，一个进程最多的线程数
+pub const USER_THREAD_MAX_COUNT: usize = (USER_STACK_TOP_VIRT_ADDRESS - (ASLR_STACK_RANDOM_PAGE_SIZE << 12) - USER_SHM_END_VIRT_ADDRESS - USER_STACK_MAX_SIZE) / (USER_STACK_MAX_SIZE + MEMORY_PAGE_SIZE) + 1;
+
+// 共享内存段默认映射到该区域，每个共享内存段最多的页数
+pub const USER_SHM_BEGIN_VIRT_ADDRESS: usize = 0x80000000;
+pub const USER_SHM_END_VIRT_ADDRESS: usize = 0xa0000000;
+pub const SHM_MAX_PAGE_SIZE: usize = 0x400;
+
+// 位置无关的可执行文件（PIE）的加载地址
+pub const PIE_LOAD_BASE_VIRT_ADDRESS: usize = 0x10000000;
+// 开启 ASLR 时，用户栈顶向下、共享内存区域和 PIE 加载地址向上随机偏移的最大页数
+pub const ASLR_STACK_RANDOM_PAGE_SIZE: usize = 0x1000;
+pub const ASLR_SHM_RANDOM_PAGE_SIZE: usize = 0x1000;
+pub const ASLR_PIE_RANDOM_PAGE_SIZE: usize = 0x10000;
+
+// swap 区在硬盘上的起始扇区，位于内核镜像之后
+pub const SWAP_BEGIN_SECTOR: usize = 0x8000;
+pub const SWAP_PAGE_COUNT: usize = 0x2000;
+pub const SWAP_SLOT_BITMAP_SIZE: usize = SWAP_PAGE_COUNT / 8;
 
 pub const GDT_SIZE: usize = 512 / 8;
 pub const RPL0: u8 = 0b00;
@@ -67,4 +141,14 @@ pub const CODE_SELECTOR: u16 = (1u16 << 3) | ((TI_GDT as u16) << 2) | RPL0 as u1
 pub const DATA_SELECTOR: u16 = (2u16 << 3) | ((TI_GDT as u16) << 2) | RPL0 as u16;
 pub const USER_CODE_SELECTOR: u16 = (3u16 << 3) | ((TI_GDT as u16) << 2) | RPL3 as u16;
 pub const USER_DATA_SELECTOR: u16 = (4u16 << 3) | ((TI_GDT as u16) << 2) | RPL3 as u16;
-pub const TSS_SELECTOR: u16 = (5u16 << 3) | ((TI_GDT as u16) << 2) | RPL0 as u16;
+pub const TSS_SELECTOR: u16 = (TSS_GDT_INDEX << 3) | ((TI_GDT as u16) << 2) | RPL0 as u16;
+// 每个 CPU 的 TSS 描述符在 GDT 中的下标为 TSS_GDT_INDEX + CPU 编号，BSP 使用 TSS_SELECTOR
+pub const TSS_GDT_INDEX: u16 = 5;
+// 每个 CPU 的 GDT 在同一个下标处放置指向自己的 per-CPU 数据区的数据段描述符，内核运行时 gs 总是加载 PERCPU_SELECTOR
+pub const PERCPU_GDT_INDEX: u16 = TSS_GDT_INDEX + MAX_CPU_COUNT as u16;
+pub const PERCPU_SELECTOR: u16 = (PERCPU_GDT_INDEX << 3) | ((TI_GDT as u16) << 2) | RPL0 as u16;
+
+// 支持的最多 CPU 数
+pub const MAX_CPU_COUNT: usize = 8;
+// AP 启动代码复制到的物理地址，SIPI 的向量为该地址的页号，需要在 1MiB 以内
+pub const AP_TRAMPOLINE_PHYS_ADDRESS: usize = 0x8000;
@@ -0,0 +1,112 @@
+use bitflags::bitflags;
+use spin::Mutex;
+
+use crate::arch::x86::{inb, inw, outb, outw};
+
+use super::{BlockDevice, BLOCK_SIZE};
+
+// primary 总线的端口，和 loader 读取内核时使用的端口相同
+const ATA_DATA_PORT: u16 = 0x1f0;
+const ATA_SECTOR_COUNT_PORT: u16 = 0x1f2;
+const ATA_LBA_LOW_PORT: u16 = 0x1f3;
+const ATA_LBA_MID_PORT: u16 = 0x1f4;
+const ATA_LBA_HIGH_PORT: u16 = 0x1f5;
+const ATA_DEVICE_PORT: u16 = 0x1f6;
+const ATA_STATUS_PORT: u16 = 0x1f7;
+const ATA_COMMAND_PORT: u16 = 0x1f7;
+const ATA_DEVICE_CONTROL_PORT: u16 = 0x3f6;
+
+const ATA_CMD_READ_SECTORS: u8 = 0x20;
+const ATA_CMD_WRITE_SECTORS: u8 = 0x30;
+const ATA_CMD_CACHE_FLUSH: u8 = 0xe7;
+
+// LBA 模式，选择主盘
+const ATA_DEVICE_LBA_MASTER: u8 = 0xe0;
+// 关闭硬盘中断
+const ATA_CONTROL_NIEN: u8 = 1 << 1;
+
+bitflags! {
+    struct AtaStatus: u8 {
+        const ERR = 1;
+        const DRQ = 1 << 3;
+        const DF = 1 << 5;
+        const DRDY = 1 << 6;
+        const BSY = 1 << 7;
+    }
+}
+
+/// primary 总线上的主盘，使用 PIO 方式和 LBA28 寻址
+pub struct AtaPio {
+    lock: Mutex<()>,
+}
+
+impl AtaPio {
+    pub fn new() -> Self {
+        Self { lock: Mutex::new(()) }
+    }
+
+    fn status() -> AtaStatus {
+        AtaStatus::from_bits_truncate(inb(ATA_STATUS_PORT))
+    }
+
+    fn wait_not_busy() -> AtaStatus {
+        loop {
+            let status = Self::status();
+            if !status.contains(AtaStatus::BSY) {
+                return status;
+            }
+        }
+    }
+
+    fn wait_data_request() {
+        loop {
+            let status = Self::wait_not_busy();
+            assert!(!status.intersects(AtaStatus::ERR | AtaStatus::DF), "ata error, status {:#x}", status.bits());
+            if status.contains(AtaStatus::DRQ) {
+                break;
+            }
+        }
+    }
+
+    fn select_sector(block_id: usize) {
+        assert!(block_id < (1 << 28), "block {:#x} is out of LBA28", block_id);
+        Self::wait_not_busy();
+        outb(ATA_DEVICE_LBA_MASTER | ((block_id >> 24) & 0x0f) as u8, ATA_DEVICE_PORT);
+        outb(1, ATA_SECTOR_COUNT_PORT);
+        outb((block_id & 0xff) as u8, ATA_LBA_LOW_PORT);
+        outb(((block_id >> 8) & 0xff) as u8, ATA_LBA_MID_PORT);
+        outb(((block_id >> 16) & 0xff) as u8, ATA_LBA_HIGH_PORT);
+    }
+}
+
+impl BlockDevice for AtaPio {
+    fn init(&self) {
+        // 使用轮询方式，不需要硬盘中断
+        outb(ATA_CONTROL_NIEN, ATA_DEVICE_CONTROL_PORT);
+    }
+
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        assert_eq!(buf.len(), BLOCK_SIZE);
+        let _lock = self.lock.lock();
+        Self::select_sector(block_id);
+        outb(ATA_CMD_READ_SECTORS, ATA_COMMAND_PORT);
+        Self::wait_data_request();
+        for bytes in buf.as_chunks_mut::<2>().0 {
+            *bytes = inw(ATA_DATA_PORT).to_le_bytes();
+        }
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        assert_eq!(buf.len(), BLOCK_SIZE);
+        let _lock = self.lock.lock();
+        Self::select_sector(block_id);
+        outb(ATA_CMD_WRITE_SECTORS, ATA_COMMAND_PORT);
+        Self::wait_data_request();
+        for bytes in buf.as_chunks::<2>().0 {
+            outw(u16::from_le_bytes(*bytes), ATA_DATA_PORT);
+        }
+        // 等待数据真正写入磁盘
+        outb(ATA_CMD_CACHE_FLUSH, ATA_COMMAND_PORT);
+        Self::wait_not_busy();
+    }
+}
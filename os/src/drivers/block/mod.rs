@@ -0,0 +1,19 @@
+mod ata;
+
+pub const BLOCK_SIZE: usize = 512;
+
+pub trait BlockDevice {
+    fn init(&self);
+    fn read_block(&self, block_id: usize, buf: &mut [u8]);
+    fn write_block(&self, block_id: usize, buf: &[u8]);
+}
+
+pub type BlockDeviceImpl = ata::AtaPio;
+
+lazy_static! {
+    pub static ref BLOCK_DEVICE: BlockDeviceImpl = {
+        let driver = BlockDeviceImpl::new();
+        driver.init();
+        driver
+    };
+}
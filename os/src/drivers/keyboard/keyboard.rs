@@ -1,4 +1,4 @@
-use crate::{arch::x86::ByteReadPort, console::print, utils::ring_buffer::RingBuffer};
+use crate::{console::print, utils::ring_buffer::RingBuffer};
 use super::scan_code_set::*;
 
 pub struct KeyboardDriver {
@@ -32,13 +32,7 @@ impl KeyboardDriver {
 }
 
 impl KeyboardDriver {
-    pub fn handle_intr(&mut self) {
-        let port = ByteReadPort::new(0x60);
-        let code = port.read();
-        self.push(code);
-    }
-
-    fn push(&mut self, code: u8) {
+    pub fn push(&mut self, code: u8) {
         self.scan_codes[self.size] = code;
         self.size += 1;
         self.parse_scan_code();
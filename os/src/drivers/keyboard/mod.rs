@@ -2,20 +2,40 @@ use alloc::sync::Arc;
 use keyboard::KeyboardDriver;
 use spin::Mutex;
 
+use crate::arch::x86::ByteReadPort;
+use crate::intr::softirq::without_intr;
+use crate::utils::ring_buffer::RingBuffer;
+
 mod keyboard;
 mod scan_code_set;
 
 lazy_static! {
     static ref KEYBOARD_DRIVER: Arc<Mutex<keyboard::KeyboardDriver>> = Arc::new(Mutex::new(KeyboardDriver::new()));
+    /// 中断处理函数读到的扫描码，等待在软中断中解析
+    static ref SCAN_CODE_BUFFER: Arc<Mutex<RingBuffer<u8, SCAN_CODE_BUFFER_SIZE>>> = Arc::new(Mutex::new(RingBuffer::new(0)));
 }
 
+const SCAN_CODE_BUFFER_SIZE: usize = 16;
+
 pub fn init() {
 
 }
 
+/// 在中断处理函数中调用，只读取扫描码，缓冲区满时丢弃最早的扫描码
 pub fn handle_keyboard_intr() {
-    let mut keyboard = KEYBOARD_DRIVER.lock();
-    keyboard.handle_intr();
+    let code = ByteReadPort::new(0x60).read();
+    let mut buffer = SCAN_CODE_BUFFER.lock();
+    if buffer.is_full() {
+        buffer.pop();
+    }
+    buffer.push(code);
+}
+
+/// 在软中断中调用，解析中断处理函数读到的扫描码
+pub fn handle_scan_codes() {
+    while let Some(code) = without_intr(|| SCAN_CODE_BUFFER.lock().pop()) {
+        KEYBOARD_DRIVER.lock().push(code);
+    }
 }
 
 pub fn get_char() -> Option<u8> {
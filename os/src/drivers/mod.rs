@@ -1,8 +1,11 @@
 pub mod screen;
 pub mod chardev;
+pub mod block;
 pub mod keyboard;
 pub mod rtc;
 pub mod tsc;
+#[cfg(feature = "aslr")]
+pub mod random;
 
 use core::arch::asm;
 
@@ -68,13 +71,19 @@ pub fn read_cpu_info() {
     info!("Highest Extended Function: {:#x}\n", eax);
 }
 
-pub fn get_tsc_frequency() -> Option<u32> {
+/// 通过 CPUID 0x15 得到 TSC 的频率，单位为 Hz；
+/// 不支持这个功能号或者没有给出晶振频率时返回 None，需要用 PIT 校准
+pub fn get_tsc_frequency() -> Option<u64> {
+    let (max_leaf, _, _, _) = cpuid(0);
+    if max_leaf < 0x15 {
+        return None;
+    }
     let eax: u32 = 0x15;
     let (eax, ebx, ecx, edx) = cpuid(eax);
 
-    if ebx == 0 || ecx == 0 {
+    if eax == 0 || ebx == 0 || ecx == 0 {
         None
     } else {
-        Some(ecx * ebx / eax)
+        Some(ecx as u64 * ebx as u64 / eax as u64)
     }
 }
@@ -0,0 +1,62 @@
+//! 内核随机数，种子来自 RDRAND，CPU 不支持时使用 TSC 和 RTC 的抖动
+
+use core::arch::asm;
+use alloc::sync::Arc;
+use spin::Mutex;
+
+use super::{cpuid, rdtsc, read_second};
+
+lazy_static! {
+    static ref RANDOM_STATE: Arc<Mutex<u64>> = {
+        Arc::new(Mutex::new(seed()))
+    };
+}
+
+/// CPUID.01H:ECX[bit 30] 表示是否支持 RDRAND
+fn is_rdrand_supported() -> bool {
+    let (_, _, ecx, _) = cpuid(1);
+    ecx & (1 << 30) != 0
+}
+
+/// RDRAND 可能暂时没有可用的随机数，CF 为 0 时重试
+fn rdrand() -> Option<u32> {
+    for _ in 0..10 {
+        let value: u32;
+        let is_ok: u8;
+        unsafe {
+            asm!("rdrand {}", "setc {}", out(reg) value, out(reg_byte) is_ok);
+        }
+        if is_ok != 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn seed() -> u64 {
+    if is_rdrand_supported() {
+        if let (Some(low), Some(high)) = (rdrand(), rdrand()) {
+            return ((high as u64) << 32) | low as u64;
+        }
+    }
+    // 读取 RTC 端口的耗时有抖动，把每次读取的 TSC 差值混合到种子中
+    let mut seed = rdtsc() ^ ((read_second() as u64) << 56);
+    for _ in 0..64 {
+        let begin = rdtsc();
+        read_second();
+        seed = seed.rotate_left(7) ^ (rdtsc() - begin);
+    }
+    // xorshift 的状态不能为 0
+    if seed == 0 { 1 } else { seed }
+}
+
+/// xorshift64* 生成伪随机数
+pub fn random() -> u32 {
+    let mut state = RANDOM_STATE.lock();
+    let mut x = *state;
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    *state = x;
+    (x.wrapping_mul(0x2545f4914f6cdd1d) >> 32) as u32
+}
@@ -1,8 +1,31 @@
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use spin::Mutex;
+
 use crate::drivers::{keyboard::get_char, screen_print};
 
 use super::File;
 use crate::screen_print;
-use crate::schedule::suspend_current_and_run_next;
+use crate::process::TaskControlBlock;
+use crate::schedule::{block_current_and_run_next, current_task, wakeup_task};
+
+lazy_static! {
+    /// 等待键盘输入的线程
+    static ref STDIN_WAIT_QUEUE: Arc<Mutex<VecDeque<Arc<TaskControlBlock>>>> = Arc::new(Mutex::new(VecDeque::new()));
+}
+
+/// 键盘中断后唤醒所有等待输入的线程
+pub fn wakeup_stdin_readers() {
+    let mut wait_queue = STDIN_WAIT_QUEUE.lock();
+    while let Some(task) = wait_queue.pop_front() {
+        wakeup_task(task);
+    }
+}
+
+/// 线程被销毁时从等待队列中移除
+pub fn remove_stdin_reader(task: &Arc<TaskControlBlock>) {
+    STDIN_WAIT_QUEUE.lock().retain(|t| !Arc::ptr_eq(t, task));
+}
 
 pub struct Stdin;
 pub struct Stdout;
@@ -20,17 +43,24 @@ impl File for Stdin {
         if buf.len() == 0 {
             return 0;
         }
-        let c: u8;
+        if let Some(ch) = get_char() {
+            buf[0] = ch;
+            return 1;
+        }
+        let task = current_task().unwrap();
         loop {
+            // 没有输入时阻塞，键盘中断时唤醒
+            // 先在等待队列的锁内加入队列再检查输入：软中断先放入字符再取得这个锁唤醒线程，不会错过这期间到达的输入
+            let mut wait_queue = STDIN_WAIT_QUEUE.lock();
+            wait_queue.push_back(task.clone());
             if let Some(ch) = get_char() {
-                c = ch;
-                break;
-            } else {
-                suspend_current_and_run_next();
+                wait_queue.retain(|t| !Arc::ptr_eq(t, &task));
+                buf[0] = ch;
+                return 1;
             }
+            drop(wait_queue);
+            block_current_and_run_next();
         }
-        buf[0] = c;
-        1
     }
 
     fn write(&self, buf: &[u8]) -> usize {
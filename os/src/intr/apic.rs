@@ -0,0 +1,522 @@
+//! Local APIC 和 I/O APIC。
+//! CPU 支持 APIC 并且能从 ACPI 的 MADT 或者 MP 表中找到 I/O APIC 时，
+//! ISA 中断线改由 I/O APIC 投递到当前 CPU 的 Local APIC，EOI 发送给 Local APIC；
+//! 否则继续使用 8259A。
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use alloc::vec::Vec;
+use alloc::sync::Arc;
+use spin::Mutex;
+
+use crate::arch::x86::{outb, rdmsr, wrmsr};
+use crate::mm::ioremap::{ioremap, IoMapping};
+use crate::mm::PhysAddr;
+use super::irq::{CASCADE_IRQ, IRQ_COUNT};
+use super::pic::IRQ_BASE_VECTOR;
+use super::softirq::without_intr;
+use super::{IntrContext, INTR_HANDLER_TABLE};
+
+const IA32_APIC_BASE_MSR: u32 = 0x1b;
+/// IA32_APIC_BASE 的全局使能位
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_ADDRESS_MASK: u64 = 0xfffff000;
+
+/// Local APIC 寄存器的偏移
+const LAPIC_ID: usize = 0x20;
+const LAPIC_VERSION: usize = 0x30;
+const LAPIC_TPR: usize = 0x80;
+const LAPIC_EOI: usize = 0xb0;
+const LAPIC_SVR: usize = 0xf0;
+const LAPIC_ICR_LOW: usize = 0x300;
+const LAPIC_ICR_HIGH: usize = 0x310;
+const LAPIC_LVT_TIMER: usize = 0x320;
+const LAPIC_LVT_LINT0: usize = 0x350;
+const LAPIC_LVT_ERROR: usize = 0x370;
+const LAPIC_TIMER_INITIAL_COUNT: usize = 0x380;
+const LAPIC_TIMER_CURRENT_COUNT: usize = 0x390;
+const LAPIC_TIMER_DIVIDE: usize = 0x3e0;
+const LAPIC_SIZE: usize = 0x400;
+/// SVR 的软件使能位
+const LAPIC_SVR_ENABLE: u32 = 1 << 8;
+/// LVT 和重定向表项的屏蔽位
+const MASKED: u32 = 1 << 16;
+/// LVT 定时器表项的周期模式，否则为单次模式
+const LVT_TIMER_PERIODIC: u32 = 1 << 17;
+/// 定时器的分频系数为 16
+const LAPIC_TIMER_DIVIDE_BY_16: u32 = 0b0011;
+/// ICR 的投递方式和电平
+const ICR_FIXED: u32 = 0b000 << 8;
+const ICR_NMI: u32 = 0b100 << 8;
+const ICR_INIT: u32 = 0b101 << 8;
+const ICR_STARTUP: u32 = 0b110 << 8;
+const ICR_DELIVERY_PENDING: u32 = 1 << 12;
+const ICR_LEVEL_ASSERT: u32 = 1 << 14;
+const ICR_LEVEL_TRIGGER: u32 = 1 << 15;
+/// 伪中断的中断向量，P6 的 Local APIC 要求低 4 位全为 1
+const SPURIOUS_VECTOR: usize = 0x7f;
+
+/// I/O APIC 的寄存器选择和数据窗口
+const IOAPIC_REGSEL: usize = 0x00;
+const IOAPIC_WIN: usize = 0x10;
+const IOAPIC_SIZE: usize = 0x20;
+const IOAPIC_REG_VERSION: u32 = 0x01;
+const IOAPIC_REG_REDTBL: u32 = 0x10;
+/// 重定向表项低 32 位的极性和触发方式
+const REDIRECTION_ACTIVE_LOW: u32 = 1 << 13;
+const REDIRECTION_LEVEL: u32 = 1 << 15;
+
+/// 中断源覆盖的标志，ACPI 和 MP 表的编码相同
+/// bit 0~1 为极性，bit 2~3 为触发方式，0 表示总线默认（ISA 为高电平边沿触发）
+const INTI_POLARITY_MASK: u16 = 0b11;
+const INTI_POLARITY_LOW: u16 = 0b11;
+const INTI_TRIGGER_MASK: u16 = 0b11 << 2;
+const INTI_TRIGGER_LEVEL: u16 = 0b11 << 2;
+
+/// IMCR 的端口，写入 0x70 选择 IMCR，写入 1 后中断不再经过 8259A
+const IMCR_SELECT_PORT: u16 = 0x22;
+const IMCR_DATA_PORT: u16 = 0x23;
+
+/// BIOS 数据区中保存 EBDA 段地址的位置
+const EBDA_SEGMENT_ADDRESS: usize = 0x40e;
+const BIOS_ROM_BEGIN: usize = 0xe0000;
+const BIOS_ROM_END: usize = 0x100000;
+const SDT_HEADER_LEN: usize = 36;
+
+/// Local APIC 映射后的虚拟地址，0 表示没有启用 APIC
+static LAPIC_BASE: AtomicUsize = AtomicUsize::new(0);
+
+/// ISA 中断线在 I/O APIC 上对应的全局中断号（GSI）
+#[derive(Clone, Copy)]
+struct IrqRoute {
+    gsi: u32,
+    flags: u16,
+}
+
+struct IoApic {
+    id: u8,
+    gsi_base: u32,
+    entry_count: u32,
+    regs: IoMapping,
+}
+
+impl IoApic {
+    fn new(id: u8, address: usize, gsi_base: u32) -> Option<Self> {
+        let regs = ioremap(PhysAddr(address), IOAPIC_SIZE)?;
+        let mut ioapic = Self { id, gsi_base, entry_count: 0, regs };
+        ioapic.entry_count = ((ioapic.read(IOAPIC_REG_VERSION) >> 16) & 0xff) + 1;
+        Some(ioapic)
+    }
+
+    fn read(&self, reg: u32) -> u32 {
+        self.regs.write_u32(IOAPIC_REGSEL, reg);
+        self.regs.read_u32(IOAPIC_WIN)
+    }
+
+    fn write(&self, reg: u32, value: u32) {
+        self.regs.write_u32(IOAPIC_REGSEL, reg);
+        self.regs.write_u32(IOAPIC_WIN, value);
+    }
+
+    fn contains(&self, gsi: u32) -> bool {
+        self.gsi_base <= gsi && gsi < self.gsi_base + self.entry_count
+    }
+
+    /// 先写高 32 位的目标 APIC ID，再写包含屏蔽位的低 32 位
+    fn set_redirection(&self, gsi: u32, low: u32, high: u32) {
+        let reg = IOAPIC_REG_REDTBL + (gsi - self.gsi_base) * 2;
+        self.write(reg + 1, high);
+        self.write(reg, low);
+    }
+}
+
+struct Apic {
+    /// 所有 CPU 的 Local APIC 的物理地址相同，访问到的是各自的 Local APIC
+    lapic: IoMapping,
+    /// 中断投递的目标，即 BSP 的 APIC ID
+    bsp_id: u32,
+    ioapics: Vec<IoApic>,
+    routes: [Option<IrqRoute>; IRQ_COUNT],
+    /// 固件表中所有可用 CPU 的 APIC ID
+    cpus: Vec<u8>,
+}
+
+impl Apic {
+    fn set_irq_mask(&self, mask: u16) {
+        for (irq, route) in self.routes.iter().enumerate() {
+            let Some(route) = route else { continue };
+            let Some(ioapic) = self.ioapics.iter().find(|ioapic| ioapic.contains(route.gsi)) else { continue };
+            let mut low = (IRQ_BASE_VECTOR + irq) as u32;
+            if route.flags & INTI_POLARITY_MASK == INTI_POLARITY_LOW {
+                low |= REDIRECTION_ACTIVE_LOW;
+            }
+            if route.flags & INTI_TRIGGER_MASK == INTI_TRIGGER_LEVEL {
+                low |= REDIRECTION_LEVEL;
+            }
+            if mask & (1 << irq) != 0 {
+                low |= MASKED;
+            }
+            ioapic.set_redirection(route.gsi, low, self.bsp_id << 24);
+        }
+    }
+}
+
+/// 从 ACPI MADT 或者 MP 表中读到的中断控制器信息
+struct ApicConfig {
+    /// 所有可用 CPU 的 APIC ID
+    cpus: Vec<u8>,
+    /// (I/O APIC ID, 物理地址, 起始 GSI)
+    ioapics: Vec<(u8, usize, u32)>,
+    /// 中断源覆盖，(ISA 中断线, GSI, 标志)
+    overrides: Vec<(usize, u32, u16)>,
+    /// 需要通过 IMCR 把中断从 8259A 切换到 APIC
+    imcr_present: bool,
+}
+
+lazy_static! {
+    static ref APIC: Arc<Mutex<Option<Apic>>> = Arc::new(Mutex::new(None));
+}
+
+/// 检测并初始化 Local APIC 和 I/O APIC，屏蔽所有重定向表项
+/// 返回内容：false 表示不支持 APIC，需要使用 8259A
+pub fn init() -> bool {
+    // CPUID.01H:EDX[bit 9] 表示是否支持 Local APIC
+    let (_, _, _, edx) = crate::drivers::cpuid(1);
+    if edx & (1 << 9) == 0 {
+        info!("apic: not supported");
+        return false;
+    }
+    let Some(config) = parse_madt().or_else(parse_mp_table) else {
+        info!("apic: no MADT or MP table found");
+        return false;
+    };
+
+    let apic_base = rdmsr(IA32_APIC_BASE_MSR) | APIC_BASE_ENABLE;
+    wrmsr(IA32_APIC_BASE_MSR, apic_base);
+    let Some(lapic) = ioremap(PhysAddr((apic_base & APIC_BASE_ADDRESS_MASK) as usize), LAPIC_SIZE) else {
+        return false;
+    };
+    let ioapics: Vec<IoApic> = config.ioapics.iter().filter_map(|&(id, address, gsi_base)| IoApic::new(id, address, gsi_base)).collect();
+    if ioapics.is_empty() {
+        info!("apic: no I/O APIC found");
+        return false;
+    }
+
+    if config.imcr_present {
+        outb(0x70, IMCR_SELECT_PORT);
+        outb(0x01, IMCR_DATA_PORT);
+    }
+
+    LAPIC_BASE.store(lapic.base_address().0, Ordering::Relaxed);
+    setup_local_apic();
+    let bsp_id = local_apic_id();
+
+    let apic = Apic { lapic, bsp_id, ioapics, routes: isa_irq_routes(&config.overrides), cpus: config.cpus };
+    for ioapic in apic.ioapics.iter() {
+        for gsi in ioapic.gsi_base..ioapic.gsi_base + ioapic.entry_count {
+            ioapic.set_redirection(gsi, MASKED, 0);
+        }
+        info!("apic: I/O APIC {} gsi [{}, {})", ioapic.id, ioapic.gsi_base, ioapic.gsi_base + ioapic.entry_count);
+    }
+    info!("apic: local APIC id {} version {:#x}, {} cpus", bsp_id, lapic_read(LAPIC_VERSION) & 0xff, apic.cpus.len());
+
+    INTR_HANDLER_TABLE.lock()[SPURIOUS_VECTOR] = spurious_intr_handler;
+    *APIC.lock() = Some(apic);
+    true
+}
+
+/// AP 启动后打开自己的 Local APIC
+pub fn init_ap() {
+    assert!(is_enabled());
+    wrmsr(IA32_APIC_BASE_MSR, rdmsr(IA32_APIC_BASE_MSR) | APIC_BASE_ENABLE);
+    setup_local_apic();
+}
+
+/// 8259A 不再经过 LINT0 投递，Local APIC 的定时器由 clock 模块设置
+fn setup_local_apic() {
+    lapic_write(LAPIC_LVT_TIMER, MASKED);
+    lapic_write(LAPIC_LVT_LINT0, MASKED);
+    lapic_write(LAPIC_LVT_ERROR, MASKED);
+    lapic_write(LAPIC_TPR, 0);
+    lapic_write(LAPIC_SVR, LAPIC_SVR_ENABLE | SPURIOUS_VECTOR as u32);
+}
+
+fn lapic_read(reg: usize) -> u32 {
+    let base = LAPIC_BASE.load(Ordering::Relaxed);
+    assert_ne!(base, 0);
+    unsafe { core::ptr::read_volatile((base + reg) as *const u32) }
+}
+
+fn lapic_write(reg: usize, value: u32) {
+    let base = LAPIC_BASE.load(Ordering::Relaxed);
+    assert_ne!(base, 0);
+    unsafe { core::ptr::write_volatile((base + reg) as *mut u32, value) }
+}
+
+pub fn is_enabled() -> bool {
+    LAPIC_BASE.load(Ordering::Relaxed) != 0
+}
+
+/// 设置 ISA 中断线对应的重定向表项，mask 的位为 1 表示屏蔽
+pub fn set_irq_mask(mask: u16) {
+    if let Some(apic) = APIC.lock().as_ref() {
+        apic.set_irq_mask(mask);
+    }
+}
+
+/// 向 Local APIC 发送 EOI，I/O APIC 中的边沿和电平触发中断都由 Local APIC 转发 EOI
+pub fn send_eoi() {
+    lapic_write(LAPIC_EOI, 0);
+}
+
+/// 当前 CPU 的 APIC ID
+pub fn local_apic_id() -> u32 {
+    lapic_read(LAPIC_ID) >> 24
+}
+
+/// 固件表中所有可用 CPU 的 APIC ID，包括 BSP
+pub fn cpu_apic_ids() -> Vec<u32> {
+    APIC.lock().as_ref().map_or(Vec::new(), |apic| apic.cpus.iter().map(|&id| id as u32).collect())
+}
+
+/// 启动当前 CPU 的 Local APIC 定时器，从 initial_count 开始按总线频率的 1/16 递减，减到 0 时产生中断向量为 vector 的中断；
+/// 周期模式下重新从 initial_count 开始递减
+pub fn timer_start(vector: usize, initial_count: u32, periodic: bool) {
+    let mode = if periodic { LVT_TIMER_PERIODIC } else { 0 };
+    lapic_write(LAPIC_TIMER_DIVIDE, LAPIC_TIMER_DIVIDE_BY_16);
+    lapic_write(LAPIC_LVT_TIMER, mode | vector as u32);
+    lapic_write(LAPIC_TIMER_INITIAL_COUNT, initial_count);
+}
+
+/// 停止当前 CPU 的 Local APIC 定时器
+pub fn timer_stop() {
+    lapic_write(LAPIC_LVT_TIMER, MASKED);
+    lapic_write(LAPIC_TIMER_INITIAL_COUNT, 0);
+}
+
+/// 当前 CPU 的 Local APIC 定时器的当前计数
+pub fn timer_current_count() -> u32 {
+    lapic_read(LAPIC_TIMER_CURRENT_COUNT)
+}
+
+/// 写 ICR 发送 IPI，等待 Local APIC 把消息发出去；
+/// 写高低两个寄存器之间不能被同样发送 IPI 的中断处理函数打断
+fn send_icr(apic_id: u32, low: u32) {
+    without_intr(|| {
+        lapic_write(LAPIC_ICR_HIGH, apic_id << 24);
+        lapic_write(LAPIC_ICR_LOW, low);
+        while lapic_read(LAPIC_ICR_LOW) & ICR_DELIVERY_PENDING != 0 {
+            core::hint::spin_loop();
+        }
+    });
+}
+
+/// 向 apic_id 对应的 CPU 发送中断向量为 vector 的 IPI
+pub fn send_ipi(apic_id: u32, vector: usize) {
+    send_icr(apic_id, ICR_FIXED | ICR_LEVEL_ASSERT | vector as u32);
+}
+
+/// NMI 不受 IF 的影响，关中断自旋的 CPU 也会立即处理
+pub fn send_nmi(apic_id: u32) {
+    send_icr(apic_id, ICR_NMI | ICR_LEVEL_ASSERT);
+}
+
+/// INIT IPI 让 AP 进入等待 SIPI 的状态，旧的 APIC 还需要再发送一次 deassert
+pub fn send_init(apic_id: u32) {
+    send_icr(apic_id, ICR_INIT | ICR_LEVEL_TRIGGER | ICR_LEVEL_ASSERT);
+    send_icr(apic_id, ICR_INIT | ICR_LEVEL_TRIGGER);
+}
+
+/// SIPI 让 AP 从物理地址 page << 12 开始以实模式运行
+pub fn send_startup(apic_id: u32, page: usize) {
+    assert!(page < 0x100);
+    send_icr(apic_id, ICR_STARTUP | page as u32);
+}
+
+/// 伪中断不需要发送 EOI
+fn spurious_intr_handler(_intr_context: &mut IntrContext) {}
+
+/// ISA 中断线默认与 GSI 一一对应，被覆盖的中断线使用覆盖后的 GSI，
+/// 被其他中断线占用的 GSI 不再作为默认映射；级联用的 IRQ 2 不存在
+fn isa_irq_routes(overrides: &[(usize, u32, u16)]) -> [Option<IrqRoute>; IRQ_COUNT] {
+    let mut routes: [Option<IrqRoute>; IRQ_COUNT] = core::array::from_fn(|irq| Some(IrqRoute { gsi: irq as u32, flags: 0 }));
+    for &(irq, gsi, flags) in overrides.iter() {
+        if irq >= IRQ_COUNT {
+            continue;
+        }
+        for route in routes.iter_mut() {
+            if matches!(route, Some(route) if route.gsi == gsi) {
+                *route = None;
+            }
+        }
+        routes[irq] = Some(IrqRoute { gsi, flags });
+    }
+    routes[CASCADE_IRQ] = None;
+    routes
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+fn checksum(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) == 0
+}
+
+/// 在 [begin, end) 中按 16 字节对齐查找签名，valid 检查找到的结构是否有效
+/// 返回内容：结构的物理地址
+fn scan(begin: usize, end: usize, signature: &[u8], valid: fn(&[u8]) -> bool) -> Option<usize> {
+    let region = ioremap(PhysAddr(begin), end - begin)?;
+    let bytes = region.as_bytes();
+    (0..bytes.len()).step_by(16)
+        .find(|&offset| bytes[offset..].starts_with(signature) && valid(&bytes[offset..]))
+        .map(|offset| begin + offset)
+}
+
+/// EBDA 的物理地址
+fn ebda_address() -> Option<usize> {
+    let bda = ioremap(PhysAddr(EBDA_SEGMENT_ADDRESS), 2)?;
+    let address = (u16_at(bda.as_bytes(), 0) as usize) << 4;
+    if address == 0 { None } else { Some(address) }
+}
+
+/// 依次在 EBDA 的前 1KB 和 BIOS ROM 中查找
+fn scan_bios(signature: &[u8], rom_begin: usize, valid: fn(&[u8]) -> bool) -> Option<usize> {
+    ebda_address()
+        .and_then(|ebda| scan(ebda, ebda + 0x400, signature, valid))
+        .or_else(|| scan(rom_begin, BIOS_ROM_END, signature, valid))
+}
+
+/// 映射一张 ACPI 表，先读表头得到长度再映射整张表
+fn map_sdt(pa: usize) -> Option<IoMapping> {
+    let header = ioremap(PhysAddr(pa), SDT_HEADER_LEN)?;
+    let length = u32_at(header.as_bytes(), 4) as usize;
+    drop(header);
+    if length < SDT_HEADER_LEN {
+        return None;
+    }
+    let table = ioremap(PhysAddr(pa), length)?;
+    if checksum(table.as_bytes()) { Some(table) } else { None }
+}
+
+/// 通过 RSDP 找到 RSDT，再在 RSDT 中找到签名为 signature 的 ACPI 表
+/// 返回内容：映射好的整张表，包括表头
+pub fn find_acpi_table(signature: &[u8; 4]) -> Option<IoMapping> {
+    let rsdp_address = scan_bios(b"RSD PTR ", BIOS_ROM_BEGIN, |bytes| bytes.len() >= 20 && checksum(&bytes[..20]))?;
+    let rsdp = ioremap(PhysAddr(rsdp_address), 20)?;
+    let rsdt = map_sdt(u32_at(rsdp.as_bytes(), 16) as usize)?;
+    let rsdt_bytes = rsdt.as_bytes();
+    (SDT_HEADER_LEN..rsdt_bytes.len()).step_by(4)
+        .filter_map(|offset| map_sdt(u32_at(rsdt_bytes, offset) as usize))
+        .find(|table| table.as_bytes().starts_with(signature))
+}
+
+/// MADT 的签名为 "APIC"
+fn parse_madt() -> Option<ApicConfig> {
+    let madt = find_acpi_table(b"APIC")?;
+    let bytes = madt.as_bytes();
+    let mut config = ApicConfig { cpus: Vec::new(), ioapics: Vec::new(), overrides: Vec::new(), imcr_present: false };
+    // 表头之后是 Local APIC 地址和标志，然后是变长的表项
+    let mut offset = SDT_HEADER_LEN + 8;
+    while offset + 2 <= bytes.len() {
+        let (entry_type, entry_len) = (bytes[offset], bytes[offset + 1] as usize);
+        if entry_len < 2 || offset + entry_len > bytes.len() {
+            break;
+        }
+        let entry = &bytes[offset..offset + entry_len];
+        match entry_type {
+            // Processor Local APIC，flags bit 0 表示可用
+            0 if entry_len >= 8 => {
+                if u32_at(entry, 4) & 1 != 0 {
+                    config.cpus.push(entry[3]);
+                }
+            }
+            // I/O APIC
+            1 if entry_len >= 12 => config.ioapics.push((entry[2], u32_at(entry, 4) as usize, u32_at(entry, 8))),
+            // Interrupt Source Override，只处理 ISA 总线
+            2 if entry_len >= 10 && entry[2] == 0 => config.overrides.push((entry[3] as usize, u32_at(entry, 4), u16_at(entry, 8))),
+            _ => {}
+        }
+        offset += entry_len;
+    }
+    Some(config)
+}
+
+/// MP 浮动指针结构（签名为 "_MP_"）指向 MP 配置表（签名为 "PCMP"）
+fn parse_mp_table() -> Option<ApicConfig> {
+    let valid = |bytes: &[u8]| bytes.len() >= 16 && bytes[8] != 0 && bytes.len() >= bytes[8] as usize * 16 && checksum(&bytes[..bytes[8] as usize * 16]);
+    let mpfp_address = scan_bios(b"_MP_", 0xf0000, valid)
+        .or_else(|| scan(0x9fc00, 0xa0000, b"_MP_", valid))?;
+    let mpfp = ioremap(PhysAddr(mpfp_address), 16)?;
+    let mpfp_bytes = mpfp.as_bytes();
+    // 没有配置表、使用默认配置的情况不支持
+    let table_address = u32_at(mpfp_bytes, 4) as usize;
+    if table_address == 0 {
+        return None;
+    }
+    let imcr_present = mpfp_bytes[12] & (1 << 7) != 0;
+
+    let header = ioremap(PhysAddr(table_address), 44)?;
+    let length = u16_at(header.as_bytes(), 4) as usize;
+    let entry_count = u16_at(header.as_bytes(), 34) as usize;
+    drop(header);
+    let table = ioremap(PhysAddr(table_address), length)?;
+    let bytes = table.as_bytes();
+    if !bytes.starts_with(b"PCMP") || !checksum(bytes) {
+        return None;
+    }
+
+    let mut config = ApicConfig { cpus: Vec::new(), ioapics: Vec::new(), overrides: Vec::new(), imcr_present };
+    let mut isa_buses = Vec::new();
+    // (I/O APIC ID, 中断线, 标志, 引脚)
+    let mut intr_entries = Vec::new();
+    let mut gsi_base = 0;
+    let mut offset = 44;
+    for _ in 0..entry_count {
+        if offset >= bytes.len() {
+            break;
+        }
+        // 处理器表项 20 字节，其余表项 8 字节
+        let entry_len = if bytes[offset] == 0 { 20 } else { 8 };
+        if offset + entry_len > bytes.len() {
+            break;
+        }
+        let entry = &bytes[offset..offset + entry_len];
+        match entry[0] {
+            0 => {
+                if entry[3] & 1 != 0 {
+                    config.cpus.push(entry[1]);
+                }
+            }
+            1 => {
+                if entry[2..8].starts_with(b"ISA") {
+                    isa_buses.push(entry[1]);
+                }
+            }
+            // I/O APIC 的起始 GSI 按出现的顺序累加
+            2 => {
+                if entry[3] & 1 != 0 {
+                    let ioapic = IoApic::new(entry[1], u32_at(entry, 4) as usize, gsi_base)?;
+                    gsi_base += ioapic.entry_count;
+                    config.ioapics.push((ioapic.id, u32_at(entry, 4) as usize, ioapic.gsi_base));
+                }
+            }
+            // I/O 中断表项，中断类型 0 表示普通的向量中断
+            3 if entry[1] == 0 => intr_entries.push((entry[4], entry[5] as usize, u16_at(entry, 2), entry[6], entry[7] as u32)),
+            _ => {}
+        }
+        offset += entry_len;
+    }
+    for (bus, irq, flags, ioapic_id, pin) in intr_entries {
+        if !isa_buses.contains(&bus) {
+            continue;
+        }
+        if let Some(&(_, _, base)) = config.ioapics.iter().find(|ioapic| ioapic.0 == ioapic_id) {
+            config.overrides.push((irq, base + pin, flags));
+        }
+    }
+    Some(config)
+}
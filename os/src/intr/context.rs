@@ -107,7 +107,7 @@ impl IntrRegisterContext {
     }
 
     pub fn kernel_default() -> Self {
-        Self { es: DATA_SELECTOR as usize, ds: DATA_SELECTOR as usize, fs: DATA_SELECTOR as usize, gs: DATA_SELECTOR as usize, eax: 0, ecx: 0, edx: 0, ebx: 0, ebp: 0, esi: 0, edi: 0 }
+        Self { es: DATA_SELECTOR as usize, ds: DATA_SELECTOR as usize, fs: DATA_SELECTOR as usize, gs: PERCPU_SELECTOR as usize, eax: 0, ecx: 0, edx: 0, ebx: 0, ebp: 0, esi: 0, edi: 0 }
     }
     
     pub fn user_default() -> Self {
@@ -0,0 +1,175 @@
+//! ISA 的 16 条中断线的注册和分发，中断由 I/O APIC 或者 8259A 投递。
+//! 一条中断线可以注册多个处理函数（共享中断），中断到来时依次调用，
+//! 有处理函数时自动打开中断线，分发后统一发送 EOI。
+
+use alloc::vec::Vec;
+use alloc::sync::Arc;
+use spin::Mutex;
+
+use super::{IntrContext, INTR_HANDLER_TABLE};
+use super::pic::{self, IRQ_BASE_VECTOR};
+use super::apic;
+use super::softirq::without_intr;
+
+/// 中断线的数量，IRQ 0~7 接主片，IRQ 8~15 接从片
+pub const IRQ_COUNT: usize = 16;
+/// 从片接在主片的 IRQ 2 上
+pub const CASCADE_IRQ: usize = 2;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum IrqReturn {
+    /// 中断不是这个设备产生的
+    None,
+    Handled,
+}
+
+/// 参数：中断线、注册时的 dev_data、中断上下文
+pub type IrqHandler = fn(usize, usize, &mut IntrContext) -> IrqReturn;
+
+struct IrqAction {
+    handler: IrqHandler,
+    name: &'static str,
+    /// 区分同一条中断线上的设备，free_irq 时使用
+    dev_data: usize,
+}
+
+struct IrqDesc {
+    actions: Vec<IrqAction>,
+    /// 中断次数
+    count: u64,
+    /// 没有处理函数处理的中断次数
+    spurious_count: u64,
+}
+
+/// 中断线的统计信息
+pub struct IrqStat {
+    pub irq: usize,
+    pub count: u64,
+    pub spurious_count: u64,
+    pub names: Vec<&'static str>,
+}
+
+lazy_static! {
+    static ref IRQ_DESCS: Arc<Mutex<Vec<IrqDesc>>> = {
+        let mut irq_descs = Vec::new();
+        for _ in 0..IRQ_COUNT {
+            irq_descs.push(IrqDesc { actions: Vec::new(), count: 0, spurious_count: 0 });
+        }
+        Arc::new(Mutex::new(irq_descs))
+    };
+    /// 中断线的屏蔽位，1 表示屏蔽
+    static ref IRQ_MASK: Arc<Mutex<u16>> = Arc::new(Mutex::new(0xffff));
+}
+
+pub fn init() {
+    // 优先使用 APIC，此时 8259A 的中断线全部屏蔽
+    if apic::init() {
+        pic::set_irq_mask(0xffff);
+        info!("irq: use local APIC and I/O APIC");
+    } else {
+        info!("irq: use 8259A");
+    }
+    set_irq_mask(*IRQ_MASK.lock());
+    let mut intr_handler_table = INTR_HANDLER_TABLE.lock();
+    for irq in 0..IRQ_COUNT {
+        intr_handler_table[IRQ_BASE_VECTOR + irq] = handle_irq;
+    }
+}
+
+/// 为中断线注册处理函数并打开中断线，同一条中断线上的 dev_data 不能重复
+/// 返回内容：None 表示中断线不存在或者 dev_data 已经注册过
+pub fn request_irq(irq: usize, handler: IrqHandler, name: &'static str, dev_data: usize) -> Option<()> {
+    if irq >= IRQ_COUNT {
+        return None;
+    }
+    without_intr(|| {
+        let mut irq_descs = IRQ_DESCS.lock();
+        let desc = &mut irq_descs[irq];
+        if desc.actions.iter().any(|action| action.dev_data == dev_data) {
+            return None;
+        }
+        desc.actions.push(IrqAction { handler, name, dev_data });
+        drop(irq_descs);
+        update_irq_mask();
+        Some(())
+    })
+}
+
+/// 注销中断线上 dev_data 对应的处理函数，没有处理函数时屏蔽中断线
+/// 返回内容：None 表示没有找到处理函数
+pub fn free_irq(irq: usize, dev_data: usize) -> Option<()> {
+    if irq >= IRQ_COUNT {
+        return None;
+    }
+    without_intr(|| {
+        let mut irq_descs = IRQ_DESCS.lock();
+        let actions = &mut irq_descs[irq].actions;
+        let index = actions.iter().position(|action| action.dev_data == dev_data)?;
+        actions.remove(index);
+        drop(irq_descs);
+        update_irq_mask();
+        Some(())
+    })
+}
+
+/// 所有中断线的统计信息
+pub fn irq_stats() -> Vec<IrqStat> {
+    without_intr(|| {
+        IRQ_DESCS.lock().iter().enumerate().map(|(irq, desc)| IrqStat {
+            irq,
+            count: desc.count,
+            spurious_count: desc.spurious_count,
+            names: desc.actions.iter().map(|action| action.name).collect(),
+        }).collect()
+    })
+}
+
+fn set_irq_mask(mask: u16) {
+    if apic::is_enabled() {
+        apic::set_irq_mask(mask);
+    } else {
+        pic::set_irq_mask(mask);
+    }
+}
+
+fn send_eoi(irq: usize) {
+    if apic::is_enabled() {
+        apic::send_eoi();
+    } else {
+        pic::send_eoi(irq);
+    }
+}
+
+/// 根据注册的处理函数重新设置屏蔽位，从片有打开的中断线时打开级联的中断线
+fn update_irq_mask() {
+    let mut mask: u16 = 0xffff;
+    for (irq, desc) in IRQ_DESCS.lock().iter().enumerate() {
+        if !desc.actions.is_empty() {
+            mask &= !(1 << irq);
+        }
+    }
+    if mask & 0xff00 != 0xff00 {
+        mask &= !(1 << CASCADE_IRQ);
+    }
+    let mut irq_mask = IRQ_MASK.lock();
+    if *irq_mask != mask {
+        *irq_mask = mask;
+        set_irq_mask(mask);
+    }
+}
+
+fn handle_irq(intr_context: &mut IntrContext) {
+    let irq = intr_context.intr - IRQ_BASE_VECTOR;
+    let mut irq_descs = IRQ_DESCS.lock();
+    let desc = &mut irq_descs[irq];
+    desc.count += 1;
+    let mut handled = false;
+    for action in desc.actions.iter() {
+        handled |= (action.handler)(irq, action.dev_data, intr_context) == IrqReturn::Handled;
+    }
+    if !handled {
+        desc.spurious_count += 1;
+    }
+    drop(irq_descs);
+    send_eoi(irq);
+}
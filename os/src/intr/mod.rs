@@ -2,6 +2,9 @@
 mod define;
 mod context;
 mod pic;
+pub mod apic;
+pub mod irq;
+pub mod softirq;
 
 pub use context::*;
 pub use define::IrqErrorCode;
@@ -11,7 +14,9 @@ use define::*;
 use spin::Mutex;
 use IrqType::TIME;
 use crate::arch::x86::{DescriptorTablePointer, GateDescriptor};
-use crate::schedule::suspend_current_and_run_next;
+use crate::schedule::{account_kernel_entry, account_kernel_exit, current_task, exit_current_if_killed, preempt_if_need_resched, suspend_current_and_run_next};
+use softirq::do_softirq;
+use crate::smp::handle_nmi;
 
 global_asm!(include_str!("trap.S"));
 
@@ -29,18 +34,23 @@ pub fn set_ldt_entry(idx: usize, dpl: usize) {
 }
 
 pub fn init() {
+    define::init();
+    softirq::init();
+    pic::init();
+    load_idt();
+    info!("intr::init done");
+}
+
+/// 所有 CPU 共用一张 IDT，AP 启动后也需要加载
+pub fn load_idt() {
     extern "C" {
         fn intr_table();
     }
 
-    define::init();
-    pic::init();
-
     let idt_pointer = DescriptorTablePointer::new((intr_table as usize).try_into().unwrap(), (IDT_MAX_LEN * 8 - 1).try_into().unwrap());
     unsafe {
         asm!("lidt [{}]", in(reg) &idt_pointer);
     }
-    info!("intr::init done");
 }
 
 pub fn begin_intr() {
@@ -58,9 +68,30 @@ pub extern "C" fn intr_handler(mut intr_context: IntrContext) {
     let eip = intr_context.eip;
     let cs = intr_context.cs;
     assert!((intr >> 8) == 0);
+    // NMI 可能打断持有锁的代码，不能查询处理函数表
+    if intr == IrqType::NMI_INTR as usize {
+        handle_nmi();
+        return;
+    }
+    let from_user = intr_context.cs & 0b11 == 0b11;
+    if from_user {
+        exit_current_if_killed();
+        account_kernel_entry();
+    }
     let handler = INTR_HANDLER_TABLE.lock()[intr];
     handler(&mut intr_context);
 
+    // 中断前在用户态或者 CPU 空闲时，处理软中断并检查是否需要切换线程；
+    // 内核代码关中断运行，在内核态被打断的只有软中断，返回后由外层中断处理
+    if intr_context.cs & 0b11 == 0b11 || current_task().is_none() {
+        do_softirq();
+        preempt_if_need_resched();
+    }
+    if from_user {
+        exit_current_if_killed();
+        account_kernel_exit();
+    }
+
     let eip = intr_context.eip;
     let cs = intr_context.cs;
     let ss = intr_context.ss;
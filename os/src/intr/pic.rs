@@ -1,12 +1,17 @@
 use super::IntrContext;
-use super::IrqType;
-use super::INTR_HANDLER_TABLE;
+use super::irq::{self, request_irq, IrqReturn};
 use crate::arch::x86::pic;
 use crate::arch::x86::outb;
-use crate::schedule::suspend_current_and_run_next;
-use crate::drivers::keyboard::handle_keyboard_intr;
-use crate::timer::check_timer;
-use crate::timer::update_time;
+use super::softirq::{without_intr, Tasklet};
+use crate::fs::stdio::wakeup_stdin_readers;
+use crate::drivers::keyboard::{handle_keyboard_intr, handle_scan_codes};
+
+/// 在软中断中解析扫描码并唤醒等待输入的线程
+static KEYBOARD_TASKLET: Tasklet = Tasklet::new(keyboard_tasklet);
+
+/// IRQ 0 对应的中断向量
+pub const IRQ_BASE_VECTOR: usize = 0x20;
+const KEYBOARD_IRQ: usize = 1;
 
 /// 主片的控制端口
 const PIC_M_CTRL: u16 = 0x20;
@@ -21,7 +26,7 @@ pub fn init() {
     // 初始化主片
     assert_eq!(pic::ICW1::new(true, false, false).0, 0x11u8);
     outb(pic::ICW1::new(true, false, false).0, PIC_M_CTRL);  // ICW1: 边缘触发，级联 8259，需要ICW4
-    outb(pic::ICW2(0x20).0, PIC_M_DATA);  // ICW2: 起始中断向量为 0x20
+    outb(pic::ICW2(IRQ_BASE_VECTOR as u8).0, PIC_M_DATA);  // ICW2: 起始中断向量为 0x20
     assert_eq!(pic::ICW3::master(2).0, 0x04u8);
     outb(pic::ICW3::master(2).0, PIC_M_DATA);  // ICW3: IR2 接从片
     assert_eq!(pic::ICW4::uPM.bits(), 0x01u8);
@@ -30,63 +35,38 @@ pub fn init() {
     // 初始化从片
     assert_eq!(pic::ICW1::new(true, false, false).0, 0x11u8);
     outb(pic::ICW1::new(true, false, false).0, PIC_S_CTRL);  // ICW1: ICW1: 边缘触发，级联 8259，需要ICW4
-    outb(pic::ICW2(0x28).0, PIC_S_DATA);  // ICW2: 起始中断向量为 0x28
+    outb(pic::ICW2(IRQ_BASE_VECTOR as u8 + 8).0, PIC_S_DATA);  // ICW2: 起始中断向量为 0x28
     assert_eq!(pic::ICW3::slaver(2).0, 0x02u8);
     outb(pic::ICW3::slaver(2).0, PIC_S_DATA);  // ICW3: 设置从片连接到主片的IR2引脚
     assert_eq!(pic::ICW4::uPM.bits(), 0x01u8);
     outb(pic::ICW4::uPM.bits(), PIC_S_DATA);  // ICW4: 8086 模式，正常 EOI
 
-    // 只打开时钟中断
-    outb(0xfc, PIC_M_DATA);
-    outb(0xff, PIC_S_DATA);
-
-    register_pic_intr();
+    irq::init();
+    request_irq(KEYBOARD_IRQ, keyboard_intr_handler, "keyboard", 0).unwrap();
 }
 
-fn register_pic_intr() {
-    let mut intr_handler_table = INTR_HANDLER_TABLE.lock();
-    intr_handler_table[IrqType::TIME as usize] = time_intr_handler;
-    intr_handler_table[IrqType::KEYBOARD as usize] = keyboard_intr_handler;
-    intr_handler_table[IrqType::IRQ_0X22 as usize] = pic_master_intr_handler;
-    intr_handler_table[IrqType::IRQ_0X23 as usize] = pic_master_intr_handler;
-    intr_handler_table[IrqType::IRQ_0X24 as usize] = pic_master_intr_handler;
-    intr_handler_table[IrqType::IRQ_0X25 as usize] = pic_master_intr_handler;
-    intr_handler_table[IrqType::IRQ_0X26 as usize] = pic_master_intr_handler;
-    intr_handler_table[IrqType::IRQ_0X27 as usize] = pic_master_intr_handler;
-
-    intr_handler_table[IrqType::IRQ_0X28 as usize] = pic_slaver_intr_handler;
-    intr_handler_table[IrqType::IRQ_0X29 as usize] = pic_slaver_intr_handler;
-    intr_handler_table[IrqType::IRQ_0X2A as usize] = pic_slaver_intr_handler;
-    intr_handler_table[IrqType::IRQ_0X2B as usize] = pic_slaver_intr_handler;
-    intr_handler_table[IrqType::IRQ_0X2C as usize] = pic_slaver_intr_handler;
-    intr_handler_table[IrqType::IRQ_0X2D as usize] = pic_slaver_intr_handler;
-    intr_handler_table[IrqType::IRQ_0X2E as usize] = pic_slaver_intr_handler;
-    intr_handler_table[IrqType::IRQ_0X2F as usize] = pic_slaver_intr_handler;
+/// 设置主片和从片的 OCW1，mask 的低 8 位对应主片，高 8 位对应从片
+pub fn set_irq_mask(mask: u16) {
+    outb((mask & 0xff) as u8, PIC_M_DATA);
+    outb((mask >> 8) as u8, PIC_S_DATA);
 }
 
-fn time_intr_handler(intr_context: &mut IntrContext) {
-    update_time();
-    check_timer();
-
+/// 中断处理完后发送 EOI，从片的中断需要同时向主片和从片发送
+pub fn send_eoi(irq: usize) {
     assert_eq!(pic::OCW2::new(false, false, true, 0).0, 0x20);
+    if irq >= 8 {
+        outb(pic::OCW2::new(false, false, true, 0).0, PIC_S_CTRL);
+    }
     outb(pic::OCW2::new(false, false, true, 0).0, PIC_M_CTRL);
-    
-    suspend_current_and_run_next();
 }
 
-fn keyboard_intr_handler(intr_context: &mut IntrContext) {
+fn keyboard_intr_handler(_irq: usize, _dev_data: usize, _intr_context: &mut IntrContext) -> IrqReturn {
     handle_keyboard_intr();
-    assert_eq!(pic::OCW2::new(false, false, true, 0).0, 0x20);
-    outb(pic::OCW2::new(false, false, true, 0).0, PIC_M_CTRL);
+    KEYBOARD_TASKLET.schedule();
+    IrqReturn::Handled
 }
 
-fn pic_master_intr_handler(intr_context: &mut IntrContext) {
-    assert_eq!(pic::OCW2::new(false, false, true, 0).0, 0x20);
-    outb(pic::OCW2::new(false, false, true, 0).0, PIC_M_CTRL);
-}
-
-fn pic_slaver_intr_handler(intr_context: &mut IntrContext) {
-    assert_eq!(pic::OCW2::new(false, false, true, 0).0, 0x20);
-    outb(pic::OCW2::new(false, false, true, 0).0, PIC_S_CTRL);
-    outb(pic::OCW2::new(false, false, true, 0).0, PIC_M_CTRL);
+fn keyboard_tasklet() {
+    handle_scan_codes();
+    without_intr(wakeup_stdin_readers);
 }
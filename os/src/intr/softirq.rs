@@ -0,0 +1,136 @@
+//! 软中断：中断处理函数（上半部）只做必须关中断完成的工作，
+//! 其余工作推迟到软中断（下半部）中开中断运行，缩短关中断的时间。
+//! 软中断在中断返回用户态或者空闲循环前运行，运行时可能被硬件中断打断，
+//! 所以软中断中访问上半部也会使用的锁时需要用 without_intr 关中断。
+//! 等待的软中断和是否正在处理都是每个 CPU 一份的，软中断在标记它的 CPU 上处理。
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, Ordering};
+use alloc::{collections::VecDeque, sync::Arc};
+use spin::Mutex;
+
+use crate::arch::x86::Eflags;
+use crate::percpu;
+
+#[derive(Clone, Copy)]
+pub enum SoftIrq {
+    /// 唤醒定时器到期的线程
+    Timer = 0,
+    /// 运行 Tasklet
+    Tasklet = 1,
+}
+
+const SOFTIRQ_COUNT: usize = 2;
+/// 一次中断返回前最多处理软中断的轮数，中断频繁时剩下的软中断留到下次中断返回时处理
+const MAX_SOFTIRQ_RESTART: usize = 10;
+
+percpu! {
+    /// 当前 CPU 等待处理的软中断，第 i 位对应 SoftIrq 中值为 i 的软中断
+    static PENDING: usize = 0;
+    /// 当前 CPU 正在处理软中断，打断软中断的硬件中断返回时不再处理软中断
+    static IN_SOFTIRQ: bool = false;
+}
+
+/// 每种软中断的处理函数，下标是软中断号
+type SoftirqHandlers = [Option<fn()>; SOFTIRQ_COUNT];
+
+lazy_static! {
+    static ref SOFTIRQ_HANDLERS: Arc<Mutex<SoftirqHandlers>> = Arc::new(Mutex::new([None; SOFTIRQ_COUNT]));
+    static ref TASKLET_QUEUE: Arc<Mutex<VecDeque<&'static Tasklet>>> = Arc::new(Mutex::new(VecDeque::new()));
+}
+
+pub fn init() {
+    open_softirq(SoftIrq::Tasklet, tasklet_action);
+}
+
+/// 注册软中断的处理函数
+pub fn open_softirq(softirq: SoftIrq, handler: fn()) {
+    SOFTIRQ_HANDLERS.lock()[softirq as usize] = Some(handler);
+}
+
+/// 标记软中断在当前 CPU 上等待处理，通常在中断处理函数中调用
+pub fn raise_softirq(softirq: SoftIrq) {
+    PENDING.with_mut(|pending| *pending |= 1 << softirq as usize);
+}
+
+/// 处理当前 CPU 上等待的软中断，调用前后都是关中断的状态；
+/// 软中断运行时被打断也只会返回内核态，不会切换线程，始终在同一个 CPU 上
+pub fn do_softirq() {
+    let is_started = IN_SOFTIRQ.with_mut(|in_softirq| {
+        if *in_softirq || PENDING.with(|pending| *pending == 0) {
+            return false;
+        }
+        *in_softirq = true;
+        true
+    });
+    if !is_started {
+        return;
+    }
+    let handlers = *SOFTIRQ_HANDLERS.lock();
+    for _ in 0..MAX_SOFTIRQ_RESTART {
+        let pending = PENDING.with_mut(core::mem::take);
+        if pending == 0 {
+            break;
+        }
+        unsafe {
+            asm!("sti");
+        }
+        for (nr, handler) in handlers.iter().enumerate() {
+            if pending & (1 << nr) != 0 {
+                if let Some(handler) = handler {
+                    handler();
+                }
+            }
+        }
+        unsafe {
+            asm!("cli");
+        }
+    }
+    IN_SOFTIRQ.with_mut(|in_softirq| *in_softirq = false);
+}
+
+/// 关中断运行 f，之后恢复原来的中断状态
+pub fn without_intr<R>(f: impl FnOnce() -> R) -> R {
+    let old_eflags = Eflags::read();
+    if old_eflags.contains(Eflags::IF) {
+        unsafe {
+            asm!("cli");
+        }
+    }
+    let result = f();
+    if old_eflags.contains(Eflags::IF) {
+        unsafe {
+            asm!("sti");
+        }
+    }
+    result
+}
+
+/// 在软中断中运行的函数，同一个 Tasklet 在运行前多次调度只运行一次
+pub struct Tasklet {
+    func: fn(),
+    scheduled: AtomicBool,
+}
+
+impl Tasklet {
+    pub const fn new(func: fn()) -> Self {
+        Self { func, scheduled: AtomicBool::new(false) }
+    }
+
+    /// 加入 Tasklet 队列，在下次处理软中断时运行
+    pub fn schedule(&'static self) {
+        if self.scheduled.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        without_intr(|| TASKLET_QUEUE.lock().push_back(self));
+        raise_softirq(SoftIrq::Tasklet);
+    }
+}
+
+fn tasklet_action() {
+    while let Some(tasklet) = without_intr(|| TASKLET_QUEUE.lock().pop_front()) {
+        // 先清除标记，运行期间再次调度的 Tasklet 会重新运行
+        tasklet.scheduled.store(false, Ordering::Release);
+        (tasklet.func)();
+    }
+}
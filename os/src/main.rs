@@ -31,6 +31,7 @@ mod boards;
 mod mm;
 mod intr;
 mod timer;
+mod clock;
 mod process;
 mod schedule;
 mod syscall;
@@ -38,6 +39,7 @@ mod utils;
 mod programs;
 mod fs;
 mod sync;
+mod smp;
 
 use core::arch::global_asm;
 global_asm!(include_str!("entry.asm"));
@@ -60,6 +62,9 @@ pub fn main() -> ! {
     timer::init();
     syscall::init();
     schedule::init();
+    process::workqueue::init();
+    mm::swap::start_kswapd();
+    smp::start_aps();
     // schedule::test();
     // intr::begin_intr();
     schedule::run_tasks();
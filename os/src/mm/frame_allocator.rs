@@ -5,6 +5,7 @@ use spin::Mutex;
 
 use crate::{config::*, mm::{PhysAddr, VirtAddr, VirtPageNum}};
 use super::{PhysPageNum, MEMORY_INFO};
+use super::swap::swap_out_one;
 use crate::utils::*;
 
 pub trait FrameAllocator {
@@ -41,6 +42,17 @@ impl<'a, const N: usize> SimpleAllocator<'a, { N }> {
     fn get_bitmap(&self, idx: usize) -> bool {
         self.page_map.get(self.inner_index(idx))
     }
+
+    /// 可以分配的页总数
+    fn total_count(&self) -> usize {
+        self.end - self.begin
+    }
+
+    /// 空闲的页数，current 之后的页都是空闲的
+    fn free_count(&self) -> usize {
+        let free_count = (self.begin..self.current).filter(|idx| !self.get_bitmap(*idx)).count();
+        free_count + self.end - self.current
+    }
 }
 
 impl<const N: usize> FrameAllocator for SimpleAllocator<'_, { N }> {
@@ -143,9 +155,24 @@ impl Drop for PhysFrameStub {
 }
 
 pub fn alloc_phys_frame(page_size: usize) -> Option<PhysFrameStub> {
-    PHYS_FRAME_ALLOCATOR.lock().alloc_contiguous_pages(page_size).map(|ppn| {
-        PhysFrameStub::new(PhysPageNum(ppn), page_size)
-    })
+    loop {
+        let ppn = PHYS_FRAME_ALLOCATOR.lock().alloc_contiguous_pages(page_size);
+        if let Some(ppn) = ppn {
+            return Some(PhysFrameStub::new(PhysPageNum(ppn), page_size));
+        }
+        // 物理页不足，换出一个用户页后重试。换出时用 PIO 同步写入一页（8 个扇区），每个扇区都等待磁盘缓存写回，
+        // 可能需要几毫秒；内核代码关中断运行，这期间当前 CPU 不处理时钟中断和 IPI，
+        // 所以由 kswapd 在空闲物理页低于水位时提前换出，尽量避免在这里同步换出
+        if !swap_out_one() {
+            return None;
+        }
+    }
+}
+
+/// 返回内容：物理页的总数和空闲的物理页数
+pub fn phys_frame_usage() -> (usize, usize) {
+    let allocator = PHYS_FRAME_ALLOCATOR.lock();
+    (allocator.total_count(), allocator.free_count())
 }
 
 pub struct VirtFrameStub {
@@ -5,13 +5,20 @@ use crate::config::*;
 use crate::arch::x86::*;
 use crate::mm::*;
 
+/// 开启 PAE 时，从 loader 建立的 32 位分页切换到 PAE 分页
+#[cfg(feature = "pae")]
 pub fn init_kernel_page_table() {
-    let kernel_pdt_pa = PhysAddr(KERNEL_PDT_PHYS_ADDRESS);
+    super::pae::switch_to_pae();
+}
+
+#[cfg(not(feature = "pae"))]
+pub fn init_kernel_page_table() {
+    let kernel_pdt_pa = PhysAddr(LOADER_PDT_PHYS_ADDRESS);
     let kernel_pdt_va = VirtAddr(PDT_VIRT_ADDRESS);
     let kernel_pdt_ppn = kernel_pdt_pa.phys_page_num_floor();
     let kernel_pdt_vpn = kernel_pdt_va.virt_page_num_floor();
     let kernel_pde_array = kernel_pdt_vpn.get_pde_array();
-    let kernel_pet_begin_pa = PhysAddr(KERNEL_PDT_PHYS_ADDRESS + MEMORY_PAGE_SIZE);
+    let kernel_pet_begin_pa = PhysAddr(LOADER_PDT_PHYS_ADDRESS + MEMORY_PAGE_SIZE);
 
     // clean #0 pde
     kernel_pde_array[0] = PageDirectoryEntry::empty();
@@ -44,6 +51,20 @@ pub fn init_kernel_page_table() {
         assert!(kernel_pde_array[1023].flag().contains(PdeFlags::P | PdeFlags::RW));
     }
 
+    if is_pse_supported() {
+        // 直接映射区域改用 4MiB 大页，loader 为这些 pde 预先分配的页表不再使用
+        let cr4 = Cr4::read() | Cr4::PSE;
+        cr4.write();
+        for idx in 0..(KERNEL_DIRECT_MAP_SIZE / LARGE_PAGE_SIZE) {
+            let page_pa = PhysAddr(LARGE_PAGE_SIZE * idx);
+            let entry = PageDirectoryEntry::new(page_pa.0.try_into().unwrap(), PdeFlags::P | PdeFlags::RW | PdeFlags::PS | PdeFlags::G);
+            kernel_pde_array[768 + idx] = entry;
+        }
+        PageTable::refresh();
+        info!("kernel direct map [{:#x}, {:#x}) with 4MiB pages", HIGH_ADDRESS_BASE, HIGH_ADDRESS_BASE + KERNEL_DIRECT_MAP_SIZE);
+        return;
+    }
+
     fn map(vpn: VirtPageNum, ppn: PhysPageNum, isAssert: bool) {
         if isAssert {
             return
@@ -64,7 +85,7 @@ pub fn init_kernel_page_table() {
 
     // map low 1m and kernel space
     let high_ppn_base = VirtAddr(HIGH_ADDRESS_BASE).virt_page_num_floor();
-    for idx in 0..(KERNEL_PDT_PHYS_ADDRESS >> 12) {
+    for idx in 0..(LOADER_PDT_PHYS_ADDRESS >> 12) {
         let vpn = VirtPageNum(high_ppn_base.0 + idx);
         let ppn = PhysPageNum(idx);
         map(vpn, ppn, true);
@@ -103,3 +124,65 @@ pub fn init_kernel_page_table() {
         map(page_vpn, page_ppn, false);
     }
 }
+
+/// 内核镜像按段设置权限：.text 只读，.rodata 只读，其他页可写；开启 NX 时只有 .text 可执行
+/// 设置 CR0.WP 后，内核写只读页也会触发缺页异常
+pub fn protect_kernel_image() {
+    extern "C" {
+        fn stext();
+        fn etext();
+        fn srodata();
+        fn erodata();
+        fn ekernel();
+    }
+    #[cfg(not(feature = "pae"))]
+    let data_flag = PteFlags::P | PteFlags::RW;
+    #[cfg(feature = "pae")]
+    let data_flag = if is_nx_enabled() { PteFlags::P | PteFlags::RW | PteFlags::NX } else { PteFlags::P | PteFlags::RW };
+    let rodata_flag = data_flag - PteFlags::RW;
+    let text_flag = PteFlags::P;
+
+    // 内核镜像所在的大页拆成 4KiB 页
+    let begin_vpn = VirtAddr(HIGH_ADDRESS_BASE).virt_page_num_floor();
+    let end_vpn = VirtAddr(ekernel as usize).virt_page_num_ceil();
+    for pde_offset in 0..=((end_vpn.0 - 1 - begin_vpn.0) / PTE_SIZE_IN_PAGE) {
+        let vpn = VirtPageNum(begin_vpn.0 + pde_offset * PTE_SIZE_IN_PAGE);
+        let pte_page_pa = PhysAddr(KERNEL_PTE_PAGE_PHYS_ADDRESS + pde_offset * MEMORY_PAGE_SIZE);
+        PageTable::static_split_large_page(vpn, pte_page_pa, data_flag);
+    }
+
+    let text_range = VirtAddr(stext as usize).virt_page_num_floor()..VirtAddr(etext as usize).virt_page_num_ceil();
+    let rodata_range = VirtAddr(srodata as usize).virt_page_num_floor()..VirtAddr(erodata as usize).virt_page_num_ceil();
+    for vpn in begin_vpn..end_vpn {
+        let flag = if text_range.contains(&vpn) {
+            text_flag
+        } else if rodata_range.contains(&vpn) {
+            rodata_flag
+        } else {
+            data_flag
+        };
+        PageTable::static_set_pte_flag(vpn, flag);
+    }
+
+    let cr0 = Cr0::read() | Cr0::WP;
+    cr0.write();
+    info!("kernel image W^X: text [{:#x}, {:#x}) rodata [{:#x}, {:#x})", stext as usize, etext as usize, srodata as usize, erodata as usize);
+}
+
+/// 开启 CR4.PGE，内核空间的页设置了 G 位，切换 cr3 时不会被刷新
+/// CPUID.01H:EDX[bit 13] 表示是否支持 PGE
+pub fn enable_global_pages() {
+    let (_, _, _, edx) = crate::drivers::cpuid(1);
+    if edx & (1 << 13) != 0 {
+        let cr4 = Cr4::read() | Cr4::PGE;
+        cr4.write();
+        info!("enable global pages");
+    }
+}
+
+/// CPUID.01H:EDX[bit 3] 表示是否支持 PSE
+#[cfg(not(feature = "pae"))]
+fn is_pse_supported() -> bool {
+    let (_, _, _, edx) = crate::drivers::cpuid(1);
+    edx & (1 << 3) != 0
+}
@@ -0,0 +1,58 @@
+//! 把直接映射区域之外的物理地址（设备寄存器、ACPI 表等）映射到内核虚拟地址空间
+
+use crate::arch::x86::PteFlags;
+use crate::config::MEMORY_PAGE_SIZE;
+
+use super::{alloc_kernel_virt_frame, PageTable, PhysAddr, PhysPageNum, VirtAddr, VirtFrameStub, VirtPageNum};
+
+/// 一段映射好的物理地址，drop 时取消映射并释放虚拟页
+pub struct IoMapping {
+    vstub: VirtFrameStub,
+    /// 物理地址在第一页中的偏移
+    offset: usize,
+    size: usize,
+}
+
+impl IoMapping {
+    pub fn base_address(&self) -> VirtAddr {
+        self.vstub.base_vpn.offset(self.offset)
+    }
+
+    /// 按字节读取映射的内容，用于解析固件中的表
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.base_address().0 as *const u8, self.size) }
+    }
+
+    /// 读取偏移 offset 处的 32 位寄存器
+    pub fn read_u32(&self, offset: usize) -> u32 {
+        let address = self.base_address().0 + offset;
+        unsafe { core::ptr::read_volatile(address as *const u32) }
+    }
+
+    /// 写入偏移 offset 处的 32 位寄存器
+    pub fn write_u32(&self, offset: usize, value: u32) {
+        let address = self.base_address().0 + offset;
+        unsafe { core::ptr::write_volatile(address as *mut u32, value) }
+    }
+}
+
+impl Drop for IoMapping {
+    fn drop(&mut self) {
+        for idx in 0..self.vstub.len {
+            PageTable::static_unmap(VirtPageNum(self.vstub.base_vpn.0 + idx));
+        }
+    }
+}
+
+/// 映射物理地址 [pa, pa + size)，关闭缓存
+/// 内核空间的页表在所有地址空间中共享，映射后在任何进程中都可以访问
+pub fn ioremap(pa: PhysAddr, size: usize) -> Option<IoMapping> {
+    let offset = pa.0 % MEMORY_PAGE_SIZE;
+    let base_ppn = pa.phys_page_num_floor();
+    let page_count = PhysAddr(pa.0 + size).phys_page_num_ceil().0 - base_ppn.0;
+    let vstub = alloc_kernel_virt_frame(page_count)?;
+    for idx in 0..page_count {
+        PageTable::static_map(VirtPageNum(vstub.base_vpn.0 + idx), PhysPageNum(base_ppn.0 + idx), PteFlags::P | PteFlags::RW | PteFlags::PCD | PteFlags::PWT);
+    }
+    Some(IoMapping { vstub, offset, size })
+}
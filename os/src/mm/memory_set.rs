@@ -6,11 +6,15 @@ use bitflags::bitflags;
 use spin::Mutex;
 
 use crate::arch::x86::PteFlags;
-use crate::config::{KERNEL_PDT_PHYS_ADDRESS, MEMORY_PAGE_SIZE};
+use crate::config::*;
 use crate::mm::{alloc_kernel_virt_frame, PhysAddr, VirtAddr};
+#[cfg(feature = "pae")]
+use crate::mm::is_nx_enabled;
 use crate::utils::*;
 
 use super::VirtFrameStub;
+use super::swap::{alloc_swap_slot, read_swap_slot, write_swap_slot, SwapSlotStub};
+use super::shm::ShmStub;
 use super::{alloc_phys_frame, page_table::{self, PageTable}, PhysFrameStub, PhysPageNum, VPNRange, VirtPageNum};
 
 bitflags! {
@@ -31,102 +35,310 @@ impl From<MapPermission> for PteFlags {
         if value.contains(MapPermission::U) {
             ret |= PteFlags::US;
         }
+        #[cfg(feature = "pae")]
+        if !value.contains(MapPermission::X) && is_nx_enabled() {
+            ret |= PteFlags::NX;
+        }
         ret
     }
 }
 
+lazy_static! {
+    /// 全局共享的零页，只读地映射到首次读取的匿名页和 .bss 页
+    static ref ZERO_FRAME: Arc<PhysFrameStub> = {
+        let frame = alloc_phys_frame(1).unwrap();
+        let virt_frame_stub = alloc_kernel_virt_frame(1).unwrap();
+        PageTable::static_map(virt_frame_stub.base_vpn, frame.base_ppn, PteFlags::P | PteFlags::RW);
+        virt_frame_stub.base_vpn.as_byte_array_mut().iter_mut().for_each(|b| *b = 0);
+        PageTable::static_unmap(virt_frame_stub.base_vpn);
+        Arc::new(frame)
+    };
+}
+
+/// 分配物理页时可能换出其他进程的页，需要在创建进程前初始化零页
+pub fn init_zero_frame() {
+    lazy_static::initialize(&ZERO_FRAME);
+}
+
+fn is_zero_frame(frame: &Arc<PhysFrameStub>) -> bool {
+    Arc::ptr_eq(frame, &ZERO_FRAME)
+}
+
 pub struct MapArea {
     pub vpn_range: VPNRange,
     pub map_perm: MapPermission,
     data_frames: BTreeMap<VirtPageNum, Arc<PhysFrameStub>>,
+    /// 被换出的页，fork 后和子进程共享 swap slot
+    swap_slots: BTreeMap<VirtPageNum, Arc<SwapSlotStub>>,
+    /// 共享内存段，fork 时不做写时复制
+    shm_stub: Option<Arc<ShmStub>>,
 }
 
 impl MapArea {
     pub fn new(vpn_range: VPNRange, map_perm: MapPermission) -> Self {
-        Self { vpn_range, map_perm, data_frames: BTreeMap::new()}
+        Self { vpn_range, map_perm, data_frames: BTreeMap::new(), swap_slots: BTreeMap::new(), shm_stub: None }
+    }
+
+    /// 映射共享内存段的区域，物理页在 map_shared 时映射
+    pub fn new_shared(start_vpn: VirtPageNum, map_perm: MapPermission, shm_stub: ShmStub) -> Self {
+        let end_vpn = VirtPageNum(start_vpn.0 + shm_stub.segment.page_count());
+        Self { vpn_range: start_vpn..end_vpn, map_perm, data_frames: BTreeMap::new(), swap_slots: BTreeMap::new(), shm_stub: Some(Arc::new(shm_stub)) }
+    }
+
+    pub fn is_shared(&self) -> bool {
+        self.shm_stub.is_some()
     }
 
     pub fn copy(&self) -> Self {
         let vpn_range = self.vpn_range.clone();
         let map_perm = self.map_perm;
-        MapArea { vpn_range, map_perm, data_frames: self.data_frames.clone() }
+        MapArea { vpn_range, map_perm, data_frames: self.data_frames.clone(), swap_slots: self.swap_slots.clone(), shm_stub: self.shm_stub.clone() }
+    }
+
+    /// 向下扩展区域的起始页，新增的页在下次 map_if_need 时映射
+    pub fn grow_down(&mut self, new_start_vpn: VirtPageNum) {
+        assert!(new_start_vpn < self.vpn_range.start);
+        self.vpn_range.start = new_start_vpn;
     }
 
     /// 映射 vpn 到 ppn，并清理 vpn 页内容
-    /// 返回内容：是否有修复页表
-    pub fn map_if_need(&mut self, page_table: &mut PageTable) -> bool {
+    /// 返回内容：是否有修复页表，None 表示物理页不足，已经映射的页保持不变
+    pub fn map_if_need(&mut self, page_table: &mut PageTable) -> Option<bool> {
         let mut is_modified = false;
         for vpn in self.vpn_range.clone() {
-            if !page_table.is_vpn_present(vpn) {
-                self.map_once(page_table, vpn);
+            if !page_table.is_vpn_present(vpn) && !self.swap_slots.contains_key(&vpn) {
+                self.map_once(page_table, vpn)?;
                 is_modified = true
             }
         }
-        is_modified
+        Some(is_modified)
     }
 
-    /// 取消映射 vpn 到 ppn
-    pub fn unmap(&mut self, page_table: &PageTable) {
+    /// 把共享内存段的物理页映射到区域中
+    /// 返回内容：None 表示物理页不足，已经映射的页保持不变
+    pub fn map_shared(&mut self, page_table: &mut PageTable) -> Option<()> {
+        let segment = self.shm_stub.as_ref().unwrap().segment.clone();
+        for (vpn, frame) in self.vpn_range.clone().zip(segment.frames.iter()) {
+            page_table.map_with_create_pde(vpn, frame.base_ppn, self.map_perm.into())?;
+            self.data_frames.insert(vpn, frame.clone());
+        }
+        self.shm_stub.as_ref().unwrap().mark_attached();
+        Some(())
+    }
+
+    /// 取消映射 vpn 到 ppn；pte page 和 fork 出来的进程共享时先复制一份，不修改对方的页表项，
+    /// 复制时物理页不足则和 release 一样只释放引用
+    pub fn unmap(&mut self, page_table: &mut PageTable) {
+        for vpn in self.vpn_range.clone() {
+            let is_used = page_table.is_vpn_present(vpn) || self.swap_slots.contains_key(&vpn);
+            if is_used && page_table.is_pte_page_shared(vpn) {
+                let _ = page_table.remap_pde_if_need(vpn);
+            }
+        }
+        self.release(page_table);
+    }
+
+    /// 释放区域对物理页和 swap slot 的引用，并清除页表项；
+    /// pte page 和 fork 出来的进程共享时页表项也属于对方，保留页表项。进程退出时页表随后整体释放，不需要复制 pte page
+    pub fn release(&mut self, page_table: &PageTable) {
         for vpn in self.vpn_range.clone() {
             if page_table.is_vpn_present(vpn) {
-                self.unmap_once(page_table, vpn);
+                self.data_frames.remove(&vpn);
+                if !page_table.is_pte_page_shared(vpn) {
+                    page_table.unmap_without_flush(vpn);
+                }
+            } else if self.swap_slots.remove(&vpn).is_some() && !page_table.is_pte_page_shared(vpn) {
+                page_table.clear_swap_entry(vpn);
             }
         }
+        page_table.flush_tlb_range(self.vpn_range.clone());
     }
 
     pub fn change_perm(&self, map_perm: MapPermission, page_table: &PageTable) {
         for vpn in self.vpn_range.clone() {
-            page_table.set_pte_flag(vpn, map_perm.into());
+            // 被换出的页在换入时设置权限
+            if self.swap_slots.contains_key(&vpn) {
+                continue;
+            }
+            page_table.set_pte_flag_without_flush(vpn, map_perm.into());
+        }
+        page_table.flush_tlb_range(self.vpn_range.clone());
+    }
+
+    /// fork 出来的子进程没有复制这个区域，从子进程的页表中清除区域的页表项，物理页和 swap slot 仍然属于当前进程
+    /// 返回内容：None 表示物理页不足，无法复制共享的 pte page
+    pub fn clear_from_forked_page_table(&self, page_table: &mut PageTable) -> Option<()> {
+        for vpn in self.vpn_range.clone() {
+            let is_present = page_table.is_vpn_present(vpn);
+            if !is_present && page_table.get_swap_slot(vpn).is_none() {
+                continue;
+            }
+            // pte page 和当前进程共享，先复制一份
+            page_table.remap_pde_if_need(vpn)?;
+            if is_present {
+                page_table.unmap_without_flush(vpn);
+            } else {
+                page_table.clear_swap_entry(vpn);
+            }
+        }
+        Some(())
+    }
+
+    /// 缺页的 vpn 在 swap 区时，把页换入
+    /// 返回内容：是否有换入页，None 表示物理页不足
+    pub fn swap_in_if_need(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> Option<bool> {
+        if let Some(slot_stub) = self.swap_slots.get(&vpn) {
+            let slot = slot_stub.slot;
+            assert_eq!(page_table.get_swap_slot(vpn), Some(slot));
+            let frame = alloc_phys_frame(1)?;
+            let ppn = frame.base_ppn;
+            page_table.tmp_map(ppn, |tmp_vpn| {
+                read_swap_slot(slot, tmp_vpn.as_byte_array_mut());
+            });
+            // 换入的是私有的新物理页，页表页和子进程共享时需要先复制页表页
+            page_table.remap_pde_if_need(vpn)?;
+            page_table.map(vpn, ppn, self.map_perm.into());
+            self.data_frames.insert(vpn, Arc::new(frame));
+            self.swap_slots.remove(&vpn);
+            Some(true)
+        } else {
+            Some(false)
         }
     }
 
-    pub fn copy_if_need(&mut self, page_table: &mut PageTable) -> bool {
+    /// 时钟算法：从 from_vpn 开始查找可以换出的页，最近访问过的页清除访问位后跳过。
+    /// 写入 swap slot 是同步的 PIO 操作，关中断时当前 CPU 会被阻塞几毫秒，见 alloc_phys_frame
+    /// 返回内容：换出的页
+    pub fn swap_out_one(&mut self, page_table: &mut PageTable, from_vpn: VirtPageNum) -> Option<VirtPageNum> {
+        if !self.map_perm.contains(MapPermission::U) || self.is_shared() {
+            return None;
+        }
+        for vpn in self.vpn_range.clone() {
+            if vpn < from_vpn || !page_table.is_vpn_present(vpn) {
+                continue;
+            }
+            // fork 后和其他进程共享的物理页和页表页不换出
+            let is_frame_shared = self.data_frames.get(&vpn).is_none_or(|frame_stub| Arc::strong_count(frame_stub) > 1);
+            if is_frame_shared || page_table.is_pte_page_shared(vpn) {
+                continue;
+            }
+            let flag = page_table.get_pte_flag(vpn);
+            if flag.contains(PteFlags::A) {
+                page_table.set_pte_flag(vpn, flag - PteFlags::A);
+                continue;
+            }
+            let slot_stub = alloc_swap_slot()?;
+            let frame = self.data_frames.remove(&vpn).unwrap();
+            // 先清除页表项并刷新所有使用这个页表的 CPU 的 TLB，再写入 swap slot，避免其他 CPU 写入的数据丢失；
+            // 写入完成前其他 CPU 访问这个页会缺页，等待进程锁释放后再换入
+            page_table.set_swap_entry(vpn, slot_stub.slot);
+            page_table.tmp_map(frame.base_ppn, |tmp_vpn| {
+                write_swap_slot(slot_stub.slot, tmp_vpn.as_byte_array_ref());
+            });
+            drop(frame);
+            self.swap_slots.insert(vpn, Arc::new(slot_stub));
+            return Some(vpn);
+        }
+        None
+    }
+
+    /// 修复写时复制的页，零页只复制写入的 fault_vpn，其他和 fork 出来的进程共享的页一起复制
+    /// 返回内容：是否有修复页表，None 表示物理页不足
+    pub fn copy_if_need(&mut self, page_table: &mut PageTable, fault_vpn: VirtPageNum) -> Option<bool> {
         let mut is_modified = false;
-        if self.map_perm.contains(MapPermission::W) {
+        if self.map_perm.contains(MapPermission::W) && !self.is_shared() {
             for vpn in self.vpn_range.clone() {
+                if self.swap_slots.contains_key(&vpn) {
+                    continue;
+                }
                 assert!(page_table.is_vpn_present(vpn));
-                if !page_table.is_vpn_writable(vpn) {
-                    let mut is_need_remap = false;
-                    if let Some(frame_stub) = self.data_frames.get(&vpn) {
-                        if Arc::strong_count(frame_stub) == 1 {
-                            page_table.set_pte_flag(vpn, self.map_perm.into());
-                        } else {
-                            is_need_remap = true;
-                        }
-                    } else {
-                        assert!(false);
-                    }
-                    if is_need_remap {
-                        let frame = alloc_phys_frame(1).unwrap();
-                        let ppn = frame.base_ppn;
-                        page_table.tmp_map(ppn, |new_vpn| {
-                            new_vpn.as_byte_array_mut().copy_from_slice(vpn.as_byte_array_ref());
-                        });
-                        page_table.remap_for_fork_process(vpn, ppn, self.map_perm.into());
-                        self.data_frames.insert(vpn, Arc::new(frame));
-                    }
-                    is_modified = true;
+                if vpn != fault_vpn && self.is_zero_page(vpn) {
+                    continue;
                 }
+                is_modified |= self.copy_on_write(page_table, vpn)?;
             }
         }
-        is_modified
+        Some(is_modified)
     }
 
-    fn map_once(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        let frame = alloc_phys_frame(1).unwrap();
+    /// 可写的页表项被设置为只读时，复制共享的物理页或者恢复可写权限
+    /// 返回内容：是否有修复页表，None 表示物理页不足
+    pub fn copy_on_write(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> Option<bool> {
+        if !self.map_perm.contains(MapPermission::W) || self.is_shared() || page_table.is_vpn_writable(vpn) {
+            return Some(false);
+        }
+        let mut is_need_remap = false;
+        if let Some(frame_stub) = self.data_frames.get(&vpn) {
+            if Arc::strong_count(frame_stub) == 1 {
+                page_table.set_pte_flag(vpn, self.map_perm.into());
+            } else {
+                is_need_remap = true;
+            }
+        } else {
+            assert!(false);
+        }
+        if is_need_remap {
+            let frame = alloc_phys_frame(1)?;
+            let ppn = frame.base_ppn;
+            page_table.tmp_map(ppn, |new_vpn| {
+                new_vpn.as_byte_array_mut().copy_from_slice(vpn.as_byte_array_ref());
+            });
+            page_table.remap_for_fork_process(vpn, ppn, self.map_perm.into())?;
+            self.data_frames.insert(vpn, Arc::new(frame));
+        }
+        Some(true)
+    }
+
+    /// 可写的私有用户区域首次访问时映射只读的零页，写入时再通过写时复制分配物理页；其他区域直接分配物理页
+    /// 返回内容：是否有修复页表，None 表示物理页不足，已经映射的页保持不变
+    pub fn map_zero_page_if_need(&mut self, page_table: &mut PageTable) -> Option<bool> {
+        if !self.map_perm.contains(MapPermission::W | MapPermission::U) || self.is_shared() {
+            return self.map_if_need(page_table);
+        }
+        let mut is_modified = false;
+        for vpn in self.vpn_range.clone() {
+            if !page_table.is_vpn_present(vpn) && !self.swap_slots.contains_key(&vpn) {
+                page_table.map_with_create_pde(vpn, ZERO_FRAME.base_ppn, (self.map_perm - MapPermission::W).into())?;
+                self.data_frames.insert(vpn, ZERO_FRAME.clone());
+                is_modified = true;
+            }
+        }
+        Some(is_modified)
+    }
+
+    fn is_zero_page(&self, vpn: VirtPageNum) -> bool {
+        self.data_frames.get(&vpn).is_some_and(is_zero_frame)
+    }
+
+    fn map_once(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> Option<()> {
+        let frame = alloc_phys_frame(1)?;
         let ppn: PhysPageNum = frame.base_ppn;
+        page_table.map_with_create_pde(vpn, ppn, self.map_perm.into())?;
         self.data_frames.insert(vpn, Arc::new(frame));
-        page_table.map_with_create_pde(vpn, ppn, self.map_perm.into());
         assert!(page_table.is_pte_present(vpn));
         page_table.get_mut::<[u8; MEMORY_PAGE_SIZE], _>(vpn, 0, |bytes_array| {
             bytes_array.iter_mut().for_each(|b| * b = 0);
         });
         assert!(page_table.is_pte_present(vpn));
+        Some(())
+    }
+
+    /// 驻留在内存中的页数，映射的零页不计算在内
+    pub fn resident_page_count(&self) -> usize {
+        self.data_frames.values().filter(|frame| !is_zero_frame(frame)).count()
+    }
+
+    /// 被换出到 swap 区的页数
+    pub fn swapped_page_count(&self) -> usize {
+        self.swap_slots.len()
     }
 
-    fn unmap_once(&mut self, page_table: &PageTable, vpn: VirtPageNum) {
-        self.data_frames.remove(&vpn);
-        page_table.unmap(vpn);
+    /// fork 后和其他进程写时复制共享的驻留页数，共享内存段的页不计算在内
+    pub fn cow_shared_page_count(&self) -> usize {
+        if self.is_shared() {
+            return 0;
+        }
+        self.data_frames.values().filter(|frame| Arc::strong_count(frame) > 1 && !is_zero_frame(frame)).count()
     }
 
     /// data: start-aligned but maybe with shorter length
@@ -158,6 +370,76 @@ pub struct ProgramHeader {
     pub flags: xmas_elf::program::Flags,
 }
 
+/// 重定位类型：加载偏移 + 原来的值
+const R_386_RELATIVE: u8 = 8;
+
+/// PIE 加载到 PIE_LOAD_BASE_VIRT_ADDRESS，开启 ASLR 时再随机偏移；普通的可执行文件加载到链接地址
+fn elf_load_bias(elf: &xmas_elf::ElfFile) -> usize {
+    if elf.header.pt2.type_().as_type() == xmas_elf::header::Type::SharedObject {
+        PIE_LOAD_BASE_VIRT_ADDRESS + random_page_offset(ASLR_PIE_RANDOM_PAGE_SIZE)
+    } else {
+        0
+    }
+}
+
+/// 返回内容：[0, page_count) 中随机页数的字节偏移
+#[cfg(feature = "aslr")]
+fn random_page_offset(page_count: usize) -> usize {
+    (crate::drivers::random::random() as usize % page_count) * MEMORY_PAGE_SIZE
+}
+
+/// 没有开启 ASLR，偏移为 0
+#[cfg(not(feature = "aslr"))]
+fn random_page_offset(_page_count: usize) -> usize {
+    0
+}
+
+/// 进程内存使用情况的统计，除特别说明外单位都是页
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct MemoryUsage {
+    /// 驻留在内存中的页数
+    pub resident: usize,
+    /// 被换出到 swap 区的页数
+    pub swapped: usize,
+    /// 写时复制共享的驻留页数
+    pub cow_shared: usize,
+    /// 只读段（代码和只读数据）的驻留页数
+    pub code: usize,
+    /// 可写段的驻留页数，用户程序的堆位于 .bss 中，也计算在这里
+    pub data: usize,
+    /// 所有线程的用户栈的驻留页数
+    pub stack: usize,
+    /// 映射的共享内存段页数
+    pub shm: usize,
+    /// 页表占用的页数
+    pub page_table: usize,
+}
+
+impl MemoryUsage {
+    pub fn add_area(&mut self, area: &MapArea) {
+        let resident = area.resident_page_count();
+        self.resident += resident;
+        self.swapped += area.swapped_page_count();
+        self.cow_shared += area.cow_shared_page_count();
+        if area.is_shared() {
+            self.shm += resident;
+        } else if area.map_perm.contains(MapPermission::W) {
+            self.data += resident;
+        } else {
+            self.code += resident;
+        }
+    }
+
+    pub fn add_stack_area(&mut self, area: &MapArea) {
+        let resident = area.resident_page_count();
+        self.resident += resident;
+        self.swapped += area.swapped_page_count();
+        self.cow_shared += area.cow_shared_page_count();
+        self.stack += resident;
+    }
+}
+
 pub struct MemorySet {
     /// 页表的物理页 stub
     pdt_pstub: PhysFrameStub,
@@ -167,11 +449,21 @@ pub struct MemorySet {
     pub areas: Vec<MapArea>,
     user_stack_base: usize,
     pub program_headers: Option<Vec<ProgramHeader>>,
+    /// 用户栈顶，开启 ASLR 时随机向下偏移
+    pub user_stack_top: usize,
+    /// 共享内存段映射区域的起始地址，开启 ASLR 时随机向上偏移
+    pub shm_base: usize,
+    /// PIE 的加载偏移，普通的可执行文件为 0
+    pub load_bias: usize,
+    /// ELF 文件中的数据是否已经拷贝到用户空间并完成重定位，没有 ELF 文件的地址空间为 true
+    pub is_elf_loaded: bool,
 }
 
 impl Drop for MemorySet {
     fn drop(&mut self) {
-        PageTable::static_unmap(self.pdt_vstub.base_vpn);
+        for idx in 0..PDT_PAGE_SIZE {
+            PageTable::static_unmap(VirtPageNum(self.pdt_vstub.base_vpn.0 + idx));
+        }
     }
 }
 
@@ -184,25 +476,42 @@ impl MemorySet {
             areas: Vec::new(),
             user_stack_base: 0,
             program_headers: None,
+            user_stack_top: USER_STACK_TOP_VIRT_ADDRESS,
+            shm_base: USER_SHM_BEGIN_VIRT_ADDRESS,
+            load_bias: 0,
+            is_elf_loaded: true,
         }
     }
 
-    pub fn new_kernel_memory_set() -> Self {
+    /// 返回内容：None 表示物理页不足
+    pub fn new_kernel_memory_set() -> Option<Self> {
         // 创建 page_table
-        let pdt_pstub = alloc_phys_frame(1).unwrap();
+        let pdt_pstub = alloc_phys_frame(PDT_PAGE_SIZE)?;
         let pdt_ppn = pdt_pstub.base_ppn;
-        let pdt_vstub = alloc_kernel_virt_frame(1).unwrap();
+        let pdt_vstub = alloc_kernel_virt_frame(PDT_PAGE_SIZE)?;
         let pdt_vpn = pdt_vstub.base_vpn;
         let page_table = PageTable::new(pdt_ppn, pdt_vpn);
-        MemorySet { pdt_pstub, pdt_vstub, page_table, areas: Vec::new(), user_stack_base: 0, program_headers: None }
+        Some(MemorySet {
+            pdt_pstub,
+            pdt_vstub,
+            page_table,
+            areas: Vec::new(),
+            user_stack_base: 0,
+            program_headers: None,
+            user_stack_top: USER_STACK_TOP_VIRT_ADDRESS,
+            shm_base: USER_SHM_BEGIN_VIRT_ADDRESS,
+            load_bias: 0,
+            is_elf_loaded: true,
+        })
     }
 
     /// return MemorySet and entry point
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize) {
+    /// 返回内容：None 表示物理页不足
+    pub fn from_elf(elf_data: &[u8]) -> Option<(Self, usize)> {
         // 创建 page_table
-        let pdt_pstub = alloc_phys_frame(1).unwrap();
+        let pdt_pstub = alloc_phys_frame(PDT_PAGE_SIZE)?;
         let pdt_ppn = pdt_pstub.base_ppn;
-        let pdt_vstub = alloc_kernel_virt_frame(1).unwrap();
+        let pdt_vstub = alloc_kernel_virt_frame(PDT_PAGE_SIZE)?;
         let pdt_vpn = pdt_vstub.base_vpn;
         let page_table = PageTable::new(pdt_ppn, pdt_vpn);
 
@@ -213,19 +522,20 @@ impl MemorySet {
         let magic = elf_header.pt1.magic;
         assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
         let ph_count: u16 = elf_header.pt2.ph_count();
+        let load_bias = elf_load_bias(&elf);
         let mut max_end_vpn = VirtPageNum(0);
         for i in 0..ph_count {
             let ph = elf.program_header(i).unwrap();
             if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
                 let program_header = ProgramHeader {
-                    virtual_addr: ph.virtual_addr() as usize,
+                    virtual_addr: ph.virtual_addr() as usize + load_bias,
                     mem_size: ph.mem_size() as usize,
                     file_offset: ph.offset() as usize,
                     file_size: ph.file_size() as usize,
                     flags: ph.flags(),
                 };
                 program_headers.push(program_header);
-                let end_va = VirtAddr((ph.virtual_addr() + ph.mem_size()) as usize);
+                let end_va = VirtAddr((ph.virtual_addr() + ph.mem_size()) as usize + load_bias);
                 max_end_vpn = end_va.virt_page_num_ceil();
             }
         }
@@ -235,9 +545,20 @@ impl MemorySet {
         user_stack_base += MEMORY_PAGE_SIZE;
 
         let areas = Self::generate_map_area(&program_headers);
-        let memory_set = MemorySet { pdt_pstub, pdt_vstub, page_table, areas, user_stack_base: user_stack_base, program_headers: Some(program_headers) };
-
-        (memory_set, elf.header.pt2.entry_point() as usize)
+        let memory_set = MemorySet {
+            pdt_pstub,
+            pdt_vstub,
+            page_table,
+            areas,
+            user_stack_base: user_stack_base,
+            program_headers: Some(program_headers),
+            user_stack_top: USER_STACK_TOP_VIRT_ADDRESS - random_page_offset(ASLR_STACK_RANDOM_PAGE_SIZE),
+            shm_base: USER_SHM_BEGIN_VIRT_ADDRESS + random_page_offset(ASLR_SHM_RANDOM_PAGE_SIZE),
+            load_bias,
+            is_elf_loaded: false,
+        };
+
+        Some((memory_set, elf.header.pt2.entry_point() as usize + load_bias))
     }
 
     pub fn reset_from_elf(&mut self, elf_data: &[u8]) -> usize {
@@ -252,19 +573,20 @@ impl MemorySet {
         let magic = elf_header.pt1.magic;
         assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
         let ph_count: u16 = elf_header.pt2.ph_count();
+        let load_bias = elf_load_bias(&elf);
         let mut max_end_vpn = VirtPageNum(0);
         for i in 0..ph_count {
             let ph = elf.program_header(i).unwrap();
             if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
                 let program_header = ProgramHeader {
-                    virtual_addr: ph.virtual_addr() as usize,
+                    virtual_addr: ph.virtual_addr() as usize + load_bias,
                     mem_size: ph.mem_size() as usize,
                     file_offset: ph.offset() as usize,
                     file_size: ph.file_size() as usize,
                     flags: ph.flags(),
                 };
                 program_headers.push(program_header);
-                let end_va = VirtAddr((ph.virtual_addr() + ph.mem_size()) as usize);
+                let end_va = VirtAddr((ph.virtual_addr() + ph.mem_size()) as usize + load_bias);
                 max_end_vpn = end_va.virt_page_num_ceil();
             }
         }
@@ -277,24 +599,30 @@ impl MemorySet {
         self.areas = areas;
         self.user_stack_base = user_stack_base;
         self.program_headers = Some(program_headers);
+        self.user_stack_top = USER_STACK_TOP_VIRT_ADDRESS - random_page_offset(ASLR_STACK_RANDOM_PAGE_SIZE);
+        self.shm_base = USER_SHM_BEGIN_VIRT_ADDRESS + random_page_offset(ASLR_SHM_RANDOM_PAGE_SIZE);
+        self.load_bias = load_bias;
+        self.is_elf_loaded = false;
 
-        elf.header.pt2.entry_point() as usize
+        elf.header.pt2.entry_point() as usize + load_bias
     }
 
-    pub fn copy(&self) -> Self {
+    /// 返回内容：None 表示物理页不足
+    pub fn copy(&self) -> Option<Self> {
         // 创建 page_table
-        let pdt_pstub = alloc_phys_frame(1).unwrap();
+        let pdt_pstub = alloc_phys_frame(PDT_PAGE_SIZE)?;
         let pdt_ppn = pdt_pstub.base_ppn;
-        let pdt_vstub = alloc_kernel_virt_frame(1).unwrap();
+        let pdt_vstub = alloc_kernel_virt_frame(PDT_PAGE_SIZE)?;
         let pdt_vpn = pdt_vstub.base_vpn;
         let page_table = self.page_table.copy(pdt_ppn, pdt_vpn);
 
-        // 设置当前进程和新进程用户空间的内存只读
+        // 设置当前进程和新进程用户空间的内存只读，共享内存保持可写
+        // pte page 是共享的，通过当前进程的页表修改才能刷新当前的 TLB
         for area in &self.areas {
-            if area.map_perm.contains(MapPermission::W) {
+            if area.map_perm.contains(MapPermission::W) && !area.is_shared() {
                 let mut map_perm: MapPermission = area.map_perm;
                 map_perm.remove(MapPermission::W);
-                area.change_perm(map_perm, &page_table);
+                area.change_perm(map_perm, &self.page_table);
             }
         }
 
@@ -303,13 +631,127 @@ impl MemorySet {
             new_areas.push(area.copy());
         }
 
-        MemorySet { 
+        Some(MemorySet { 
             pdt_pstub, 
             pdt_vstub, 
             page_table, 
             areas: new_areas, 
             user_stack_base: self.user_stack_base, 
             program_headers: self.program_headers.clone(),
+            user_stack_top: self.user_stack_top,
+            shm_base: self.shm_base,
+            load_bias: self.load_bias,
+            is_elf_loaded: self.is_elf_loaded,
+        })
+    }
+
+    /// 返回内容：[start_vpn, end_vpn) 是否没有和已有的区域重叠
+    pub fn is_range_free(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        self.areas.iter().all(|area| area.vpn_range.end <= start_vpn || end_vpn <= area.vpn_range.start)
+    }
+
+    /// 统计地址空间中各区域的内存使用情况，不包括线程的用户栈
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage::default();
+        for area in &self.areas {
+            usage.add_area(area);
+        }
+        usage.page_table = self.page_table.page_table_frames().len();
+        usage
+    }
+
+    /// 写时复制 vpn 所在的页，内核写用户空间之前调用，避免持有进程锁时触发缺页异常
+    /// 返回内容：是否有修复页表，None 表示物理页不足
+    pub fn copy_on_write(&mut self, vpn: VirtPageNum) -> Option<bool> {
+        let page_table = &mut self.page_table;
+        for area in &mut self.areas {
+            if area.vpn_range.contains(&vpn) {
+                return area.copy_on_write(page_table, vpn);
+            }
+        }
+        Some(false)
+    }
+
+    /// 在共享内存区域中查找 page_count 个连续的空闲页
+    pub fn find_free_shm_range(&self, page_count: usize) -> Option<VirtPageNum> {
+        let end_vpn = VirtAddr(USER_SHM_END_VIRT_ADDRESS).virt_page_num_floor();
+        let mut start_vpn = VirtAddr(self.shm_base).virt_page_num_floor();
+        while start_vpn.0 + page_count <= end_vpn.0 {
+            let range_end_vpn = VirtPageNum(start_vpn.0 + page_count);
+            if let Some(area) = self.areas.iter().find(|area| !(area.vpn_range.end <= start_vpn || range_end_vpn <= area.vpn_range.start)) {
+                start_vpn = area.vpn_range.end;
+            } else {
+                return Some(start_vpn);
+            }
+        }
+        None
+    }
+
+    /// 首次缺页时拷贝 ELF 文件中的数据，PIE 还要修正 R_386_RELATIVE 重定位项。
+    /// 先分配所有要写入的物理页再写入，物理页不足时数据保持不变，只读区域恢复权限，之后可以重试
+    /// 返回内容：None 表示物理页不足
+    pub fn load_elf(&mut self, elf_data: &[u8]) -> Option<()> {
+        let relocations = self.relocations(elf_data);
+        // 只读的区域临时设置为可写，内核才能写入数据
+        self.change_readonly_area_perm(MapPermission::W);
+        let result = self.alloc_elf_frames(&relocations);
+        if result.is_some() {
+            for ph in self.program_headers.as_ref().unwrap() {
+                let src = &elf_data[ph.file_offset..(ph.file_offset + ph.file_size)];
+                let dst = unsafe { core::slice::from_raw_parts_mut(ph.virtual_addr as *mut u8, ph.file_size) };
+                dst.copy_from_slice(src);
+            }
+            for &address in &relocations {
+                let value = unsafe { (address as *mut u32).as_mut() }.unwrap();
+                *value += self.load_bias as u32;
+            }
+            self.is_elf_loaded = true;
+        }
+        self.change_readonly_area_perm(MapPermission::empty());
+        result
+    }
+
+    /// PIE 中 R_386_RELATIVE 重定位项要修正的地址，普通的可执行文件没有
+    fn relocations(&self, elf_data: &[u8]) -> Vec<usize> {
+        let mut relocations = Vec::new();
+        if self.load_bias == 0 {
+            return relocations;
+        }
+        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
+        for section in elf.section_iter() {
+            if let Ok(xmas_elf::sections::SectionData::Rel32(rels)) = section.get_data(&elf) {
+                for rel in rels {
+                    if rel.get_type() == R_386_RELATIVE {
+                        relocations.push(rel.get_offset() as usize + self.load_bias);
+                    }
+                }
+            }
+        }
+        relocations
+    }
+
+    /// 为文件中的数据和重定位项所在的页分配物理页，.bss 剩下的部分仍然映射零页
+    /// 返回内容：None 表示物理页不足，已经分配的物理页保留，重试时不再分配
+    fn alloc_elf_frames(&mut self, relocations: &[usize]) -> Option<()> {
+        for ph in self.program_headers.clone().unwrap() {
+            let start_vpn = VirtAddr(ph.virtual_addr).virt_page_num_floor();
+            let end_vpn = VirtAddr(ph.virtual_addr + ph.file_size).virt_page_num_ceil();
+            for vpn in start_vpn.0..end_vpn.0 {
+                self.copy_on_write(VirtPageNum(vpn))?;
+            }
+        }
+        for &address in relocations {
+            self.copy_on_write(VirtAddr(address).virt_page_num_floor())?;
+        }
+        Some(())
+    }
+
+    /// 没有写权限的区域设置为原来的权限加上 extra_perm
+    fn change_readonly_area_perm(&self, extra_perm: MapPermission) {
+        for area in &self.areas {
+            if !area.map_perm.contains(MapPermission::W) {
+                area.change_perm(area.map_perm | extra_perm, &self.page_table);
+            }
         }
     }
 
@@ -3,19 +3,28 @@ pub mod frame_allocator;
 pub mod heap_allocator;
 pub mod page_table;
 pub mod memory_set;
+pub mod swap;
+pub mod shm;
+pub mod ioremap;
 mod init;
+#[cfg(feature = "pae")]
+mod pae;
 mod tss;
 
 use core::{arch::asm, assert};
+use core::sync::atomic::{AtomicUsize, Ordering};
 use crate::config::*;
 use crate::arch::x86::{AddressRangeDescriptorStructure, DescriptorType, GDTRegister, SegmentDescriptor};
+use crate::percpu;
+use crate::smp::percpu;
 
 pub use address::*;
-use alloc::sync::Arc;
+use alloc::boxed::Box;
 pub use frame_allocator::*;
 pub use memory_set::*;
 pub use page_table::*;
-use spin::Mutex;
+#[cfg(feature = "pae")]
+pub use pae::is_nx_enabled;
 
 const ARDS_MAX_COUNT: usize = 25;
 
@@ -98,27 +107,83 @@ lazy_static! {
         let stack_space = (sbss_with_stack as usize, ebss_with_stack as usize);
         MemoryInfo::new(kernel_space, stack_space, &ARDS_ARRAY_REFERENCE)
     };
+}
 
-    pub static ref TSS_MUTEX: Arc<Mutex<()>> = {
-        Arc::new(Mutex::new(()))
-    };
+const EMPTY_TSS: tss::TSS = tss::TSS { last_tss_ptr: 0, esp0: 0, ss0: 0, esp1: 0, ss1: 0, esp2: 0, ss2: 0, cr3: 0, eip: 0, eflags: 0, eax: 0, ecx: 0, edx: 0, ebx: 0, esp: 0, ebp: 0, esi: 0, edi: 0, es: 0, cs: 0, ss: 0, ds: 0, fs: 0, gs: 0, ldt_selector: 0, reserve: 0, io_map_offset: 0 };
+
+percpu! {
+    /// 每个 CPU 一个 TSS，中断时切换到各自正在运行的线程的内核栈
+    static TSS: tss::TSS = EMPTY_TSS;
 }
 
-static mut TSS: tss::TSS = tss::TSS { last_tss_ptr: 0, esp0: 0, ss0: 0, esp1: 0, ss1: 0, esp2: 0, ss2: 0, cr3: 0, eip: 0, eflags: 0, eax: 0, ecx: 0, edx: 0, ebx: 0, esp: 0, ebp: 0, esi: 0, edi: 0, es: 0, cs: 0, ss: 0, ds: 0, fs: 0, gs: 0, ldt_selector: 0, reserve: 0, io_map_offset: 0 };
+/// AP 的 GDT 的虚拟地址，BSP 使用 loader 设置的 GDT
+static CPU_GDT_ADDRESS: [AtomicUsize; MAX_CPU_COUNT] = [const { AtomicUsize::new(0) }; MAX_CPU_COUNT];
 
+#[repr(C, align(8))]
+struct Gdt([SegmentDescriptor; GDT_SIZE]);
+
+/// 设置当前 CPU 的 TSS 中的内核栈
 pub fn update_tss(ss0: usize, esp0: usize) {
-    let lock: spin::MutexGuard<()> = TSS_MUTEX.lock();
+    TSS.with_mut(|tss| {
+        tss.ss0 = ss0;
+        tss.esp0 = esp0;
+    });
+}
+
+fn tss_descriptor(cpu: usize) -> SegmentDescriptor {
+    let tss_address = TSS.as_ptr_on(cpu) as u32;
+    SegmentDescriptor::new(tss_address, core::mem::size_of::<tss::TSS>().try_into().unwrap(), false, DescriptorType::from_bits(9).unwrap(), false, 0, true, false, false, false)
+}
+
+/// 为 cpu 创建 per-CPU 数据区，返回指向数据区的数据段描述符
+fn percpu_descriptor(cpu: usize) -> SegmentDescriptor {
+    let (address, size) = percpu::init_area(cpu);
+    SegmentDescriptor::new(address as u32, (size - 1) as u32, false, DescriptorType::R_W, true, 0, true, false, false, true)
+}
+
+/// 为 AP 创建 GDT，复制 BSP 的代码段和数据段描述符，TSS 描述符位于 TSS_GDT_INDEX + cpu，
+/// 通过 str 指令读出的 TSS 选择子就可以知道当前 CPU 的编号；同时创建 AP 的 per-CPU 数据区
+pub fn init_cpu_gdt(cpu: usize) {
+    assert!(0 < cpu && cpu < MAX_CPU_COUNT);
+    let bsp_gdt = unsafe {
+        core::slice::from_raw_parts(0xc0090000usize as *const SegmentDescriptor, GDT_SIZE)
+    };
+    let mut gdt = Box::new(Gdt([SegmentDescriptor::empty(); GDT_SIZE]));
+    let tss_index = TSS_GDT_INDEX as usize;
+    gdt.0[..tss_index].copy_from_slice(&bsp_gdt[..tss_index]);
+    gdt.0[PERCPU_GDT_INDEX as usize] = percpu_descriptor(cpu);
+    gdt.0[tss_index + cpu] = tss_descriptor(cpu);
+    CPU_GDT_ADDRESS[cpu].store(Box::leak(gdt) as *const Gdt as usize, Ordering::Release);
+}
+
+/// AP 启动后加载自己的 GDT 和 TSS
+pub fn load_cpu_gdt(cpu: usize) {
+    let gdt_address = CPU_GDT_ADDRESS[cpu].load(Ordering::Acquire);
+    assert_ne!(gdt_address, 0);
+    let gdtr = GDTRegister::new((GDT_SIZE * 8 - 1) as u16, gdt_address as u32);
+    let tss_selector = (TSS_GDT_INDEX + cpu as u16) << 3;
     unsafe {
-        TSS.ss0 = ss0;
-        TSS.esp0 = esp0;
+        asm!("lgdt [{}]", in(reg) &gdtr as *const _ as usize);
+        asm!(
+            "mov ds, ax",
+            "mov es, ax",
+            "mov fs, ax",
+            "mov ss, ax",
+            in("ax") DATA_SELECTOR,
+        );
+        asm!("mov gs, ax", in("ax") PERCPU_SELECTOR);
+        asm!("ltr ax", in("ax") tss_selector);
     }
-    drop(lock);
+    TSS.with_mut(|tss| tss.ss0 = DATA_SELECTOR as usize);
 }
 
 pub fn init() {
     memory_info();
     init::init_kernel_page_table();
+    init::protect_kernel_image();
+    init::enable_global_pages();
     heap_allocator::init();
+    memory_set::init_zero_frame();
     // let _ = KERNEL_MEMORY_SET.lock();
 
     // 设置用户态的全局描述符表表项
@@ -134,11 +199,16 @@ pub fn init() {
     gdt[3] = SegmentDescriptor::new(0, u32::MAX, true, DescriptorType::X, true, 0b11, true, false, false, true);
     gdt[4] = SegmentDescriptor::new(0, u32::MAX, true, DescriptorType::R_W, true, 0b11, true, false, false, true);
     
+    // 设置 BSP 的 per-CPU 数据区，之后才能访问 percpu! 定义的变量
+    gdt[PERCPU_GDT_INDEX as usize] = percpu_descriptor(0);
+    unsafe {
+        asm!("mov gs, ax", in("ax") PERCPU_SELECTOR);
+    }
+
     // 设置 tss
     {
         update_tss(DATA_SELECTOR as usize, KERNEL_ORIGIN_STACK_TOP_VIRT_ADDRESS);
-        let tss_reference = unsafe { &TSS };
-        gdt[5] = SegmentDescriptor::new(tss_reference as *const _ as u32, core::mem::size_of::<tss::TSS>().try_into().unwrap(), false, DescriptorType::from_bits(9).unwrap(), false, 0, true, false, false, false);
+        gdt[TSS_GDT_INDEX as usize] = tss_descriptor(0);
     }
 
     let gdtr = GDTRegister::new(511, 0xc0090000);
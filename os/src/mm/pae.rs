@@ -0,0 +1,131 @@
+//! 从 loader 建立的 32 位分页切换到 PAE 分页，并在 CPU 支持时开启 NX
+use core::arch::asm;
+
+use crate::config::*;
+use crate::arch::x86::*;
+use crate::mm::*;
+
+/// loader 建立的 32 位页目录的虚拟地址（自映射）
+const LOADER_PDT_VIRT_ADDRESS: usize = 0xfffff000;
+/// 32 位分页下 PSE 大页的大小
+const PSE_PAGE_SIZE: usize = 0x400000;
+
+lazy_static! {
+    /// CPUID.80000001H:EDX[bit 20] 表示是否支持 NX
+    static ref NX_SUPPORTED: bool = {
+        let (max_extended_leaf, _, _, _) = crate::drivers::cpuid(0x80000000);
+        if max_extended_leaf < 0x80000001 {
+            false
+        } else {
+            let (_, _, _, edx) = crate::drivers::cpuid(0x80000001);
+            edx & (1 << 20) != 0
+        }
+    };
+}
+
+/// 是否可以在页表项中设置 NX 位
+pub fn is_nx_enabled() -> bool {
+    *NX_SUPPORTED
+}
+
+pub fn switch_to_pae() {
+    // CPUID.01H:EDX[bit 3] PSE，[bit 6] PAE
+    let (_, _, _, edx) = crate::drivers::cpuid(1);
+    assert!(edx & (1 << 3) != 0, "cpu does not support PSE");
+    assert!(edx & (1 << 6) != 0, "cpu does not support PAE");
+
+    // 32 位分页下用 4MiB 大页映射直接映射区域，PAE 页表所在的物理内存 loader 没有映射
+    // 切换分页模式时代码在低地址执行，同时建立临时的恒等映射
+    let loader_pde_array = unsafe {
+        core::slice::from_raw_parts_mut(LOADER_PDT_VIRT_ADDRESS as *mut u32, 1024)
+    };
+    let cr4 = Cr4::read() | Cr4::PSE;
+    cr4.write();
+    let pse_flag = (PdeFlags::P | PdeFlags::RW | PdeFlags::PS).bits() as u32;
+    for idx in 0..((KERNEL_DIRECT_MAP_SIZE + PSE_PAGE_SIZE - 1) / PSE_PAGE_SIZE) {
+        let entry = (idx * PSE_PAGE_SIZE) as u32 | pse_flag;
+        loader_pde_array[(HIGH_ADDRESS_BASE / PSE_PAGE_SIZE) + idx] = entry;
+        loader_pde_array[idx] = entry;
+    }
+    unsafe {
+        asm!("mov cr3, {}", in(reg) LOADER_PDT_PHYS_ADDRESS);
+    }
+
+    // 建立 PAE 页表：4 个页目录、PDPT，之后是内核空间的页表
+    let pdt_pa = KERNEL_PDT_PHYS_ADDRESS;
+    let pdpt_pa = pdt_pa + PDT_DIRECTORY_PAGE_SIZE * MEMORY_PAGE_SIZE;
+    let page_table_data = unsafe {
+        core::slice::from_raw_parts_mut((HIGH_ADDRESS_BASE + pdt_pa) as *mut u8, KERNEL_PAGE_TABLE_DATA_SIZE)
+    };
+    page_table_data.iter_mut().for_each(|b| *b = 0);
+    let pde_array = unsafe {
+        core::slice::from_raw_parts_mut((HIGH_ADDRESS_BASE + pdt_pa) as *mut PageDirectoryEntry, PDE_COUNT)
+    };
+    let large_page_count = KERNEL_DIRECT_MAP_SIZE / LARGE_PAGE_SIZE;
+    for idx in KERNEL_PDE_BEGIN_INDEX..PDT_RECURSIVE_PDE_INDEX {
+        let offset = idx - KERNEL_PDE_BEGIN_INDEX;
+        if offset < large_page_count {
+            // 直接映射区域使用 2MiB 大页，临时的恒等映射不是全局页
+            let page_pa = (offset * LARGE_PAGE_SIZE) as u64;
+            pde_array[idx] = PageDirectoryEntry::new(page_pa, PdeFlags::P | PdeFlags::RW | PdeFlags::PS | PdeFlags::G);
+            pde_array[offset] = PageDirectoryEntry::new(page_pa, PdeFlags::P | PdeFlags::RW | PdeFlags::PS);
+        } else {
+            // 内核空间的页表预先分配，所有进程共享
+            let page_pa = KERNEL_PTE_PAGE_PHYS_ADDRESS + offset * MEMORY_PAGE_SIZE;
+            assert!(page_pa < pdt_pa + KERNEL_PAGE_TABLE_DATA_SIZE);
+            pde_array[idx] = PageDirectoryEntry::new(page_pa as u64, PdeFlags::P | PdeFlags::RW);
+        }
+    }
+    for idx in 0..PDT_DIRECTORY_PAGE_SIZE {
+        let page_pa = pdt_pa + idx * MEMORY_PAGE_SIZE;
+        pde_array[PDT_RECURSIVE_PDE_INDEX + idx] = PageDirectoryEntry::new(page_pa as u64, PdeFlags::P | PdeFlags::RW);
+    }
+    let pdpt_array = unsafe {
+        core::slice::from_raw_parts_mut((HIGH_ADDRESS_BASE + pdpt_pa) as *mut PageDirectoryPointerTableEntry, PDT_DIRECTORY_PAGE_SIZE)
+    };
+    for idx in 0..PDT_DIRECTORY_PAGE_SIZE {
+        pdpt_array[idx] = PageDirectoryPointerTableEntry::new((pdt_pa + idx * MEMORY_PAGE_SIZE) as u64);
+    }
+
+    // 跳到恒等映射的低地址，关闭分页后设置 cr3 和 CR4.PAE，再开启分页跳回高地址
+    unsafe {
+        asm!(
+            "lea {tmp}, [2f]",
+            "sub {tmp}, {high_address_base}",
+            "jmp {tmp}",
+            "2:",
+            "mov {tmp}, cr0",
+            "and {tmp}, 0x7fffffff",
+            "mov cr0, {tmp}",
+            "mov cr3, {pdpt}",
+            "mov {tmp}, cr4",
+            "or {tmp}, {cr4_pae}",
+            "mov cr4, {tmp}",
+            "mov {tmp}, cr0",
+            "or {tmp}, 0x80000000",
+            "mov cr0, {tmp}",
+            "lea {tmp}, [3f]",
+            "jmp {tmp}",
+            "3:",
+            tmp = out(reg) _,
+            high_address_base = in(reg) HIGH_ADDRESS_BASE,
+            pdpt = in(reg) pdpt_pa,
+            cr4_pae = in(reg) Cr4::PAE.bits(),
+        );
+    }
+
+    // 删除临时的恒等映射
+    let pde_array = PageTable::pdt_vpn().get_pde_array();
+    for idx in 0..large_page_count {
+        pde_array[idx] = PageDirectoryEntry::empty();
+    }
+    PageTable::refresh();
+    assert_eq!(PageTable::pdt_ppn(), PhysAddr(KERNEL_PDT_PHYS_ADDRESS).phys_page_num_floor());
+    info!("switch to PAE paging, kernel direct map [{:#x}, {:#x}) with 2MiB pages", HIGH_ADDRESS_BASE, HIGH_ADDRESS_BASE + KERNEL_DIRECT_MAP_SIZE);
+
+    if is_nx_enabled() {
+        let efer = Efer::read() | Efer::NXE;
+        efer.write();
+        info!("enable NX");
+    }
+}
@@ -11,7 +11,10 @@ use spin::Mutex;
 use crate::config::*;
 use crate::mm::*;
 use crate::process::KERNEL_PROCESS;
-use crate::{arch::x86::{PageDirectoryEntry, PageTableEntry, PdeFlags, PteFlags}, config::PTE_SIZE_IN_PAGE};
+use crate::smp::flush_tlb_others;
+use crate::{arch::x86::{invlpg, Cr4, PageDirectoryEntry, PageTableEntry, PdeFlags, PteFlags}, config::PTE_SIZE_IN_PAGE};
+#[cfg(feature = "pae")]
+use crate::arch::x86::PageDirectoryPointerTableEntry;
 
 pub struct PageTable {
     pub pdt_ppn: PhysPageNum,
@@ -21,14 +24,13 @@ pub struct PageTable {
 }
 
 impl PageTable {
+    /// pdt_ppn 和 pdt_vpn 是连续的 PDT_PAGE_SIZE 页
     pub fn new(pdt_ppn: PhysPageNum, pdt_vpn: VirtPageNum) -> Self {
         let ret = Self { pdt_ppn, pdt_vpn, frames: BTreeMap::new() };
-        Self::static_map(pdt_vpn, pdt_ppn, PteFlags::P | PteFlags::RW);
-        pdt_vpn.get_bytes_array().iter_mut().for_each(|b| *b = 0);
+        Self::static_map_pdt(pdt_vpn, pdt_ppn);
+        pdt_vpn.get_pde_array().iter_mut().for_each(|pde| *pde = PageDirectoryEntry::empty());
         ret.copy_kernel_space();
-        let pde_array = pdt_vpn.get_pde_array();
-        let pde = &mut pde_array[1023];
-        pde.set_address(pdt_ppn.base_address().0.try_into().unwrap());
+        ret.set_recursive_pde();
         ret
     }
 
@@ -38,15 +40,72 @@ impl PageTable {
 
     pub fn copy(&self, pdt_ppn: PhysPageNum, pdt_vpn: VirtPageNum) -> Self {
         let ret = Self { pdt_ppn, pdt_vpn, frames: self.frames.clone() };
-        Self::static_map(pdt_vpn, pdt_ppn, PteFlags::P | PteFlags::RW);
-        let src = self.pdt_vpn.as_byte_array_ref();
-        let dst = pdt_vpn.as_byte_array_mut();
+        Self::static_map_pdt(pdt_vpn, pdt_ppn);
+        let src = self.pdt_vpn.get_pde_array();
+        let dst = pdt_vpn.get_pde_array();
         dst.copy_from_slice(src);
-        let pde_array = pdt_vpn.as_pde_array_mut();
-        let pde = &mut pde_array[1023];
-        pde.set_address(pdt_ppn.base_address().0.try_into().unwrap());
+        ret.set_recursive_pde();
         ret
     }
+
+    fn static_map_pdt(pdt_vpn: VirtPageNum, pdt_ppn: PhysPageNum) {
+        for idx in 0..PDT_PAGE_SIZE {
+            Self::static_map(VirtPageNum(pdt_vpn.0 + idx), PhysPageNum(pdt_ppn.0 + idx), PteFlags::P | PteFlags::RW);
+        }
+    }
+
+    /// 页目录的最后几项指向页目录自身；PAE 分页时还要填写 PDPT
+    fn set_recursive_pde(&self) {
+        let pde_array = self.pdt_vpn.get_pde_array();
+        for idx in 0..PDT_DIRECTORY_PAGE_SIZE {
+            let pde = &mut pde_array[PDT_RECURSIVE_PDE_INDEX + idx];
+            pde.set_address(PhysPageNum(self.pdt_ppn.0 + idx).base_address().0.try_into().unwrap());
+        }
+        #[cfg(feature = "pae")]
+        {
+            let pdpt_vpn = VirtPageNum(self.pdt_vpn.0 + PDT_DIRECTORY_PAGE_SIZE);
+            let pdpt_array = unsafe {
+                core::slice::from_raw_parts_mut(pdpt_vpn.base_address().0 as *mut PageDirectoryPointerTableEntry, PTE_SIZE_IN_PAGE)
+            };
+            pdpt_array.iter_mut().for_each(|pdpte| *pdpte = PageDirectoryPointerTableEntry(0));
+            for idx in 0..PDT_DIRECTORY_PAGE_SIZE {
+                pdpt_array[idx] = PageDirectoryPointerTableEntry::new(PhysPageNum(self.pdt_ppn.0 + idx).base_address().0.try_into().unwrap());
+            }
+        }
+    }
+
+    /// 切换到该页表时写入 cr3 的物理地址，PAE 分页时是 PDPT 的地址
+    pub fn cr3_address(&self) -> usize {
+        Self::pdt_cr3_address(self.pdt_ppn)
+    }
+
+    fn pdt_cr3_address(pdt_ppn: PhysPageNum) -> usize {
+        PhysPageNum(pdt_ppn.0 + PDT_PAGE_SIZE - 1).base_address().0
+    }
+}
+
+/// 内核空间的页表所有进程共享，设置为全局页，切换 cr3 时不会被刷新
+fn with_global_flag(vpn: VirtPageNum, flag: PteFlags) -> PteFlags {
+    if vpn.base_address().0 >= HIGH_ADDRESS_BASE {
+        flag | PteFlags::G
+    } else {
+        flag
+    }
+}
+
+/// vpn 在页目录中的下标
+fn pde_index(vpn: VirtPageNum) -> usize {
+    (vpn.0 >> PDE_INDEX_SHIFT) & (PDE_COUNT - 1)
+}
+
+/// vpn 在页表中的下标
+fn pte_index(vpn: VirtPageNum) -> usize {
+    vpn.0 & (PTE_SIZE_IN_PAGE - 1)
+}
+
+/// 通过自映射访问 pde_index 对应页表页的虚拟页
+fn pte_page_vpn(pde_index: usize) -> VirtPageNum {
+    VirtAddr(PTE_PAGE_VIRT_ADDRESS | pde_index << 12).into()
 }
 
 impl PageTable {
@@ -56,12 +115,12 @@ impl PageTable {
 
     pub fn pdt_ppn() -> PhysPageNum {
         let pde_array = Self::pdt_vpn().get_pde_array();
-        PhysAddr(pde_array[0x3ff].address().try_into().unwrap()).into()
+        PhysAddr(pde_array[PDT_RECURSIVE_PDE_INDEX].address().try_into().unwrap()).into()
     }
 
     pub fn refresh() {
         unsafe {
-            asm!("mov cr3, {}", in(reg) Self::pdt_ppn().base_address().0);
+            asm!("mov cr3, {}", in(reg) Self::pdt_cr3_address(Self::pdt_ppn()));
         }
     }
 
@@ -70,69 +129,134 @@ impl PageTable {
         let kernel_memory_set = &kernel_proces_inner.memory_set;
         let src = kernel_memory_set.page_table.pdt_vpn.get_pde_array();
         let dst = self.pdt_vpn.get_pde_array();
-        for idx in 768..=1023 {
-            dst[idx] = src[idx];
-        }
+        dst[KERNEL_PDE_BEGIN_INDEX..PDE_COUNT].copy_from_slice(&src[KERNEL_PDE_BEGIN_INDEX..PDE_COUNT]);
     }
 
     pub fn static_map(vpn: VirtPageNum, ppn: PhysPageNum, flag: PteFlags) {
-        let index2 = vpn.0 & 0x3ff;
-        let index1 = (vpn.0 >> 10)& 0x3ff;
+        let index2 = pte_index(vpn);
+        let index1 = pde_index(vpn);
         let pdt_vpn = Self::pdt_vpn();
-        let pde_array = pdt_vpn.as_pde_array_ref();
+        let pde_array = pdt_vpn.get_pde_array();
         let pde = &pde_array[index1];
         assert!(pde.flag().contains(PdeFlags::P));
-        let address = 0x3ffusize << 22 | index1 << 12;
-        let second_vpn: VirtPageNum = VirtAddr(address).into();
+        assert!(!pde.is_large_page());
+        let second_vpn = pte_page_vpn(index1);
         let pte_array = second_vpn.get_pte_array();
         let pte = &mut pte_array[index2];
         assert_eq!(pte.flag().contains(PteFlags::P), false);
-        let new_entry = PageTableEntry::new(ppn.base_address().0.try_into().unwrap(), flag);
+        let new_entry = PageTableEntry::new(ppn.base_address().0.try_into().unwrap(), with_global_flag(vpn, flag));
         *pte = new_entry;
-        PageTable::refresh();
+        invlpg(vpn.base_address().0);
     }
 
     pub fn static_unmap(vpn: VirtPageNum) {
-        let index2 = vpn.0 & 0x3ff;
-        let index1 = (vpn.0 >> 10)& 0x3ff;
+        let index2 = pte_index(vpn);
+        let index1 = pde_index(vpn);
         let pdt_vpn = Self::pdt_vpn();
-        let pde_array = pdt_vpn.as_pde_array_ref();
+        let pde_array = pdt_vpn.get_pde_array();
         let pde = &pde_array[index1];
         assert!(pde.flag().contains(PdeFlags::P));
-        let address = 0x3ffusize << 22 | index1 << 12;
-        let second_vpn: VirtPageNum = VirtAddr(address).into();
-        let pte_array = second_vpn.as_pte_array_mut();
+        assert!(!pde.is_large_page());
+        let second_vpn = pte_page_vpn(index1);
+        let pte_array = second_vpn.get_pte_array();
         let pte = &mut pte_array[index2];
         assert!(pte.flag().contains(PteFlags::P));
         *pte = PageTableEntry::empty();
-        PageTable::refresh();
+        invlpg(vpn.base_address().0);
+        flush_tlb_others(None);
+    }
+
+    /// 修改当前页表中 vpn 的权限
+    pub fn static_set_pte_flag(vpn: VirtPageNum, flag: PteFlags) {
+        let index2 = pte_index(vpn);
+        let index1 = pde_index(vpn);
+        let pde = &Self::pdt_vpn().get_pde_array()[index1];
+        assert!(pde.flag().contains(PdeFlags::P));
+        assert!(!pde.is_large_page());
+        let pte = &mut pte_page_vpn(index1).get_pte_array()[index2];
+        assert!(pte.flag().contains(PteFlags::P));
+        pte.set_flag(with_global_flag(vpn, flag));
+        invlpg(vpn.base_address().0);
+        flush_tlb_others(None);
+    }
+
+    /// 当前页表中 vpn 所在的页目录项是大页时，拆成 4KiB 页，不是大页时不做处理
+    /// pte_page_pa 是直接映射区域中的空闲页，拆分后的页使用 flag 权限
+    pub fn static_split_large_page(vpn: VirtPageNum, pte_page_pa: PhysAddr, flag: PteFlags) {
+        let index1 = pde_index(vpn);
+        let pde = &mut Self::pdt_vpn().get_pde_array()[index1];
+        if !pde.is_large_page() {
+            return;
+        }
+        assert!(pte_page_pa.0 < KERNEL_DIRECT_MAP_SIZE);
+        let large_ppn: PhysPageNum = PhysAddr(pde.address() as usize).into();
+        // 新页表先通过直接映射填写，再替换页目录项，替换前后映射的物理页不变
+        let pte_array = unsafe {
+            core::slice::from_raw_parts_mut((HIGH_ADDRESS_BASE + pte_page_pa.0) as *mut PageTableEntry, PTE_SIZE_IN_PAGE)
+        };
+        for (idx, pte) in pte_array.iter_mut().enumerate() {
+            let page_vpn = VirtPageNum(vpn.0 - pte_index(vpn) + idx);
+            *pte = PageTableEntry::new(PhysPageNum(large_ppn.0 + idx).base_address().0.try_into().unwrap(), with_global_flag(page_vpn, flag));
+        }
+        *pde = PageDirectoryEntry::new(pte_page_pa.0.try_into().unwrap(), PdeFlags::P | PdeFlags::RW);
+        PageTable::flush_tlb_all();
     }
 
     pub fn map(&self, vpn: VirtPageNum, ppn: PhysPageNum, flag: PteFlags) {
         assert!(self.is_pde_present(vpn));
         self.get_pte_mut(vpn, |pte| {
             assert!(!pte.flag().contains(PteFlags::P), "vpn {:#x} exist pte address {:#x}", vpn.base_address().0, pte.address());
-            let new_entry = PageTableEntry::new(ppn.base_address().0.try_into().unwrap(), flag);
+            let new_entry = PageTableEntry::new(ppn.base_address().0.try_into().unwrap(), with_global_flag(vpn, flag));
             *pte = new_entry;
         });
-        PageTable::refresh();
+        self.flush_tlb(vpn);
         assert!(self.is_pte_present(vpn));
         assert_eq!(ppn, self.get_ppn(vpn));
     }
 
-    pub fn map_with_create_pde(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flag: PteFlags) {
+    /// 用 4MiB 大页映射 vpn 到 ppn，vpn 和 ppn 都需要按大页对齐
+    pub fn map_large(&self, vpn: VirtPageNum, ppn: PhysPageNum, flag: PdeFlags) {
+        assert_eq!(vpn.0 % LARGE_PAGE_PAGE_SIZE, 0);
+        assert_eq!(ppn.0 % LARGE_PAGE_PAGE_SIZE, 0);
+        assert!(!self.is_pde_present(vpn), "vpn {:#x} exist pde", vpn.base_address().0);
+        self.get_pde_mut(vpn, |pde| {
+            *pde = PageDirectoryEntry::new(ppn.base_address().0.try_into().unwrap(), flag | PdeFlags::P | PdeFlags::PS);
+        });
+        self.flush_tlb(vpn);
+        assert!(self.is_large_page(vpn));
+    }
+
+    pub fn unmap_large(&self, vpn: VirtPageNum) {
+        assert_eq!(vpn.0 % LARGE_PAGE_PAGE_SIZE, 0);
+        assert!(self.is_large_page(vpn));
+        self.get_pde_mut(vpn, |pde| {
+            *pde = PageDirectoryEntry::empty();
+        });
+        self.flush_tlb(vpn);
+    }
+
+    /// vpn 是否在 4MiB 大页中
+    pub fn is_large_page(&self, vpn: VirtPageNum) -> bool {
+        let mut is_large_page = false;
+        self.get_pde_ref(vpn, |pde| {
+            is_large_page = pde.is_large_page();
+        });
+        is_large_page
+    }
+
+    /// 返回内容：None 表示没有物理页创建 pte page
+    pub fn map_with_create_pde(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flag: PteFlags) -> Option<()> {
         if self.is_pde_present(vpn) {
             self.map(vpn, ppn, flag);
         } else {
-            let frame = alloc_phys_frame(1).unwrap();
+            let frame = alloc_phys_frame(1)?;
             let frame_ppn = frame.base_ppn;
             let mut pde_flags = PdeFlags::P | PdeFlags::RW;
             if vpn.base_address().0 < HIGH_ADDRESS_BASE {
                 pde_flags |= PdeFlags::US;
             }
             let new_entry = PageDirectoryEntry::new(frame_ppn.base_address().0.try_into().unwrap(), pde_flags);
-            let pde_index = (vpn.0 >> 10) & 0x3ff;
-            self.frames.insert(pde_index, Arc::new(frame));
+            self.frames.insert(pde_index(vpn), Arc::new(frame));
             self.tmp_map(frame_ppn, |vpn| {
                 let bytes_array = unsafe {
                     core::slice::from_raw_parts_mut(vpn.base_address().0 as *mut u8, MEMORY_PAGE_SIZE)
@@ -144,32 +268,35 @@ impl PageTable {
             });
             self.map(vpn, ppn, flag);
         }
+        Some(())
     }
 
-    pub fn remap_for_fork_process(&mut self, vpn: VPN, ppn: PhysPageNum, flag: PteFlags) {
+    pub fn remap_for_fork_process(&mut self, vpn: VPN, ppn: PhysPageNum, flag: PteFlags) -> Option<()> {
         assert!(self.is_pde_present(vpn));
-        self.remap_pde_if_need(vpn);
+        self.remap_pde_if_need(vpn)?;
         self.get_pte_mut(vpn, |pte| {
             let new_entry = PageTableEntry::new(ppn.base_address().0.try_into().unwrap(), flag);
             *pte = new_entry;
         });
+        self.flush_tlb(vpn);
         assert!(self.is_pte_present(vpn));
         assert_eq!(ppn, self.get_ppn(vpn));
+        Some(())
     }
 
-    pub fn remap_pde_if_need(&mut self, vpn: VPN) {
-        let pde_index = (vpn.0 >> 10) & 0x3ff;
+    /// 返回内容：None 表示没有物理页复制共享的 pte page
+    pub fn remap_pde_if_need(&mut self, vpn: VPN) -> Option<()> {
+        let pde_index = pde_index(vpn);
         if let Some(frame_ref) = self.frames.get(&pde_index) {
             if Arc::strong_count(frame_ref) > 1 {
-                let frame = alloc_phys_frame(1).unwrap();
+                let frame = alloc_phys_frame(1)?;
                 let frame_ppn = frame.base_ppn;
                 let mut pde_flags = PdeFlags::P | PdeFlags::RW;
                 if vpn.base_address().0 < HIGH_ADDRESS_BASE {
                     pde_flags |= PdeFlags::US;
                 }
                 let new_entry = PageDirectoryEntry::new(frame_ppn.base_address().0.try_into().unwrap(), pde_flags);
-                let pde_frame_vaddress = 0x3ffusize << 22 | pde_index << 12;
-                let pde_frame_vpn = VirtAddr(pde_frame_vaddress).virt_page_num_floor();
+                let pde_frame_vpn = pte_page_vpn(pde_index);
                 self.tmp_map(frame_ppn, |vpn| {
                     let src = pde_frame_vpn.as_byte_array_ref();
                     let dst = vpn.as_byte_array_mut();
@@ -183,31 +310,145 @@ impl PageTable {
         } else {
             assert!(false);
         }
+        Some(())
     }
 
     pub fn unmap(&self, vpn: VirtPageNum) {
+        self.unmap_without_flush(vpn);
+        self.flush_tlb(vpn);
+    }
+
+    /// 批量解除映射时使用，调用者最后用 flush_tlb_range 刷新 TLB
+    pub fn unmap_without_flush(&self, vpn: VirtPageNum) {
         assert_ne!(vpn, self.pdt_vpn, "can not unmap pdt vpn");
         assert!(self.is_pte_present(vpn));
         self.get_pte_mut(vpn, |pte| {
             *pte = PageTableEntry::empty();
         });
-        PageTable::refresh();
         self.get_pte_ref(vpn, |pte| {
             assert!(!pte.flag().contains(PteFlags::P));
         });
     }
 
     pub fn set_pte_flag(&self, vpn: VirtPageNum, flag: PteFlags) {
+        self.set_pte_flag_without_flush(vpn, flag);
+        self.flush_tlb(vpn);
+    }
+
+    /// 批量修改权限时使用，调用者最后用 flush_tlb_range 刷新 TLB
+    pub fn set_pte_flag_without_flush(&self, vpn: VirtPageNum, flag: PteFlags) {
         assert!(self.is_pte_present(vpn));
+        let flag = with_global_flag(vpn, flag);
         self.get_pte_mut(vpn, |pte| {
             pte.set_flag(flag);
         });
-        PageTable::refresh();
         self.get_pte_ref(vpn, |pte| {
             assert_eq!(pte.flag(), flag);
         });
     }
 
+    /// 刷新 vpn 的 TLB 项，页表不是当前页表时，用户空间的页不在当前 CPU 的 TLB 中；
+    /// 其他正在使用这个页表的 CPU 也需要刷新，内核空间的页所有 CPU 都需要刷新
+    pub fn flush_tlb(&self, vpn: VirtPageNum) {
+        self.flush_local_tlb(vpn);
+        flush_tlb_others(self.shootdown_pdt(vpn.base_address().0 < HIGH_ADDRESS_BASE));
+    }
+
+    fn flush_local_tlb(&self, vpn: VirtPageNum) {
+        if self.pdt_ppn == Self::pdt_ppn() || vpn.base_address().0 >= HIGH_ADDRESS_BASE {
+            invlpg(vpn.base_address().0);
+        }
+    }
+
+    /// 通知其他 CPU 刷新 TLB 时使用的页表，修改了内核空间时为 None
+    fn shootdown_pdt(&self, user_only: bool) -> Option<PhysPageNum> {
+        if user_only {
+            Some(self.pdt_ppn)
+        } else {
+            None
+        }
+    }
+
+    /// 页数超过 TLB_FLUSH_ALL_THRESHOLD 时刷新整个 TLB，比逐页 invlpg 更快
+    pub fn flush_tlb_range(&self, vpn_range: VPNRange) {
+        if vpn_range.end.0 <= vpn_range.start.0 {
+            return;
+        }
+        flush_tlb_others(self.shootdown_pdt(vpn_range.end.base_address().0 <= HIGH_ADDRESS_BASE));
+        if self.pdt_ppn != Self::pdt_ppn() && vpn_range.end.base_address().0 <= HIGH_ADDRESS_BASE {
+            return;
+        }
+        if vpn_range.end.0 - vpn_range.start.0 > TLB_FLUSH_ALL_THRESHOLD {
+            Self::flush_tlb_all();
+        } else {
+            for vpn in vpn_range {
+                self.flush_local_tlb(vpn);
+            }
+        }
+    }
+
+    /// 刷新当前 CPU 的整个 TLB：重新加载 cr3 不会刷新全局页，需要切换一次 CR4.PGE
+    pub fn flush_tlb_all() {
+        let cr4 = Cr4::read();
+        if cr4.contains(Cr4::PGE) {
+            (cr4 - Cr4::PGE).write();
+            cr4.write();
+        } else {
+            Self::refresh();
+        }
+    }
+
+    pub fn get_pte_flag(&self, vpn: VirtPageNum) -> PteFlags {
+        assert!(self.is_pte_present(vpn));
+        let mut flag = PteFlags::empty();
+        self.get_pte_ref(vpn, |pte| {
+            flag = pte.flag();
+        });
+        flag
+    }
+
+    /// pte page 是否和 fork 出来的进程共享
+    pub fn is_pte_page_shared(&self, vpn: VPN) -> bool {
+        self.frames.get(&pde_index(vpn)).is_some_and(|frame_ref| Arc::strong_count(frame_ref) > 1)
+    }
+
+    /// 页表占用的物理页，包括页目录和 pte page，不包括内核空间预先分配的 pte page
+    pub fn page_table_frames(&self) -> Vec<PhysPageNum> {
+        let mut ret: Vec<PhysPageNum> = (0..PDT_PAGE_SIZE).map(|idx| PhysPageNum(self.pdt_ppn.0 + idx)).collect();
+        ret.extend(self.frames.values().map(|frame| frame.base_ppn));
+        ret
+    }
+
+    /// 页被换出后，页表项记录 swap slot
+    pub fn set_swap_entry(&self, vpn: VirtPageNum, slot: usize) {
+        assert!(self.is_pte_present(vpn));
+        self.get_pte_mut(vpn, |pte| {
+            *pte = PageTableEntry::new_swapped(slot);
+        });
+        self.flush_tlb(vpn);
+    }
+
+    pub fn clear_swap_entry(&self, vpn: VirtPageNum) {
+        assert!(self.get_swap_slot(vpn).is_some());
+        self.get_pte_mut(vpn, |pte| {
+            *pte = PageTableEntry::empty();
+        });
+    }
+
+    /// 返回内容：vpn 被换出时所在的 swap slot
+    pub fn get_swap_slot(&self, vpn: VirtPageNum) -> Option<usize> {
+        if !self.is_pde_present(vpn) {
+            return None;
+        }
+        let mut slot = None;
+        self.get_pte_ref(vpn, |pte| {
+            if pte.is_swapped() {
+                slot = Some(pte.swap_slot());
+            }
+        });
+        slot
+    }
+
     pub fn tmp_map<F: FnOnce(VirtPageNum)>(&self, ppn: PhysPageNum, f: F) {
         let virt_frame_stub = alloc_kernel_virt_frame(1).unwrap();
         self.map(virt_frame_stub.base_vpn, ppn, PteFlags::P | PteFlags::RW);
@@ -216,17 +457,17 @@ impl PageTable {
     }
 
     pub fn get_pde_ref<F: FnOnce(&PageDirectoryEntry)>(&self, vpn: VirtPageNum, f: F) {
-        let index1 = (vpn.0 >> 10) & 0x3ff;
+        let index1 = pde_index(vpn);
         let pde_array = unsafe {
-            core::slice::from_raw_parts(self.pdt_vpn.base_address().0 as *const PageDirectoryEntry, PTE_SIZE_IN_PAGE)
+            core::slice::from_raw_parts(self.pdt_vpn.base_address().0 as *const PageDirectoryEntry, PDE_COUNT)
         };
         f(&pde_array[index1]);
     }
 
     pub fn get_pde_mut<F: FnOnce(&mut PageDirectoryEntry)>(&self, vpn: VirtPageNum, f: F) {
-        let index1 = (vpn.0 >> 10)& 0x3ff;
+        let index1 = pde_index(vpn);
         let pde_array = unsafe {
-            core::slice::from_raw_parts_mut(self.pdt_vpn.base_address().0 as *mut PageDirectoryEntry, PTE_SIZE_IN_PAGE)
+            core::slice::from_raw_parts_mut(self.pdt_vpn.base_address().0 as *mut PageDirectoryEntry, PDE_COUNT)
         };
         f(&mut pde_array[index1]);
     }
@@ -249,12 +490,12 @@ impl PageTable {
 
     pub fn get_pte_ref<F: FnOnce(&PageTableEntry)>(&self, vpn: VirtPageNum, f: F) {
         assert!(self.is_pde_present(vpn));
-        let index2 = vpn.0 & 0x3ff;
-        let index1 = (vpn.0 >> 10) & 0x3ff;
+        assert!(!self.is_large_page(vpn), "vpn {:#x} is in large page", vpn.base_address().0);
+        let index2 = pte_index(vpn);
+        let index1 = pde_index(vpn);
         
         if self.pdt_ppn == Self::pdt_ppn() || vpn.base_address().0 >= HIGH_ADDRESS_BASE {
-            let address = 0x3ffusize << 22 | index1 << 12;
-            let second_vpn: VirtPageNum = VirtAddr(address).into();
+            let second_vpn = pte_page_vpn(index1);
             let pte_array = unsafe {
                 core::slice::from_raw_parts(second_vpn.base_address().0 as *const PageTableEntry, PTE_SIZE_IN_PAGE)
             };
@@ -273,11 +514,11 @@ impl PageTable {
 
     pub fn get_pte_mut<F: FnOnce(&mut PageTableEntry)>(&self, vpn: VirtPageNum, f: F) {
         assert!(self.is_pde_present(vpn));
-        let index2 = vpn.0 & 0x3ff;
-        let index1 = (vpn.0 >> 10) & 0x3ff;
+        assert!(!self.is_large_page(vpn), "vpn {:#x} is in large page", vpn.base_address().0);
+        let index2 = pte_index(vpn);
+        let index1 = pde_index(vpn);
         if self.pdt_ppn == Self::pdt_ppn() || vpn.base_address().0 >= HIGH_ADDRESS_BASE {
-            let address = 0x3ffusize << 22 | index1 << 12;
-            let second_vpn: VirtPageNum = VirtAddr(address).into();
+            let second_vpn = pte_page_vpn(index1);
             let pte_array = unsafe {
                 core::slice::from_raw_parts_mut(second_vpn.base_address().0 as *mut PageTableEntry, PTE_SIZE_IN_PAGE)
             };
@@ -296,6 +537,9 @@ impl PageTable {
 
     pub fn is_pte_present(&self, vpn: VirtPageNum) -> bool {
         assert!(self.is_pde_present(vpn));
+        if self.is_large_page(vpn) {
+            return true;
+        }
         let mut is_present = false;
         self.get_pte_ref(vpn, |pte| {
             is_present = pte.flag().contains(PteFlags::P);
@@ -305,6 +549,14 @@ impl PageTable {
 
     pub fn get_ppn(&self, vpn: VirtPageNum) -> PhysPageNum {
         assert!(self.is_pte_present(vpn));
+        if self.is_large_page(vpn) {
+            let mut phys_address = 0;
+            self.get_pde_ref(vpn, |pde| {
+                phys_address = pde.address() as usize;
+            });
+            let large_ppn: PhysPageNum = PhysAddr(phys_address).into();
+            return PhysPageNum(large_ppn.0 + vpn.0 % LARGE_PAGE_PAGE_SIZE);
+        }
         let mut phys_address = 0;
         self.get_pte_ref(vpn, |pte| {
             phys_address = pte.address() as usize;
@@ -356,6 +608,9 @@ impl PageTable {
         if !self.is_pde_present(vpn) {
             return None;
         }
+        if self.is_large_page(vpn) {
+            return Some(self.get_ppn(vpn).base_address());
+        }
         let mut address: usize = 0;
         self.get_pte_ref(vpn, |pte|{
             address = pte.address() as usize;
@@ -376,6 +631,12 @@ impl PageTable {
             return false;
         }
         let mut is_writable = false;
+        if self.is_large_page(vpn) {
+            self.get_pde_ref(vpn, |pde| {
+                is_writable = pde.flag().contains(PdeFlags::RW);
+            });
+            return is_writable;
+        }
         self.get_pte_ref(vpn, |pte|{
             is_writable = pte.flag().contains(PteFlags::RW);
         });
@@ -402,10 +663,11 @@ impl VirtPageNum {
         self.as_mut()
     }
 
+    /// PAE 分页时 4 个页目录连续存放，返回 PDE_COUNT 项
     pub fn get_pde_array(&self) -> &'static mut [PageDirectoryEntry] {
         let pa: VirtAddr = self.base_address();
         unsafe {
-            core::slice::from_raw_parts_mut(pa.0 as *mut PageDirectoryEntry, PTE_SIZE_IN_PAGE)
+            core::slice::from_raw_parts_mut(pa.0 as *mut PageDirectoryEntry, PDE_COUNT)
         }
     }
 
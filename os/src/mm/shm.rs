@@ -0,0 +1,103 @@
+use core::ops::Drop;
+use core::option::Option;
+use core::sync::atomic::{AtomicBool, Ordering};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::config::*;
+use super::{alloc_phys_frame, PageTable, PhysFrameStub};
+
+/// 有名字的共享内存段，物理页在所有映射它的进程之间共享
+pub struct ShmSegment {
+    pub id: usize,
+    pub name: String,
+    pub frames: Vec<Arc<PhysFrameStub>>,
+}
+
+impl ShmSegment {
+    pub fn page_count(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+lazy_static! {
+    static ref SHM_SEGMENTS: Arc<Mutex<BTreeMap<usize, Arc<ShmSegment>>>> = Arc::new(Mutex::new(BTreeMap::new()));
+}
+
+/// 打开名字为 name 的共享内存段，不存在时创建 page_count 页的共享内存段，页的内容清零
+/// 返回内容：共享内存段的 id，None 表示已有的共享内存段小于 page_count 页或者物理页不足
+pub fn shm_open(name: &str, page_count: usize, page_table: &PageTable) -> Option<usize> {
+    let mut segments = SHM_SEGMENTS.lock();
+    if let Some(segment) = segments.values().find(|segment| segment.name == name) {
+        if segment.page_count() < page_count {
+            return None;
+        }
+        return Some(segment.id);
+    }
+
+    if page_count == 0 || page_count > SHM_MAX_PAGE_SIZE {
+        return None;
+    }
+    let mut frames = Vec::new();
+    for _ in 0..page_count {
+        let frame = alloc_phys_frame(1)?;
+        page_table.tmp_map(frame.base_ppn, |vpn| {
+            vpn.as_byte_array_mut().iter_mut().for_each(|b| *b = 0);
+        });
+        frames.push(Arc::new(frame));
+    }
+    let id = (0..).find(|id| !segments.contains_key(id)).unwrap();
+    segments.insert(id, Arc::new(ShmSegment { id, name: String::from(name), frames }));
+    Some(id)
+}
+
+/// 删除名字为 name 的共享内存段，之后不能再打开和映射它；已经映射的进程继续使用，全部解除映射后释放物理页
+/// 返回内容：None 表示共享内存段不存在
+pub fn shm_unlink(name: &str) -> Option<()> {
+    let mut segments = SHM_SEGMENTS.lock();
+    let id = segments.values().find(|segment| segment.name == name)?.id;
+    segments.remove(&id);
+    Some(())
+}
+
+/// 返回内容：共享内存段的页数，None 表示 id 不存在
+pub fn shm_page_count(id: usize) -> Option<usize> {
+    SHM_SEGMENTS.lock().get(&id).map(|segment| segment.page_count())
+}
+
+/// 返回内容：共享内存段的 stub，None 表示 id 不存在
+pub fn shm_get(id: usize) -> Option<ShmStub> {
+    SHM_SEGMENTS.lock().get(&id).map(|segment| ShmStub { segment: segment.clone(), is_attached: AtomicBool::new(false) })
+}
+
+/// 共享内存段被映射到进程的地址空间时，由 MapArea 持有
+/// 映射成功过的最后一个 stub 释放时删除共享内存段，没有映射成功的 stub 释放时不影响其他进程打开的共享内存段；
+/// 从来没有被映射的共享内存段由 shm_unlink 删除
+pub struct ShmStub {
+    pub segment: Arc<ShmSegment>,
+    is_attached: AtomicBool,
+}
+
+impl ShmStub {
+    pub fn mark_attached(&self) {
+        self.is_attached.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for ShmStub {
+    fn drop(&mut self) {
+        if !self.is_attached.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut segments = SHM_SEGMENTS.lock();
+        // 共享内存段已经被 shm_unlink 删除时，id 可能被新的共享内存段使用
+        let is_registered = segments.get(&self.segment.id).is_some_and(|segment| Arc::ptr_eq(segment, &self.segment));
+        // 只剩下注册表和当前的 stub 持有共享内存段，说明已经没有进程映射它
+        if is_registered && Arc::strong_count(&self.segment) == 2 {
+            segments.remove(&self.segment.id);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use core::ops::Drop;
+use core::option::Option;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::config::*;
+use crate::drivers::block::{BlockDevice, BLOCK_DEVICE, BLOCK_SIZE};
+use crate::process::{ProcessControlBlock, TaskControlBlock};
+use crate::process::kthread::{self, kthread_should_stop};
+use crate::schedule::PID2PCB;
+use crate::utils::*;
+use super::{phys_frame_usage, VirtPageNum};
+
+const SECTORS_PER_PAGE: usize = MEMORY_PAGE_SIZE / BLOCK_SIZE;
+/// 空闲物理页少于总数的 1 / KSWAPD_LOW_WATERMARK 时 kswapd 开始换出
+const KSWAPD_LOW_WATERMARK: usize = 32;
+/// kswapd 换出到空闲物理页达到总数的 1 / KSWAPD_HIGH_WATERMARK 为止
+const KSWAPD_HIGH_WATERMARK: usize = 16;
+/// kswapd 检查空闲物理页的间隔
+const KSWAPD_INTERVAL_MS: usize = 100;
+
+lazy_static! {
+    static ref SWAP_SLOT_ALLOCATOR: Arc<Mutex<IdAllocator<SWAP_SLOT_BITMAP_SIZE>>> = {
+        Arc::new(Mutex::new(IdAllocator::new(Bitmap::<SWAP_SLOT_BITMAP_SIZE>::new([0; SWAP_SLOT_BITMAP_SIZE]), 0, 0, SWAP_PAGE_COUNT, 0)))
+    };
+
+    /// 时钟算法的指针：上次换出页的 pid 和下一个要检查的 vpn
+    static ref SWAP_CLOCK_HAND: Arc<Mutex<(usize, VirtPageNum)>> = {
+        Arc::new(Mutex::new((0, VirtPageNum(0))))
+    };
+}
+
+pub struct SwapSlotStub {
+    pub slot: usize,
+}
+
+impl Drop for SwapSlotStub {
+    fn drop(&mut self) {
+        SWAP_SLOT_ALLOCATOR.lock().dealloc(self.slot);
+    }
+}
+
+pub fn alloc_swap_slot() -> Option<SwapSlotStub> {
+    SWAP_SLOT_ALLOCATOR.lock().alloc().map(|slot| SwapSlotStub { slot })
+}
+
+pub fn write_swap_slot(slot: usize, data: &[u8; MEMORY_PAGE_SIZE]) {
+    let base_sector = SWAP_BEGIN_SECTOR + slot * SECTORS_PER_PAGE;
+    for (idx, block) in data.as_chunks::<BLOCK_SIZE>().0.iter().enumerate() {
+        BLOCK_DEVICE.write_block(base_sector + idx, block);
+    }
+}
+
+pub fn read_swap_slot(slot: usize, data: &mut [u8; MEMORY_PAGE_SIZE]) {
+    let base_sector = SWAP_BEGIN_SECTOR + slot * SECTORS_PER_PAGE;
+    for (idx, block) in data.as_chunks_mut::<BLOCK_SIZE>().0.iter_mut().enumerate() {
+        BLOCK_DEVICE.read_block(base_sector + idx, block);
+    }
+}
+
+/// 物理页不足时，按时钟算法换出一个用户页
+/// 返回内容：是否有页被换出
+pub fn swap_out_one() -> bool {
+    let processes: Vec<Arc<ProcessControlBlock>> = PID2PCB.lock().values().cloned().collect();
+    if processes.is_empty() {
+        return false;
+    }
+    let mut hand = SWAP_CLOCK_HAND.lock();
+    // 第一圈从指针处开始并清除访问位，指针之前的页在第二圈才清除访问位，所以最多转三圈
+    for _ in 0..3 {
+        let start = processes.iter().position(|process| process.get_pid() >= hand.0).unwrap_or(0);
+        for idx in 0..processes.len() {
+            let process = &processes[(start + idx) % processes.len()];
+            let from_vpn = if process.get_pid() == hand.0 { hand.1 } else { VirtPageNum(0) };
+            if let Some(vpn) = swap_out_from_process(process, from_vpn) {
+                *hand = (process.get_pid(), VirtPageNum(vpn.0 + 1));
+                return true;
+            }
+        }
+        hand.1 = VirtPageNum(0);
+    }
+    false
+}
+
+fn swap_out_from_process(process: &Arc<ProcessControlBlock>, from_vpn: VirtPageNum) -> Option<VirtPageNum> {
+    // 已经被锁住的进程（比如正在处理缺页的当前进程）不参与换出
+    let mut process_inner = process.inner.try_lock()?;
+    let process_inner = &mut *process_inner;
+    let page_table = &mut process_inner.memory_set.page_table;
+    for area in process_inner.memory_set.areas.iter_mut() {
+        if let Some(vpn) = area.swap_out_one(page_table, from_vpn) {
+            return Some(vpn);
+        }
+    }
+    let tasks: Vec<Arc<TaskControlBlock>> = process_inner.tasks.iter().flatten().cloned().collect();
+    for task in tasks {
+        if let Some(mut task_inner) = task.inner.try_lock() {
+            if let Some(user_stack_map_area) = task_inner.user_stack_map_area.as_mut() {
+                if let Some(vpn) = user_stack_map_area.swap_out_one(page_table, from_vpn) {
+                    return Some(vpn);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// 启动 kswapd 内核线程，空闲物理页不足时提前换出用户页，减少缺页处理时同步换出的次数
+pub fn start_kswapd() {
+    kthread::spawn("kswapd", || {
+        while !kthread_should_stop() {
+            let (total, free) = phys_frame_usage();
+            if free < total / KSWAPD_LOW_WATERMARK {
+                let mut swapped = 0;
+                while phys_frame_usage().1 < total / KSWAPD_HIGH_WATERMARK && swap_out_one() {
+                    swapped += 1;
+                }
+                debug!("kswapd swapped out {} pages", swapped);
+            }
+            kthread::sleep(KSWAPD_INTERVAL_MS);
+        }
+        0
+    }).unwrap();
+}
@@ -1,4 +1,4 @@
-use crate::{config::{CODE_SELECTOR, DATA_SELECTOR}, intr::*, mm::{PhysAddr, VirtAddr, VirtPageNum}};
+use crate::{config::{CODE_SELECTOR, DATA_SELECTOR, PERCPU_SELECTOR}, intr::*, mm::{PhysAddr, VirtAddr, VirtPageNum}};
 
 
 #[repr(C)]
@@ -18,7 +18,7 @@ pub struct TaskContext {
 }
 
 impl TaskContext {
-    pub fn empty() -> Self {
+    pub const fn empty() -> Self {
         Self { return_address: 0, esp: 0, es: 0, ds: 0, fs: 0, gs: 0, ebx: 0, ebp: 0, esi: 0, edi: 0 }
     }
 
@@ -30,6 +30,6 @@ impl TaskContext {
 
         // esp point to return address in stack
         esp -= 4;
-        Self { return_address: return_address, esp: esp, es: DATA_SELECTOR as usize, ds: DATA_SELECTOR as usize, fs: DATA_SELECTOR as usize, gs: DATA_SELECTOR as usize, ebx: 0, ebp: 0, esi: 0, edi: 0 }
+        Self { return_address: return_address, esp: esp, es: DATA_SELECTOR as usize, ds: DATA_SELECTOR as usize, fs: DATA_SELECTOR as usize, gs: PERCPU_SELECTOR as usize, ebx: 0, ebp: 0, esi: 0, edi: 0 }
     }
 }
@@ -0,0 +1,173 @@
+//! 内核线程：在 KERNEL_PROCESS 中运行 Rust 闭包，和用户线程一起被调度。
+//! 内核代码在关中断的情况下运行，内核线程不会被时钟中断抢占，
+//! 需要调用 yield_now、sleep 或者阻塞的同步原语主动让出 CPU。
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, Ordering};
+use alloc::{boxed::Box, collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use spin::Mutex;
+
+use crate::schedule::{add_task, block_current_and_run_next, current_task, suspend_current_and_run_next, take_current_task, wakeup_task, schedule};
+use crate::timer::{add_timer, get_time_in_millisecond};
+use super::workqueue::schedule_work;
+use super::{TaskContext, TaskControlBlock, TaskStatus, KERNEL_PROCESS};
+
+type KThreadEntry = Box<dyn FnOnce() -> isize + Send>;
+
+struct KThreadInner {
+    /// 线程开始运行时取出
+    entry: Option<KThreadEntry>,
+    exit_code: Option<isize>,
+    /// 等待线程结束的线程
+    joiners: Vec<Arc<TaskControlBlock>>,
+}
+
+pub struct KThread {
+    name: String,
+    task: Arc<TaskControlBlock>,
+    should_stop: AtomicBool,
+    inner: Mutex<KThreadInner>,
+}
+
+impl KThread {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+lazy_static! {
+    /// 正在运行的内核线程，键是线程的 TID
+    static ref KTHREADS: Arc<Mutex<BTreeMap<usize, Arc<KThread>>>> = Arc::new(Mutex::new(BTreeMap::new()));
+    /// 已经结束、等待回收内核栈的内核线程
+    static ref EXITED_KTHREADS: Arc<Mutex<Vec<Arc<TaskControlBlock>>>> = Arc::new(Mutex::new(Vec::new()));
+}
+
+/// spawn 的返回值，丢弃后线程继续运行，结束后由之后的 spawn 或 join 回收
+pub struct JoinHandle {
+    kthread: Arc<KThread>,
+}
+
+impl JoinHandle {
+    pub fn name(&self) -> &str {
+        self.kthread.name()
+    }
+
+    /// 阻塞当前线程直到内核线程结束
+    /// 返回内容：闭包的返回值
+    pub fn join(self) -> isize {
+        loop {
+            let mut inner = self.kthread.inner.lock();
+            if let Some(exit_code) = inner.exit_code {
+                drop(inner);
+                reap_exited_kthreads();
+                return exit_code;
+            }
+            inner.joiners.push(current_task().unwrap());
+            drop(inner);
+            block_current_and_run_next();
+        }
+    }
+
+    /// 通知内核线程退出并等待它结束，线程需要定期调用 kthread_should_stop 检查
+    /// 返回内容：闭包的返回值
+    pub fn stop(self) -> isize {
+        self.kthread.should_stop.store(true, Ordering::Release);
+        self.join()
+    }
+}
+
+/// 创建内核线程运行 f，线程立即加入就绪队列
+/// 返回内容：None 表示物理页不足，无法分配内核栈
+pub fn spawn<F>(name: &str, f: F) -> Option<JoinHandle>
+where
+    F: FnOnce() -> isize + Send + 'static,
+{
+    reap_exited_kthreads();
+    let task = Arc::new(TaskControlBlock::new::<()>(KERNEL_PROCESS.clone(), kthread_entry as usize, true, None)?);
+    KERNEL_PROCESS.add_task(task.clone());
+    let kthread = Arc::new(KThread {
+        name: String::from(name),
+        task: task.clone(),
+        should_stop: AtomicBool::new(false),
+        inner: Mutex::new(KThreadInner { entry: Some(Box::new(f)), exit_code: None, joiners: Vec::new() }),
+    });
+    KTHREADS.lock().insert(task.tid, kthread.clone());
+    add_task(task);
+    Some(JoinHandle { kthread })
+}
+
+/// 当前内核线程是否被要求退出，不在内核线程中调用时返回 false
+pub fn kthread_should_stop() -> bool {
+    current_kthread().is_some_and(|kthread| kthread.should_stop.load(Ordering::Acquire))
+}
+
+/// 当前运行的内核线程
+pub fn current_kthread() -> Option<Arc<KThread>> {
+    let task = current_task()?;
+    let kthread = KTHREADS.lock().get(&task.tid).cloned()?;
+    Arc::ptr_eq(&kthread.task, &task).then_some(kthread)
+}
+
+/// 让出 CPU，当前线程重新加入就绪队列
+pub fn yield_now() {
+    suspend_current_and_run_next();
+}
+
+/// 阻塞当前线程 ms 毫秒
+pub fn sleep(ms: usize) {
+    let expire_ms = get_time_in_millisecond() + ms as u64;
+    add_timer(expire_ms, current_task().unwrap());
+    block_current_and_run_next();
+}
+
+/// 内核线程的入口，取出闭包运行，结束后唤醒等待的线程
+fn kthread_entry() -> ! {
+    // 中断返回时打开了中断，内核代码需要在关中断的情况下运行
+    unsafe {
+        asm!("cli");
+    }
+    let kthread = current_kthread().unwrap();
+    let entry = kthread.inner.lock().entry.take().unwrap();
+    let exit_code = entry();
+    kthread_exit(kthread, exit_code)
+}
+
+fn kthread_exit(kthread: Arc<KThread>, exit_code: isize) -> ! {
+    let task = take_current_task().unwrap();
+    let joiners = {
+        let mut inner = kthread.inner.lock();
+        inner.exit_code = Some(exit_code);
+        core::mem::take(&mut inner.joiners)
+    };
+    KTHREADS.lock().remove(&task.tid);
+    let mut task_inner = task.inner.lock();
+    task_inner.status = TaskStatus::Block;
+    task_inner.exit_code = Some(exit_code);
+    drop(task_inner);
+    // 内核栈正在使用，切换到其他线程之后才能回收
+    EXITED_KTHREADS.lock().push(task);
+    drop(kthread);
+    for joiner in joiners {
+        wakeup_task(joiner);
+    }
+    // 没有线程 join 时由工作线程回收
+    schedule_work(reap_exited_kthreads);
+    let mut _unused = TaskContext::empty();
+    schedule(&mut _unused as *mut _);
+    panic!("unreachable after kthread_exit!");
+}
+
+/// 回收已经结束的内核线程的 TID 和内核栈
+fn reap_exited_kthreads() {
+    let tasks: Vec<Arc<TaskControlBlock>> = core::mem::take(&mut *EXITED_KTHREADS.lock());
+    if tasks.is_empty() {
+        return;
+    }
+    let mut process_inner = KERNEL_PROCESS.inner.lock();
+    for task in tasks.iter() {
+        process_inner.tasks[task.tid] = None;
+    }
+    // TaskControlBlock 在 drop 时需要锁住进程
+    drop(process_inner);
+    drop(tasks);
+}
@@ -1,6 +1,8 @@
 mod context;
 mod process;
 mod task;
+pub mod kthread;
+pub mod workqueue;
 
 pub use context::*;
 pub use process::*;
@@ -1,4 +1,5 @@
 use core::option::Option;
+use core::sync::atomic::Ordering;
 use alloc::sync::Arc;
 use alloc::task;
 use alloc::vec::Vec;
@@ -16,6 +17,10 @@ use super::task::create_thread_id_allocator;
 use crate::fs::*;
 use crate::fs::stdio::*;
 use crate::sync;
+use crate::schedule::{add_task, remove_task, clamp_nice, SchedEntity, DEFAULT_NICE};
+use crate::timer::remove_timer;
+use crate::smp::{cpu_id, send_reschedule};
+use crate::fs::stdio::remove_stdin_reader;
 
 pub struct ProcessControlBlockInner {
     pub parent: Option<Weak<ProcessControlBlock>>,
@@ -23,6 +28,8 @@ pub struct ProcessControlBlockInner {
     pub memory_set: MemorySet,
     pub tid_allocator: ThreadIdAllocator,
     pub tasks: Vec<Option<Arc<TaskControlBlock>>>,
+    /// 主线程的 TID，主线程退出时进程退出；fork 和 exec 后是调用线程的 TID
+    pub main_tid: usize,
     pub exit_code: Option<isize>,
     pub is_zombie: bool,
     pub fd_table: Vec<Option<Arc<dyn File>>>,
@@ -30,6 +37,14 @@ pub struct ProcessControlBlockInner {
     pub semaphore_list: Vec<Option<Arc<sync::Semaphore>>>,
     pub condvar_list: Vec<Option<Arc<sync::Condvar>>>,
     pub elf_data: Option<&'static [u8]>,
+    /// 每个线程的用户栈最多可以增长到的页数
+    pub user_stack_limit: usize,
+    /// 进程的 nice 值，新建的线程继承这个值
+    pub nice: isize,
+    /// 已经被回收的线程的运行时间
+    pub exited_times: TaskTimes,
+    /// 已经被回收的子进程及其子进程的运行时间
+    pub children_times: TaskTimes,
 }
 
 impl ProcessControlBlockInner {
@@ -40,6 +55,7 @@ impl ProcessControlBlockInner {
             memory_set: memory_set, 
             tid_allocator: create_thread_id_allocator(), 
             tasks: Vec::new(), 
+            main_tid: 0,
             exit_code: None, 
             is_zombie: false, 
             fd_table: vec![
@@ -54,56 +70,112 @@ impl ProcessControlBlockInner {
             semaphore_list: Vec::new(),
             condvar_list: Vec::new(),
             elf_data: None,
+            user_stack_limit: USER_STACK_MAX_PAGE_SIZE,
+            nice: DEFAULT_NICE,
+            exited_times: TaskTimes::default(),
+            children_times: TaskTimes::default(),
         }
     }
 
     /// 修复缺页错误
-    /// 返回内容：是否有修复页表
-    pub fn repair_page_fault(&mut self) -> bool {
+    /// 返回内容：是否有修复页表，None 表示物理页不足
+    pub fn repair_page_fault(&mut self, fault_va: VirtAddr) -> Option<bool> {
+        // 缺页的页被换出到 swap 区，换入即可
+        let fault_vpn = fault_va.virt_page_num_floor();
+        for area in &mut self.memory_set.areas {
+            if area.swap_in_if_need(&mut self.memory_set.page_table, fault_vpn)? {
+                return Some(true);
+            }
+        }
+
         let mut is_modified = false;
         for area in &mut self.memory_set.areas {
-            is_modified |= area.map_if_need(&mut self.memory_set.page_table);
+            is_modified |= area.map_zero_page_if_need(&mut self.memory_set.page_table)?;
         }
 
-        if is_modified {
-            // 刚创建页表，拷贝数据
-            if let Some(elf_data) = self.elf_data.as_ref() {
-                for area in &self.memory_set.areas {
-                    if !area.map_perm.contains(MapPermission::W) {
-                        area.change_perm(area.map_perm | MapPermission::W, &self.memory_set.page_table);
-                    }
-                }
-                if let Some(phs) = self.memory_set.program_headers.as_ref() {
-                    for ph in phs {
-                        let src = &elf_data[ph.file_offset..(ph.file_offset + ph.file_size)];
-                        let dst = unsafe { core::slice::from_raw_parts_mut(ph.virtual_addr as *mut u8, ph.mem_size) };
-                        if ph.file_size == ph.mem_size {
-                            dst.copy_from_slice(src);
-                        } else {
-                            dst.iter_mut().for_each(|b| *b = 0);
-                        }
-                    }
-                }
-                for area in &self.memory_set.areas {
-                    if !area.map_perm.contains(MapPermission::W) {
-                        area.change_perm(area.map_perm, &self.memory_set.page_table);
-                    }
-                }
+        if !self.memory_set.is_elf_loaded {
+            // 刚创建页表，拷贝数据；物理页不足时没有拷贝任何数据，重试时重新拷贝
+            if let Some(elf_data) = self.elf_data {
+                self.memory_set.load_elf(elf_data)?;
+                is_modified = true;
             } else {
                 assert!(false, "no elf data")
             }
-        } else {
+        } else if !is_modified {
             // 已经创建过页表，判断进程是否是有过 fork 操作
             for area in &mut self.memory_set.areas {
                 if area.map_perm.contains(MapPermission::W) {
-                    is_modified |= area.copy_if_need(&mut self.memory_set.page_table);
+                    is_modified |= area.copy_if_need(&mut self.memory_set.page_table, fault_vpn)?;
                 }
             }
         }
 
-        assert!(is_modified);
+        Some(is_modified)
+    }
+
+    /// 进程退出或者被 OOM killer 杀死时，提前释放用户空间的内存，内核栈在线程回收时释放
+    pub fn recycle_user_memory(&mut self) {
+        let page_table = &self.memory_set.page_table;
+        for area in &mut self.memory_set.areas {
+            area.release(page_table);
+        }
+        self.memory_set.areas.clear();
+        for task in self.tasks.iter().flatten() {
+            let mut task_inner = task.inner.lock();
+            if let Some(mut user_stack_map_area) = task_inner.user_stack_map_area.take() {
+                user_stack_map_area.release(page_table);
+            }
+        }
+    }
+
+    /// 返回内容：地址空间和所有线程用户栈的内存使用情况
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = self.memory_set.memory_usage();
+        for task in self.tasks.iter().flatten() {
+            let task_inner = task.inner.lock();
+            if let Some(user_stack_map_area) = task_inner.user_stack_map_area.as_ref() {
+                usage.add_stack_area(user_stack_map_area);
+            }
+        }
+        usage
+    }
+
+    /// 进程的所有线程的运行时间，包括已经被回收的线程
+    pub fn times(&self) -> TaskTimes {
+        let mut times = self.exited_times;
+        for task in self.tasks.iter().flatten() {
+            times.add(&task.inner.lock().times);
+        }
+        times
+    }
+
+    /// 设置进程和所有线程的 nice 值，超出范围的值被限制在范围内
+    pub fn set_nice(&mut self, nice: isize) {
+        self.nice = clamp_nice(nice);
+        for task in self.tasks.iter().flatten() {
+            task.inner.lock().sched_entity.nice = self.nice;
+        }
+    }
 
-        is_modified
+    /// 设置所有线程的调度类，就绪的线程从原来调度类的就绪队列移到新调度类的就绪队列
+    pub fn set_scheduler(&mut self, sched_entity: &SchedEntity) {
+        for task in self.tasks.iter().flatten() {
+            let mut task_inner = task.inner.lock();
+            let entity = &mut task_inner.sched_entity;
+            entity.policy = sched_entity.policy;
+            entity.rt_priority = sched_entity.rt_priority;
+            entity.ticks = 0;
+            entity.dl_runtime = sched_entity.dl_runtime;
+            entity.dl_period = sched_entity.dl_period;
+            entity.dl_deadline = 0;
+            entity.dl_budget = 0;
+            let is_ready = task_inner.status == TaskStatus::Ready;
+            drop(task_inner);
+            if is_ready {
+                remove_task(task.clone());
+                add_task(task.clone());
+            }
+        }
     }
 }
 
@@ -120,7 +192,7 @@ impl Drop for ProcessControlBlockInner {
         }
         let page_table = &self.memory_set.page_table;
         for area in &mut self.memory_set.areas {
-            area.unmap(page_table);
+            area.release(page_table);
         }
         self.memory_set.areas.clear();
     }
@@ -132,29 +204,31 @@ pub struct ProcessControlBlock {
 }
 
 impl ProcessControlBlock {
-    pub fn from_elf_file(elf_data: &[u8]) -> Arc<Self> {
+    /// 返回内容：None 表示物理页不足
+    pub fn from_elf_file(elf_data: &[u8]) -> Option<Arc<Self>> {
         // 1. alloc pid
         let pid_stub = alloc_process_id().unwrap();
         // 2. alloc memory space
-        let (memory_set, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, entry_point) = MemorySet::from_elf(elf_data)?;
         let inner = ProcessControlBlockInner::new(memory_set);
         let process = ProcessControlBlock { pid_stub, inner: Arc::new(Mutex::new(inner)) };
         let process = Arc::new(process);
         // 3. alloc task resource
-        let task = TaskControlBlock::new::<()>(process.clone(), entry_point, false, None);
+        let task = TaskControlBlock::new::<()>(process.clone(), entry_point, false, None)?;
         process.add_task(Arc::new(task));
-        process
+        Some(process)
     }
 
-    pub fn new_kernel_process(entry_point: usize) -> Arc<Self> {
+    /// 返回内容：None 表示物理页不足
+    pub fn new_kernel_process(entry_point: usize) -> Option<Arc<Self>> {
         let pid_stub = alloc_process_id().unwrap();
-        let memory_set = MemorySet::new_kernel_memory_set();
+        let memory_set = MemorySet::new_kernel_memory_set()?;
         let inner = ProcessControlBlockInner::new(memory_set);
         let process = ProcessControlBlock { pid_stub, inner: Arc::new(Mutex::new(inner)) };
         let process = Arc::new(process);
-        let task = TaskControlBlock::new::<()>(process.clone(), entry_point, true, None);
+        let task = TaskControlBlock::new::<()>(process.clone(), entry_point, true, None)?;
         process.add_task(Arc::new(task));
-        process
+        Some(process)
     }
 
     pub fn get_pid(&self) -> usize {
@@ -170,15 +244,32 @@ impl ProcessControlBlock {
         inner.tasks[tid] = Some(task);
     }
 
-    pub fn fork(&self) -> Arc<Self> {
+    /// 只复制调用 fork 的线程，子进程中该线程的 TID 不变并成为主线程
+    /// 返回内容：None 表示物理页不足
+    pub fn fork(&self, tid: usize) -> Option<Arc<Self>> {
         // alloc pid
         let pid_stub = alloc_process_id().unwrap();
 
         let mut process_inner = self.inner.lock();
-        assert!(process_inner.tasks.len() == 1);
+        let task = process_inner.tasks[tid].clone().unwrap();
+        // 页表复制前设置用户栈只读，子进程共享的 pte page 中也是只读的
+        task.prepare_fork(&process_inner.memory_set.page_table);
         // copy memory space
-        let memory_set = process_inner.memory_set.copy();
-        let tid_allocator = process_inner.tid_allocator;
+        let mut memory_set = process_inner.memory_set.copy()?;
+        // 其他线程的用户栈不属于子进程，从子进程的页表中清除
+        let mut tid_allocator = process_inner.tid_allocator;
+        for (other_tid, other_task) in process_inner.tasks.iter().enumerate() {
+            if other_tid == tid {
+                continue;
+            }
+            if let Some(other_task) = other_task.as_ref() {
+                tid_allocator.dealloc(other_tid);
+                let other_task_inner = other_task.inner.lock();
+                if let Some(user_stack_map_area) = other_task_inner.user_stack_map_area.as_ref() {
+                    user_stack_map_area.clear_from_forked_page_table(&mut memory_set.page_table)?;
+                }
+            }
+        }
         let tasks: Vec<Option<Arc<TaskControlBlock>>> = Vec::new();
         // copy fd table
         let mut new_fd_table: Vec<Option<Arc<dyn File>>> = Vec::new();
@@ -219,6 +310,7 @@ impl ProcessControlBlock {
             memory_set,
             tid_allocator,
             tasks,
+            main_tid: tid,
             exit_code: process_inner.exit_code.clone(),
             is_zombie: process_inner.is_zombie,
             fd_table: new_fd_table,
@@ -226,18 +318,25 @@ impl ProcessControlBlock {
             semaphore_list: semaphore_list,
             condvar_list: condvar_list,
             elf_data: process_inner.elf_data,
+            user_stack_limit: process_inner.user_stack_limit,
+            nice: process_inner.nice,
+            exited_times: TaskTimes::default(),
+            children_times: TaskTimes::default(),
         };
         let new_process = ProcessControlBlock { pid_stub, inner: Arc::new(Mutex::new(inner)) };
         let new_process = Arc::new(new_process);
 
         // copy task
         let mut tasks: Vec<Option<Arc<TaskControlBlock>>> = Vec::new();
-        for task_option in &process_inner.tasks {
-            if let Some(task) = task_option.as_ref() {
-                tasks.push(Some(Arc::new(task.copy(new_process.clone()))));
-            } else {
-                tasks.push(None);
-            }
+        while tasks.len() <= tid {
+            tasks.push(None);
+        }
+        if let Some(new_task) = task.copy(new_process.clone()) {
+            tasks[tid] = Some(Arc::new(new_task));
+        } else {
+            // 子进程的页表页和当前进程共享，不能通过 unmap 释放子进程的内存
+            new_process.inner.lock().memory_set.areas.clear();
+            return None;
         }
         let _new_process = new_process.clone();
         let mut new_process_inner = _new_process.inner.lock();
@@ -246,21 +345,73 @@ impl ProcessControlBlock {
         
         process_inner.children.push(new_process.clone());
 
-        new_process
+        Some(new_process)
     }
 
-    pub fn exec(&self, elf_data: &[u8]) {
+    /// 在 TID 为 tid 的线程中执行新的程序，其他线程全部销毁
+    /// 返回内容：false 表示当前线程已经被其他线程的 exec 销毁
+    pub fn exec(&self, elf_data: &[u8], tid: usize) -> bool {
         let mut process_inner = self.inner.lock();
-        assert!(process_inner.tasks.len() == 1);
+        if process_inner.tasks[tid].is_none() {
+            return false;
+        }
+
+        let mut other_tasks: Vec<Arc<TaskControlBlock>> = Vec::new();
+        for (other_tid, task_option) in process_inner.tasks.iter_mut().enumerate() {
+            if other_tid != tid {
+                if let Some(task) = task_option.take() {
+                    other_tasks.push(task);
+                }
+            }
+        }
+        for task in &other_tasks {
+            task.killed.store(true, Ordering::SeqCst);
+            remove_task(task.clone());
+            remove_timer(task);
+            remove_stdin_reader(task);
+        }
+        // 其他 CPU 上运行的线程可能在等待进程锁，释放锁后再等待
+        drop(process_inner);
+        // 其他 CPU 上运行的线程收到 IPI 后在返回用户态前退出，等它们离开 CPU 后才能释放用户栈、重置地址空间
+        for task in &other_tasks {
+            if task.on_cpu.load(Ordering::SeqCst) {
+                let cpu = task.inner.lock().sched_entity.cpu;
+                if cpu != cpu_id() {
+                    send_reschedule(cpu);
+                }
+            }
+        }
+        for task in &other_tasks {
+            while task.on_cpu.load(Ordering::Acquire) {
+                core::hint::spin_loop();
+            }
+        }
+
+        let mut process_inner = self.inner.lock();
+        for task in &other_tasks {
+            let mut task_inner = task.inner.lock();
+            process_inner.exited_times.add(&task_inner.times);
+            if let Some(mut user_stack_map_area) = task_inner.user_stack_map_area.take() {
+                user_stack_map_area.unmap(&mut process_inner.memory_set.page_table);
+            }
+        }
+        // 阻塞的线程在同步原语的等待队列中，同步原语不能在新的程序中使用
+        process_inner.mutex_list.clear();
+        process_inner.semaphore_list.clear();
+        process_inner.condvar_list.clear();
+        process_inner.main_tid = tid;
 
         process_inner.elf_data = None;
         let entry_point = process_inner.memory_set.reset_from_elf(elf_data);
         
-        if let Some(task_option) = process_inner.tasks.first() {
-            if let Some(task) = task_option {
-                task.reset(entry_point, &process_inner.memory_set.page_table);
-            }
+        let user_stack_top = process_inner.memory_set.user_stack_top;
+        if let Some(task) = process_inner.tasks[tid].clone() {
+            task.reset(entry_point, &mut process_inner.memory_set.page_table, user_stack_top);
         }
+        // 其他线程的内核栈和 TID 在 drop 时回收，需要先释放进程锁
+        drop(process_inner);
+        drop(other_tasks);
+        true
     }
 }
 
@@ -271,13 +422,13 @@ impl Drop for ProcessControlBlock {
     }
 }
 
-pub fn fork(process: Arc<ProcessControlBlock>) -> Arc<ProcessControlBlock> {
-    let new_process = process.fork();
+pub fn fork(process: Arc<ProcessControlBlock>, tid: usize) -> Option<Arc<ProcessControlBlock>> {
+    let new_process = process.fork(tid)?;
     {
         let mut new_process_inner = new_process.inner.lock();
         new_process_inner.parent = Some(Arc::downgrade(&process));
     }
-    new_process
+    Some(new_process)
 }
 
 lazy_static! {
@@ -288,10 +439,12 @@ lazy_static! {
     pub static ref KERNEL_PROCESS: Arc<ProcessControlBlock> = {
         let pdt_pa = PhysAddr(KERNEL_PDT_PHYS_ADDRESS);
         let pdt_ppn = pdt_pa.phys_page_num_floor();
-        let pdt_pstub = PhysFrameStub { base_ppn: pdt_ppn, len: 1 };
-        let pdt_vstub = alloc_kernel_virt_frame(1).unwrap();
+        let pdt_pstub = PhysFrameStub { base_ppn: pdt_ppn, len: PDT_PAGE_SIZE };
+        let pdt_vstub = alloc_kernel_virt_frame(PDT_PAGE_SIZE).unwrap();
         let pdt_vpn = pdt_vstub.base_vpn;
-        PageTable::static_map(pdt_vpn, pdt_ppn, PteFlags::P | PteFlags::RW);
+        for idx in 0..PDT_PAGE_SIZE {
+            PageTable::static_map(VirtPageNum(pdt_vpn.0 + idx), PhysPageNum(pdt_ppn.0 + idx), PteFlags::P | PteFlags::RW);
+        }
         let page_table = PageTable::from_exists(pdt_ppn, pdt_vpn);
         // 内核的 user_stack_base 没有作用
         let memory_set = MemorySet::new(
@@ -306,6 +459,7 @@ lazy_static! {
             memory_set,
             tid_allocator: create_thread_id_allocator(),
             tasks: Vec::new(),
+            main_tid: 0,
             exit_code: None,
             is_zombie: false,
             fd_table: vec![],
@@ -313,6 +467,10 @@ lazy_static! {
             semaphore_list: Vec::new(),
             condvar_list: Vec::new(),
             elf_data: None,
+            user_stack_limit: 0,
+            nice: DEFAULT_NICE,
+            exited_times: TaskTimes::default(),
+            children_times: TaskTimes::default(),
         };
 
         let pid_stub = alloc_process_id().unwrap();
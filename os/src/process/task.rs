@@ -1,12 +1,15 @@
 use core::iter::zip;
+use core::sync::atomic::AtomicBool;
 
 use alloc::sync::{Arc, Weak};
 use spin::Mutex;
 
 use crate::{intr::IntrContext, mm::MapArea};
+use crate::clock::NSEC_PER_TICK;
 use crate::config::*;
 use crate::mm::*;
 use crate::utils::*;
+use crate::schedule::SchedEntity;
 use super::ProcessControlBlockInner;
 use super::{context::TaskContext, process::ProcessControlBlock};
 
@@ -15,6 +18,28 @@ pub enum TaskStatus {
     Ready, Running, Block
 }
 
+/// 线程的运行时间和上下文切换次数，时间的单位是时钟中断
+#[derive(Clone, Copy, Default)]
+pub struct TaskTimes {
+    /// 在用户态运行的时间，按时钟中断采样
+    pub utime: u64,
+    /// 在内核态运行的时间，内核代码关中断运行，采样不到，按进出内核的时间戳统计
+    pub stime: u64,
+    /// 主动让出 CPU 的次数，例如阻塞或者 yield
+    pub nvcsw: u64,
+    /// 被抢占的次数
+    pub nivcsw: u64,
+}
+
+impl TaskTimes {
+    pub fn add(&mut self, other: &TaskTimes) {
+        self.utime += other.utime;
+        self.stime += other.stime;
+        self.nvcsw += other.nvcsw;
+        self.nivcsw += other.nivcsw;
+    }
+}
+
 pub struct TaskControlBlockInner {
     pub status: TaskStatus,
     pub intr_cx: IntrContext,
@@ -25,23 +50,57 @@ pub struct TaskControlBlockInner {
     pub kernel_stack_map_area: MapArea,
     pub user_stack_map_area: Option<MapArea>,
     pub exit_code: Option<isize>,
+    /// 调度器使用的优先级和行程
+    pub sched_entity: SchedEntity,
+    pub times: TaskTimes,
+    /// 这次进入内核态的时间，None 表示不在内核态或者没有运行
+    kernel_since_ns: Option<u64>,
+    /// 还不满一个时钟中断周期、没有计入 stime 的内核态时间
+    stime_remainder_ns: u64,
 }
 
 impl TaskControlBlockInner {
-    pub fn repair_page_fault(&mut self, page_table: &mut PageTable) -> bool {
+    /// 从用户态陷入内核或者切换到该线程时调用，开始统计内核态运行时间
+    pub fn enter_kernel(&mut self, now_ns: u64) {
+        self.kernel_since_ns = Some(now_ns);
+    }
+
+    /// 返回用户态或者切换出该线程时调用，把这次在内核态运行的时间计入 stime
+    pub fn leave_kernel(&mut self, now_ns: u64) {
+        if let Some(since_ns) = self.kernel_since_ns.take() {
+            self.stime_remainder_ns += now_ns.saturating_sub(since_ns);
+            self.times.stime += self.stime_remainder_ns / NSEC_PER_TICK;
+            self.stime_remainder_ns %= NSEC_PER_TICK;
+        }
+    }
+
+    /// 修复用户栈的缺页错误
+    /// fault_va 是缺页地址，stack_limit 是用户栈最多可以使用的页数
+    /// 返回内容：是否有修复页表，None 表示物理页不足
+    pub fn repair_page_fault(&mut self, page_table: &mut PageTable, fault_va: VirtAddr, stack_limit: usize) -> Option<bool> {
         let mut is_modified = false;
         if let Some(user_stack_map_area) = self.user_stack_map_area.as_mut() {
-            is_modified |= user_stack_map_area.map_if_need(page_table);
+            // 缺页的页被换出到 swap 区，换入即可
+            let fault_vpn = fault_va.virt_page_num_floor();
+            if user_stack_map_area.swap_in_if_need(page_table, fault_vpn)? {
+                return Some(true);
+            }
+
+            // 缺页地址在栈底下方且没有超过栈的上限，向下扩展用户栈
+            let stack_top_vpn = self.user_stack_top_address.unwrap().virt_page_num_floor();
+            if fault_vpn < user_stack_map_area.vpn_range.start && fault_vpn.0 + stack_limit >= stack_top_vpn.0 {
+                user_stack_map_area.grow_down(fault_vpn);
+            }
+
+            is_modified |= user_stack_map_area.map_zero_page_if_need(page_table)?;
 
             if !is_modified {
                 // 已经创建过页表，判断进程是否是有过 fork 操作
-                is_modified |= user_stack_map_area.copy_if_need(page_table);
+                is_modified |= user_stack_map_area.copy_if_need(page_table, fault_vpn)?;
             }
         }
-        
-        assert!(is_modified);
 
-        is_modified
+        Some(is_modified)
     }
 }
 
@@ -49,15 +108,25 @@ pub struct TaskControlBlock {
     pub tid: usize,
     pub process: Weak<ProcessControlBlock>,
     pub inner: Arc<Mutex<TaskControlBlockInner>>,
+    /// 线程正在某个 CPU 上运行，或者刚被切换下来还没有离开内核栈
+    pub on_cpu: AtomicBool,
+    /// 线程被其他线程的 exec 销毁，不再返回用户态
+    pub killed: AtomicBool,
 }
 
 impl TaskControlBlock {
-    pub fn new<T>(process: Arc<ProcessControlBlock>, entry_point: usize, is_kernel_task: bool, user_data: Option<T>) -> Self {
+    /// 返回内容：None 表示物理页不足或者 tid 已经用完，无法创建线程
+    pub fn new<T>(process: Arc<ProcessControlBlock>, entry_point: usize, is_kernel_task: bool, user_data: Option<T>) -> Option<Self> {
+        // 内核栈的虚拟页最先分配，之后失败时随 stub 一起释放
+        let kernel_stack_vstub = alloc_kernel_virt_frame(KERNEL_STACK_PAGE_SIZE + 2)?;
         let mut process_inner = process.inner.lock();
-        let tid = process_inner.tid_allocator.alloc().unwrap();
+        let tid = process_inner.tid_allocator.alloc()?;
         // user stack
         let (user_stack_top_va, user_stack_area, intr_context) = if !is_kernel_task {
-            let user_stack_top_address = USER_STACK_TOP_VIRT_ADDRESS - (USER_STACK_SIZE + MEMORY_PAGE_SIZE) * tid;
+            let Some(user_stack_top_address) = user_stack_top_address(process_inner.memory_set.user_stack_top, tid) else {
+                process_inner.tid_allocator.dealloc(tid);
+                return None;
+            };
             let user_stack_bottom_address = user_stack_top_address - USER_STACK_SIZE;
             let user_stack_top_va = VirtAddr(user_stack_top_address);
             let user_stack_bottom_va = VirtAddr(user_stack_bottom_address);
@@ -67,7 +136,11 @@ impl TaskControlBlock {
             );
             let mut intr_cx = IntrContext::user_intr_context(VirtAddr(entry_point), VirtAddr(user_stack_top_address));
             if let Some(use_data) = user_data {
-                user_stack_area.map_if_need(&mut process_inner.memory_set.page_table);
+                if user_stack_area.map_if_need(&mut process_inner.memory_set.page_table).is_none() {
+                    user_stack_area.unmap(&mut process_inner.memory_set.page_table);
+                    process_inner.tid_allocator.dealloc(tid);
+                    return None;
+                }
                 Self::push_data_to_user_stack(&mut intr_cx, use_data);
             }
             // user_stack_area.map_if_need(&mut process_inner.memory_set.page_table);
@@ -77,14 +150,20 @@ impl TaskControlBlock {
         };
         
         // kernel stack
-        let kernel_stack_vstub = alloc_kernel_virt_frame(KERNEL_STACK_PAGE_SIZE + 2).unwrap();
         let kernel_stack_bottom_vpn = VirtPageNum(kernel_stack_vstub.base_vpn.0 + 1);
         let kernel_stack_top_vpn = VirtPageNum(kernel_stack_bottom_vpn.0 + KERNEL_STACK_PAGE_SIZE);
         let mut kernel_stack_area = MapArea::new(
             kernel_stack_bottom_vpn..kernel_stack_top_vpn, 
             MapPermission::R | MapPermission::W
         );
-        kernel_stack_area.map_if_need(&mut process_inner.memory_set.page_table);
+        if kernel_stack_area.map_if_need(&mut process_inner.memory_set.page_table).is_none() {
+            kernel_stack_area.unmap(&mut process_inner.memory_set.page_table);
+            if let Some(mut user_stack_area) = user_stack_area {
+                user_stack_area.unmap(&mut process_inner.memory_set.page_table);
+            }
+            process_inner.tid_allocator.dealloc(tid);
+            return None;
+        }
         let task_inner = TaskControlBlockInner {
             status: TaskStatus::Ready,
             intr_cx: intr_context,
@@ -95,36 +174,47 @@ impl TaskControlBlock {
             kernel_stack_map_area: kernel_stack_area,
             user_stack_map_area: user_stack_area,
             exit_code: None,
+            sched_entity: SchedEntity::new(process_inner.nice),
+            times: TaskTimes::default(),
+            kernel_since_ns: None,
+            stime_remainder_ns: 0,
         };
 
-        Self { tid: tid, process: Arc::downgrade(&process), inner: Arc::new(Mutex::new(task_inner)) }
+        Some(Self { tid: tid, process: Arc::downgrade(&process), inner: Arc::new(Mutex::new(task_inner)), on_cpu: AtomicBool::new(false), killed: AtomicBool::new(false) })
     }
 
-    pub fn copy(&self, new_process: Arc<ProcessControlBlock>) -> Self {
+    /// fork 复制页表之前调用，在当前进程的页表中设置用户栈只读，写时复制
+    pub fn prepare_fork(&self, page_table: &PageTable) {
         let task_inner = self.inner.lock();
-        let mut process_inner = new_process.inner.lock();
-        
-        let new_user_stack_area = if let Some(user_stack_map_area) = task_inner.user_stack_map_area.as_ref() {
+        if let Some(user_stack_map_area) = task_inner.user_stack_map_area.as_ref() {
             if user_stack_map_area.map_perm.contains(MapPermission::W) {
                 let mut map_perm: MapPermission = user_stack_map_area.map_perm;
                 map_perm.remove(MapPermission::W);
-                user_stack_map_area.change_perm(map_perm, &process_inner.memory_set.page_table);
+                user_stack_map_area.change_perm(map_perm, page_table);
             }
-            Some(user_stack_map_area.copy())
-        } else {
-            None
-        };
+        }
+    }
+
+    /// 返回内容：None 表示物理页不足，无法创建内核栈
+    pub fn copy(&self, new_process: Arc<ProcessControlBlock>) -> Option<Self> {
+        let kernel_stack_vstub = alloc_kernel_virt_frame(KERNEL_STACK_PAGE_SIZE + 2)?;
+        let task_inner = self.inner.lock();
+        let mut process_inner = new_process.inner.lock();
+        
+        // 用户栈已经在 prepare_fork 中设置为只读
+        let new_user_stack_area = task_inner.user_stack_map_area.as_ref().map(|user_stack_map_area| user_stack_map_area.copy());
         
         // kernel stack
-        let kernel_stack_vstub = alloc_kernel_virt_frame(KERNEL_STACK_PAGE_SIZE + 2).unwrap();
         let kernel_stack_bottom_vpn = VirtPageNum(kernel_stack_vstub.base_vpn.0 + 1);
         let kernel_stack_top_vpn = VirtPageNum(kernel_stack_bottom_vpn.0 + KERNEL_STACK_PAGE_SIZE);
         let mut kernel_stack_area = MapArea::new(
             kernel_stack_bottom_vpn..kernel_stack_top_vpn, 
             MapPermission::R | MapPermission::W
         );
-        let is_modified = kernel_stack_area.map_if_need(&mut process_inner.memory_set.page_table);
-        assert!(is_modified);
+        if kernel_stack_area.map_if_need(&mut process_inner.memory_set.page_table).is_none() {
+            kernel_stack_area.unmap(&mut process_inner.memory_set.page_table);
+            return None;
+        }
 
         let mut intr_cx = task_inner.intr_cx;
         // fork 系统调用使用该方法
@@ -141,16 +231,22 @@ impl TaskControlBlock {
             kernel_stack_map_area: kernel_stack_area,
             user_stack_map_area: new_user_stack_area,
             exit_code: task_inner.exit_code.clone(),
+            sched_entity: SchedEntity::new(process_inner.nice),
+            times: TaskTimes::default(),
+            kernel_since_ns: None,
+            stime_remainder_ns: 0,
         };
 
-        Self { 
+        Some(Self { 
             tid: self.tid, 
             process: Arc::downgrade(&new_process), 
-            inner: Arc::new(Mutex::new(task_inner)) 
-        }
+            inner: Arc::new(Mutex::new(task_inner)),
+            on_cpu: AtomicBool::new(false),
+            killed: AtomicBool::new(false),
+        })
     }
 
-    pub fn reset(&self, entry_point: usize, page_table: &PageTable) {
+    pub fn reset(&self, entry_point: usize, page_table: &mut PageTable, user_stack_top: usize) {
         assert_ne!(entry_point, 0);
         let mut task_inner = self.inner.lock();
 
@@ -158,7 +254,8 @@ impl TaskControlBlock {
             map_area.unmap(page_table);
         }
 
-        let user_stack_top_address = USER_STACK_TOP_VIRT_ADDRESS - (USER_STACK_SIZE + MEMORY_PAGE_SIZE) * self.tid;
+        // 创建线程时已经检查过 tid
+        let user_stack_top_address = user_stack_top_address(user_stack_top, self.tid).unwrap();
         let user_stack_bottom_address = user_stack_top_address - USER_STACK_SIZE;
         let user_stack_top_va = VirtAddr(user_stack_top_address);
         let user_stack_bottom_va = VirtAddr(user_stack_bottom_address);
@@ -212,8 +309,18 @@ impl Drop for TaskControlBlock {
     }
 }
 
+/// 每个线程的用户栈从进程的用户栈顶向下排列，占用 USER_STACK_MAX_SIZE 大小的空间，用户栈之间有一个 guard page；
+/// 用户栈不能进入下方的共享内存区域，按照随机化后最低的用户栈顶计算，exec 重新随机化栈顶后也不会重叠
+/// 返回内容：None 表示 tid 太大，放不下这个线程的用户栈
+fn user_stack_top_address(user_stack_top: usize, tid: usize) -> Option<usize> {
+    if tid >= USER_THREAD_MAX_COUNT {
+        return None;
+    }
+    Some(user_stack_top - (USER_STACK_MAX_SIZE + MEMORY_PAGE_SIZE) * tid)
+}
+
 pub type ThreadIdAllocator = IdAllocator<THREAD_ID_BITMAP_SIZE>;
 
 pub fn create_thread_id_allocator() -> ThreadIdAllocator {
-    IdAllocator::new(Bitmap::<THREAD_ID_BITMAP_SIZE>::new([0; THREAD_ID_BITMAP_SIZE]), 0, 0, THREAD_MAX_ID, 0)
+    IdAllocator::new(Bitmap::<THREAD_ID_BITMAP_SIZE>::new([0; THREAD_ID_BITMAP_SIZE]), 0, 0, THREAD_MAX_ID.min(USER_THREAD_MAX_COUNT), 0)
 }
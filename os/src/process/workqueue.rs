@@ -0,0 +1,91 @@
+//! 工作队列：由内核线程运行的函数队列，用于可能阻塞或者耗时较长、不适合在软中断中完成的工作。
+//! 可以在软中断中调用 queue_work 把工作推迟到内核线程中运行。
+
+use alloc::{boxed::Box, collections::VecDeque, string::String, sync::Arc};
+use spin::Mutex;
+
+use crate::intr::softirq::without_intr;
+use crate::schedule::{block_current_and_run_next, current_task, wakeup_task};
+use super::kthread;
+use super::TaskControlBlock;
+
+type Work = Box<dyn FnOnce() + Send>;
+
+struct WorkQueueInner {
+    works: VecDeque<Work>,
+    /// 没有工作时阻塞的工作线程
+    idle_worker: Option<Arc<TaskControlBlock>>,
+}
+
+pub struct WorkQueue {
+    name: String,
+    inner: Mutex<WorkQueueInner>,
+}
+
+impl WorkQueue {
+    /// 创建工作队列和它的工作线程，工作线程一直运行
+    /// 返回内容：None 表示物理页不足，无法创建工作线程
+    pub fn new(name: &str) -> Option<Arc<Self>> {
+        let work_queue = Arc::new(Self {
+            name: String::from(name),
+            inner: Mutex::new(WorkQueueInner { works: VecDeque::new(), idle_worker: None }),
+        });
+        let worker_queue = work_queue.clone();
+        kthread::spawn(name, move || worker_queue.worker_loop())?;
+        Some(work_queue)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 把工作加入队列，由工作线程按加入的顺序运行
+    pub fn queue_work<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let idle_worker = without_intr(|| {
+            let mut inner = self.inner.lock();
+            inner.works.push_back(Box::new(f));
+            inner.idle_worker.take()
+        });
+        if let Some(worker) = idle_worker {
+            without_intr(|| wakeup_task(worker));
+        }
+    }
+
+    fn worker_loop(&self) -> isize {
+        loop {
+            let work = {
+                let mut inner = self.inner.lock();
+                let work = inner.works.pop_front();
+                if work.is_none() {
+                    inner.idle_worker = Some(current_task().unwrap());
+                }
+                work
+            };
+            match work {
+                Some(work) => work(),
+                None => block_current_and_run_next(),
+            }
+        }
+    }
+}
+
+lazy_static! {
+    /// 系统默认的工作队列
+    pub static ref SYSTEM_WORK_QUEUE: Arc<WorkQueue> = WorkQueue::new("events").unwrap();
+}
+
+/// 创建系统默认的工作队列，需要在关中断的情况下创建工作线程
+pub fn init() {
+    let _ = SYSTEM_WORK_QUEUE.name();
+}
+
+/// 把工作加入系统默认的工作队列
+pub fn schedule_work<F>(f: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    SYSTEM_WORK_QUEUE.queue_work(f);
+}
@@ -39,6 +39,26 @@ fn init_prgrams() -> BTreeMap<&'static str, &'static [u8]> {
         fn app_condsync_condvar_end();
         fn app_barrier_condvar_start();
         fn app_barrier_condvar_end();
+        fn app_stack_grow_start();
+        fn app_stack_grow_end();
+        fn app_shm_producer_consumer_start();
+        fn app_shm_producer_consumer_end();
+        fn app_free_start();
+        fn app_free_end();
+        fn app_ps_start();
+        fn app_ps_end();
+        fn app_thread_fork_start();
+        fn app_thread_fork_end();
+        fn app_priority_start();
+        fn app_priority_end();
+        fn app_rt_sched_start();
+        fn app_rt_sched_end();
+        fn app_rusage_start();
+        fn app_rusage_end();
+        fn app_uptime_start();
+        fn app_uptime_end();
+        fn app_interrupts_start();
+        fn app_interrupts_end();
     }
 
     let intiproc_data: &'static [u8] = unsafe {
@@ -92,6 +112,36 @@ fn init_prgrams() -> BTreeMap<&'static str, &'static [u8]> {
     let barrier_condvar_data: &'static [u8] = unsafe {
         core::slice::from_raw_parts(app_barrier_condvar_start as usize as *const u8, app_barrier_condvar_end as usize - app_barrier_condvar_start as usize)
     };
+    let stack_grow_data: &'static [u8] = unsafe {
+        core::slice::from_raw_parts(app_stack_grow_start as usize as *const u8, app_stack_grow_end as usize - app_stack_grow_start as usize)
+    };
+    let shm_producer_consumer_data: &'static [u8] = unsafe {
+        core::slice::from_raw_parts(app_shm_producer_consumer_start as usize as *const u8, app_shm_producer_consumer_end as usize - app_shm_producer_consumer_start as usize)
+    };
+    let free_data: &'static [u8] = unsafe {
+        core::slice::from_raw_parts(app_free_start as usize as *const u8, app_free_end as usize - app_free_start as usize)
+    };
+    let ps_data: &'static [u8] = unsafe {
+        core::slice::from_raw_parts(app_ps_start as usize as *const u8, app_ps_end as usize - app_ps_start as usize)
+    };
+    let thread_fork_data: &'static [u8] = unsafe {
+        core::slice::from_raw_parts(app_thread_fork_start as usize as *const u8, app_thread_fork_end as usize - app_thread_fork_start as usize)
+    };
+    let priority_data: &'static [u8] = unsafe {
+        core::slice::from_raw_parts(app_priority_start as usize as *const u8, app_priority_end as usize - app_priority_start as usize)
+    };
+    let rt_sched_data: &'static [u8] = unsafe {
+        core::slice::from_raw_parts(app_rt_sched_start as usize as *const u8, app_rt_sched_end as usize - app_rt_sched_start as usize)
+    };
+    let rusage_data: &'static [u8] = unsafe {
+        core::slice::from_raw_parts(app_rusage_start as usize as *const u8, app_rusage_end as usize - app_rusage_start as usize)
+    };
+    let uptime_data: &'static [u8] = unsafe {
+        core::slice::from_raw_parts(app_uptime_start as usize as *const u8, app_uptime_end as usize - app_uptime_start as usize)
+    };
+    let interrupts_data: &'static [u8] = unsafe {
+        core::slice::from_raw_parts(app_interrupts_start as usize as *const u8, app_interrupts_end as usize - app_interrupts_start as usize)
+    };
 
     let mut programs = BTreeMap::new();
     programs.insert("initproc", intiproc_data);
@@ -111,6 +161,16 @@ fn init_prgrams() -> BTreeMap<&'static str, &'static [u8]> {
     programs.insert("condsync_sem", condsync_sem_data);
     programs.insert("condsync_condvar", condsync_condvar_data);
     programs.insert("barrier_condvar", barrier_condvar_data);
+    programs.insert("stack_grow", stack_grow_data);
+    programs.insert("shm_producer_consumer", shm_producer_consumer_data);
+    programs.insert("free", free_data);
+    programs.insert("ps", ps_data);
+    programs.insert("thread_fork", thread_fork_data);
+    programs.insert("priority", priority_data);
+    programs.insert("rt_sched", rt_sched_data);
+    programs.insert("rusage", rusage_data);
+    programs.insert("uptime", uptime_data);
+    programs.insert("interrupts", interrupts_data);
     programs
 }
 
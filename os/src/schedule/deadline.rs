@@ -0,0 +1,107 @@
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::process::TaskControlBlock;
+use super::scheduler::Scheduler;
+
+struct DeadlineEntry {
+    /// 就绪线程的截止时间；被限流的线程恢复运行的时间
+    deadline: u64,
+    task: Arc<TaskControlBlock>,
+}
+
+/// 最早截止时间优先：总是运行截止时间最早的线程。
+/// 线程每个周期最多运行 dl_runtime 个时钟中断，用完后被限流，到截止时间时进入下一个周期，
+/// 截止时间推后一个周期，重新获得 dl_runtime 个时钟中断
+pub struct DeadlineScheduler {
+    ready_queue: Vec<DeadlineEntry>,
+    /// 用完当前周期的运行时间的线程
+    throttled_queue: Vec<DeadlineEntry>,
+    /// 当前时间，单位是时钟中断
+    jiffies: u64,
+}
+
+impl DeadlineScheduler {
+    pub fn new() -> Self {
+        Self { ready_queue: Vec::new(), throttled_queue: Vec::new(), jiffies: 0 }
+    }
+
+    pub fn has_ready(&self) -> bool {
+        !self.ready_queue.is_empty()
+    }
+
+    /// 被限流的线程中最早恢复运行的时间，单位是时钟中断；CPU 空闲停止时钟中断时不能晚于这个时间唤醒
+    pub fn next_replenish(&self) -> Option<u64> {
+        self.throttled_queue.iter().map(|entry| entry.deadline).min()
+    }
+
+    /// 时钟中断时更新时间，到了截止时间的被限流线程进入下一个周期
+    pub fn update_clock(&mut self, jiffies: u64) {
+        self.jiffies = jiffies;
+        let mut i = 0;
+        while i < self.throttled_queue.len() {
+            if self.throttled_queue[i].deadline <= jiffies {
+                let entry = self.throttled_queue.swap_remove(i);
+                self.add(entry.task);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+impl Scheduler for DeadlineScheduler {
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let (deadline, throttled) = {
+            let mut task_inner = task.inner.lock();
+            let sched_entity = &mut task_inner.sched_entity;
+            if sched_entity.dl_deadline <= self.jiffies {
+                // 已经过了截止时间，开始新的周期
+                sched_entity.dl_deadline = self.jiffies + sched_entity.dl_period;
+                sched_entity.dl_budget = sched_entity.dl_runtime;
+            }
+            (sched_entity.dl_deadline, sched_entity.dl_budget == 0)
+        };
+        if throttled {
+            self.throttled_queue.push(DeadlineEntry { deadline, task });
+        } else {
+            self.ready_queue.push(DeadlineEntry { deadline, task });
+        }
+    }
+
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let index = self.ready_queue
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, entry)| entry.deadline)
+            .map(|(index, _)| index)?;
+        Some(self.ready_queue.remove(index).task)
+    }
+
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) {
+        self.ready_queue.retain(|entry| !Arc::ptr_eq(&entry.task, task));
+        self.throttled_queue.retain(|entry| !Arc::ptr_eq(&entry.task, task));
+    }
+
+    /// 被限流的线程不计入就绪的线程
+    fn ready_count(&self) -> usize {
+        self.ready_queue.len()
+    }
+
+    fn tick(&mut self, current: &Arc<TaskControlBlock>) -> bool {
+        let mut task_inner = current.inner.lock();
+        let sched_entity = &mut task_inner.sched_entity;
+        sched_entity.dl_budget = sched_entity.dl_budget.saturating_sub(1);
+        sched_entity.dl_budget == 0
+            || self.ready_queue.iter().any(|entry| entry.deadline < sched_entity.dl_deadline)
+    }
+
+    /// 用完当前周期的运行时间时被限流，有截止时间更早的线程就绪时需要切换
+    fn ticks_until_resched(&self, current: &Arc<TaskControlBlock>) -> u64 {
+        let task_inner = current.inner.lock();
+        let sched_entity = &task_inner.sched_entity;
+        if self.ready_queue.iter().any(|entry| entry.deadline < sched_entity.dl_deadline) {
+            return 1;
+        }
+        sched_entity.dl_budget.max(1)
+    }
+}
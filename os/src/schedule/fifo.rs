@@ -0,0 +1,39 @@
+use alloc::{collections::VecDeque, sync::Arc};
+
+use crate::process::TaskControlBlock;
+use super::scheduler::Scheduler;
+
+/// 先来先服务的轮转调度，忽略线程的优先级
+pub struct FifoScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl FifoScheduler {
+    pub fn new() -> Self {
+        Self { ready_queue: VecDeque::new() }
+    }
+}
+
+impl Scheduler for FifoScheduler {
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queue.pop_front()
+    }
+
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) {
+        if let Some((id, _)) = self
+            .ready_queue
+            .iter()
+            .enumerate()
+            .find(|(_, t)| { Arc::as_ptr(t) == Arc::as_ptr(task) }) {
+            self.ready_queue.remove(id);
+        }
+    }
+
+    fn ready_count(&self) -> usize {
+        self.ready_queue.len()
+    }
+}
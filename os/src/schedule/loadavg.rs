@@ -0,0 +1,58 @@
+use alloc::sync::Arc;
+use spin::Mutex;
+
+use crate::timer::IRQ0_FREQUENCY;
+
+/// 负载均值的定点数表示，1.0 表示为 FIXED_1
+pub const FSHIFT: usize = 11;
+const FIXED_1: usize = 1 << FSHIFT;
+/// 每隔多少个时钟中断计算一次负载均值，即 5 秒
+const LOAD_FREQ: u64 = 5 * IRQ0_FREQUENCY as u64;
+/// 1 分钟、5 分钟、15 分钟的衰减系数 FIXED_1 / exp(5s / 1min) 等，和 Linux 相同
+const EXP: [usize; 3] = [1884, 2014, 2037];
+
+/// 所有 CPU 的使用情况
+pub struct CpuStat {
+    /// 没有线程运行的时钟中断数，所有 CPU 累加
+    pub idle_ticks: u64,
+    /// 1 分钟、5 分钟、15 分钟的平均可运行线程数，定点数
+    pub loads: [usize; 3],
+    /// 距离上次计算负载均值的时钟中断数
+    load_ticks: u64,
+}
+
+impl CpuStat {
+    pub fn new() -> Self {
+        Self { idle_ticks: 0, loads: [0; 3], load_ticks: 0 }
+    }
+
+    /// 这个 CPU 空闲地经过了 ticks 个时钟中断周期，时钟中断停止期间的空闲时间在恢复时补上
+    pub fn tick_idle(&mut self, ticks: u64) {
+        self.idle_ticks += ticks;
+    }
+
+    /// 经过 ticks 个时钟中断周期后调用，nr_running 返回所有 CPU 上正在运行和就绪的线程数，只在需要时计算；
+    /// BSP 的时钟中断停止了多个计算周期时，按照恢复时的线程数补算
+    pub fn update_loads(&mut self, ticks: u64, nr_running: impl FnOnce() -> usize) {
+        self.load_ticks += ticks;
+        if self.load_ticks >= LOAD_FREQ {
+            let periods = self.load_ticks / LOAD_FREQ;
+            self.load_ticks %= LOAD_FREQ;
+            let active = nr_running() * FIXED_1;
+            for _ in 0..periods {
+                for (load, exp) in self.loads.iter_mut().zip(EXP) {
+                    *load = (*load * exp + active * (FIXED_1 - exp)) >> FSHIFT;
+                }
+            }
+        }
+    }
+
+    /// 距离下一次计算负载均值的时钟中断数
+    pub fn ticks_until_load_update(&self) -> u64 {
+        LOAD_FREQ - self.load_ticks
+    }
+}
+
+lazy_static! {
+    pub static ref CPU_STAT: Arc<Mutex<CpuStat>> = Arc::new(Mutex::new(CpuStat::new()));
+}
@@ -1,58 +1,295 @@
 use core::option::Option;
-use alloc::{collections::{BTreeMap, VecDeque}, sync::Arc};
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, vec::Vec};
 use spin::Mutex;
 
+use crate::config::MAX_CPU_COUNT;
 use crate::process::{ProcessControlBlock, TaskControlBlock};
+use crate::smp::{cpu_id, online_cpus, send_reschedule};
+use crate::timer::get_jiffies;
+use super::deadline::DeadlineScheduler;
+use super::rt::RtScheduler;
+use super::scheduler::{new_scheduler, SchedPolicy, Scheduler};
 
 
+/// 每隔多少个时钟中断在 CPU 之间均衡一次负载
+const BALANCE_INTERVAL: u64 = 10;
+
+/// 按调度类管理一个 CPU 的就绪线程，依次从 Deadline、实时、普通调度类中选择线程
 pub struct TaskManager {
-    ready_queue: VecDeque<Arc<TaskControlBlock>>
+    deadline: DeadlineScheduler,
+    rt: RtScheduler,
+    normal: Box<dyn Scheduler>,
+    /// 正在运行的线程所在调度类的等级，见 class_rank
+    current_rank: usize,
+    /// 需要在中断或者系统调用返回前切换线程：唤醒了更高调度类的线程，或者当前线程用完了时间片
+    need_resched: bool,
+}
+
+/// 调度类的等级，值越大优先级越高，没有正在运行的线程时为 0
+fn class_rank(policy: SchedPolicy) -> usize {
+    match policy {
+        SchedPolicy::Normal => 1,
+        SchedPolicy::Fifo | SchedPolicy::RoundRobin => 2,
+        SchedPolicy::Deadline => 3,
+    }
 }
 
 impl TaskManager {
-    pub fn new() -> Self {
-        Self { ready_queue: VecDeque::new() }
+    pub fn new(normal: Box<dyn Scheduler>) -> Self {
+        Self {
+            deadline: DeadlineScheduler::new(),
+            rt: RtScheduler::new(),
+            normal,
+            current_rank: 0,
+            need_resched: false,
+        }
+    }
+
+    fn scheduler_of(&mut self, policy: SchedPolicy) -> &mut dyn Scheduler {
+        match policy {
+            SchedPolicy::Deadline => &mut self.deadline,
+            SchedPolicy::Fifo | SchedPolicy::RoundRobin => &mut self.rt,
+            SchedPolicy::Normal => self.normal.as_mut(),
+        }
     }
 
     pub fn add(&mut self, task: Arc<TaskControlBlock>) {
-        self.ready_queue.push_back(task);
+        let policy = task.inner.lock().sched_entity.policy;
+        self.update_deadline_clock(policy);
+        self.scheduler_of(policy).add(task);
+    }
+
+    /// 空闲的 CPU 停止了时钟中断，Deadline 调度类的时钟可能过时，加入 Deadline 线程前先更新
+    fn update_deadline_clock(&mut self, policy: SchedPolicy) {
+        if policy == SchedPolicy::Deadline {
+            self.deadline.update_clock(get_jiffies());
+        }
+    }
+
+    /// 被限流的 Deadline 线程中最早恢复运行的时间，单位是时钟中断
+    pub fn next_deadline_replenish(&self) -> Option<u64> {
+        self.deadline.next_replenish()
     }
 
     pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
-        self.ready_queue.pop_front()
+        self.need_resched = false;
+        let (rank, task) = if let Some(task) = self.deadline.fetch() {
+            (class_rank(SchedPolicy::Deadline), Some(task))
+        } else if let Some(task) = self.rt.fetch() {
+            (class_rank(SchedPolicy::Fifo), Some(task))
+        } else {
+            (class_rank(SchedPolicy::Normal), self.normal.fetch())
+        };
+        self.current_rank = if task.is_some() { rank } else { 0 };
+        task
+    }
+
+    /// 取出一个就绪的线程迁移到其他 CPU，Deadline 线程的截止时间按本 CPU 的时钟计算，不迁移
+    pub fn steal(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.rt.steal().or_else(|| self.normal.steal())
     }
 
+    /// 调用者可能持有线程的锁，不能读取线程的调度类，从所有调度类中移除
     pub fn remove(&mut self, task: Arc<TaskControlBlock>) {
-        if let Some((id, _)) = self
-            .ready_queue
-            .iter()
-            .enumerate()
-            .find(|(_, t)| { Arc::as_ptr(t) == Arc::as_ptr(&task) }) {
-            self.ready_queue.remove(id);
+        self.deadline.remove(&task);
+        self.rt.remove(&task);
+        self.normal.remove(&task);
+    }
+
+    pub fn wakeup(&mut self, task: Arc<TaskControlBlock>) {
+        let policy = task.inner.lock().sched_entity.policy;
+        if class_rank(policy) > self.current_rank {
+            self.need_resched = true;
         }
+        self.update_deadline_clock(policy);
+        self.scheduler_of(policy).wakeup(task);
+    }
+
+    /// 所有调度类中就绪的线程数
+    pub fn ready_count(&self) -> usize {
+        self.deadline.ready_count() + self.rt.ready_count() + self.normal.ready_count()
+    }
+
+    /// 有可以迁移到其他 CPU 的就绪线程，Deadline 线程不迁移
+    pub fn has_stealable(&self) -> bool {
+        self.rt.ready_count() + self.normal.ready_count() > 0
+    }
+
+    /// 就绪和正在运行的线程数
+    pub fn load(&self) -> usize {
+        self.ready_count() + !self.is_idle() as usize
+    }
+
+    /// CPU 上没有正在运行的线程
+    pub fn is_idle(&self) -> bool {
+        self.current_rank == 0
+    }
+
+    pub fn need_resched(&self) -> bool {
+        self.need_resched
+    }
+
+    pub fn set_need_resched(&mut self) {
+        self.need_resched = true;
+    }
+
+    /// 返回内容：是否需要切换线程，同时清除标志
+    pub fn take_need_resched(&mut self) -> bool {
+        core::mem::replace(&mut self.need_resched, false)
+    }
+
+    /// 时钟中断时调用，current 为 None 表示 CPU 空闲
+    /// 返回内容：是否需要切换线程，高优先级的调度类中有就绪的线程时总是切换；CPU 空闲时空闲循环会选择线程，不需要切换
+    pub fn tick(&mut self, current: Option<&Arc<TaskControlBlock>>) -> bool {
+        self.deadline.update_clock(get_jiffies());
+        let current = match current {
+            Some(current) => current,
+            None => return false,
+        };
+        let policy = current.inner.lock().sched_entity.policy;
+        let need_resched = self.take_need_resched();
+        need_resched || match policy {
+            SchedPolicy::Deadline => self.deadline.tick(current),
+            SchedPolicy::Fifo | SchedPolicy::RoundRobin => self.deadline.has_ready() || self.rt.tick(current),
+            SchedPolicy::Normal => {
+                self.deadline.has_ready() || self.rt.highest_priority().is_some() || self.normal.tick(current)
+            }
+        }
+    }
+
+    /// 正在运行的线程 current 最多再经过多少个时钟中断，tick 可能返回需要切换，或者被限流的 Deadline 线程恢复运行
+    pub fn ticks_until_resched(&self, current: &Arc<TaskControlBlock>) -> u64 {
+        let policy = current.inner.lock().sched_entity.policy;
+        let ticks = match policy {
+            SchedPolicy::Deadline => self.deadline.ticks_until_resched(current),
+            SchedPolicy::Fifo | SchedPolicy::RoundRobin if self.deadline.has_ready() => 1,
+            SchedPolicy::Fifo | SchedPolicy::RoundRobin => self.rt.ticks_until_resched(current),
+            SchedPolicy::Normal if self.deadline.has_ready() || self.rt.highest_priority().is_some() => 1,
+            SchedPolicy::Normal => self.normal.ticks_until_resched(current),
+        };
+        let replenish = self.deadline.next_replenish().map_or(u64::MAX, |jiffies| jiffies.saturating_sub(get_jiffies()).max(1));
+        ticks.min(replenish)
     }
 }
 
 lazy_static! {
-    pub static ref TASK_MANAGER: Arc<Mutex<TaskManager>> = Arc::new(Mutex::new(TaskManager::new()));
+    /// 每个 CPU 一个就绪队列，同一时间最多锁住其中一个，避免 CPU 之间互相等待
+    pub static ref TASK_MANAGERS: Vec<Mutex<TaskManager>> = (0..MAX_CPU_COUNT).map(|_| Mutex::new(TaskManager::new(new_scheduler()))).collect();
     pub static ref PID2PCB: Arc<Mutex<BTreeMap<usize, Arc<ProcessControlBlock>>>> = Arc::new(Mutex::new(BTreeMap::new()));
 }
 
+/// 当前 CPU 的就绪队列
+pub fn local_task_manager() -> &'static Mutex<TaskManager> {
+    &TASK_MANAGERS[cpu_id()]
+}
+
+/// 选择负载最小的 CPU，负载相同时优先选择 prev_cpu
+fn select_cpu(prev_cpu: usize) -> usize {
+    let mut target = (prev_cpu, usize::MAX);
+    for cpu in online_cpus().filter(|&cpu| cpu != prev_cpu) {
+        let load = TASK_MANAGERS[cpu].lock().load();
+        if load < target.1 {
+            target = (cpu, load);
+        }
+    }
+    let prev_load = TASK_MANAGERS[prev_cpu].lock().load();
+    if prev_load <= target.1 { prev_cpu } else { target.0 }
+}
+
+/// 线程加入 cpu 的就绪队列，wakeup 表示阻塞的线程被唤醒；
+/// 目标 CPU 空闲或者需要抢占正在运行的线程时，发送 IPI 让它重新调度
+fn enqueue_task(cpu: usize, task: Arc<TaskControlBlock>, wakeup: bool) {
+    task.inner.lock().sched_entity.cpu = cpu;
+    let mut manager = TASK_MANAGERS[cpu].lock();
+    if wakeup {
+        manager.wakeup(task);
+    } else {
+        manager.add(task);
+    }
+    let need_ipi = cpu != cpu_id() && (manager.is_idle() || manager.need_resched());
+    drop(manager);
+    if need_ipi {
+        send_reschedule(cpu);
+    }
+}
+
+/// 新建的线程加入负载最小的 CPU
 pub fn add_task(task: Arc<TaskControlBlock>) {
-    let mut manager = TASK_MANAGER.lock();
-    manager.add(task);
+    let prev_cpu = task.inner.lock().sched_entity.cpu;
+    enqueue_task(select_cpu(prev_cpu), task, false);
 }
 
+/// 让出 CPU 或者被抢占的线程留在当前 CPU
+pub fn add_task_to_local(task: Arc<TaskControlBlock>) {
+    enqueue_task(cpu_id(), task, false);
+}
+
+/// 被唤醒的线程优先放回上次运行的 CPU
+pub fn add_woken_task(task: Arc<TaskControlBlock>) {
+    let prev_cpu = task.inner.lock().sched_entity.cpu;
+    enqueue_task(select_cpu(prev_cpu), task, true);
+}
+
+/// 线程可能在任意 CPU 的就绪队列中
 pub fn remove_task(task: Arc<TaskControlBlock>) {
-    let mut manager = TASK_MANAGER.lock();
-    manager.remove(task);
+    for manager in TASK_MANAGERS.iter() {
+        manager.lock().remove(task.clone());
+    }
 }
 
+/// 从当前 CPU 的就绪队列中取出线程，没有就绪的线程时从就绪线程最多的 CPU 上迁移一个
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
-    let mut manager = TASK_MANAGER.lock();
+    let cpu = cpu_id();
+    if let Some(task) = TASK_MANAGERS[cpu].lock().fetch() {
+        return Some(task);
+    }
+    let busiest = online_cpus()
+        .filter(|&other| other != cpu)
+        .map(|other| (other, TASK_MANAGERS[other].lock().ready_count()))
+        .filter(|&(_, ready_count)| ready_count > 0)
+        .max_by_key(|&(_, ready_count)| ready_count)?
+        .0;
+    let task = TASK_MANAGERS[busiest].lock().steal()?;
+    task.inner.lock().sched_entity.cpu = cpu;
+    let mut manager = TASK_MANAGERS[cpu].lock();
+    manager.add(task);
     manager.fetch()
 }
 
+/// 时钟中断时调用，经过了 ticks 个时钟中断周期；每隔 BALANCE_INTERVAL 个时钟中断从负载最大的 CPU 拉一个就绪的线程，
+/// 两个 CPU 的负载相差超过 1 时才迁移，避免线程来回迁移
+pub fn load_balance(ticks: u64) {
+    if get_jiffies() % BALANCE_INTERVAL >= ticks {
+        return;
+    }
+    let cpu = cpu_id();
+    let local_load = TASK_MANAGERS[cpu].lock().load();
+    let busiest = online_cpus()
+        .filter(|&other| other != cpu)
+        .map(|other| (other, TASK_MANAGERS[other].lock().load()))
+        .max_by_key(|&(_, load)| load);
+    let Some((busiest, busiest_load)) = busiest else {
+        return;
+    };
+    if busiest_load <= local_load + 1 {
+        return;
+    }
+    let task = TASK_MANAGERS[busiest].lock().steal();
+    if let Some(task) = task {
+        enqueue_task(cpu, task, false);
+    }
+}
+
+/// 距离下一次均衡负载的时钟中断数
+pub fn ticks_until_load_balance() -> u64 {
+    BALANCE_INTERVAL - get_jiffies() % BALANCE_INTERVAL
+}
+
+/// 所有 CPU 上就绪和正在运行的线程数
+pub fn nr_running() -> usize {
+    online_cpus().map(|cpu| TASK_MANAGERS[cpu].lock().load()).sum()
+}
+
 pub fn pid2process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
     let map = PID2PCB.lock();
     map.get(&pid).map(Arc::clone)
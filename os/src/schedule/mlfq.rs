@@ -0,0 +1,104 @@
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+
+use crate::process::TaskControlBlock;
+use super::scheduler::Scheduler;
+
+/// 队列数
+const MLFQ_LEVEL_COUNT: usize = 4;
+/// 每个队列的时间片，单位是时钟中断，优先级越低时间片越长
+const MLFQ_TIME_SLICE: [usize; MLFQ_LEVEL_COUNT] = [1, 2, 4, 8];
+/// 每隔多少个时钟中断把所有线程提升到最高优先级的队列，避免低优先级的线程饿死
+const MLFQ_BOOST_TICKS: usize = 100;
+
+/// 多级反馈队列：
+/// 1. 总是运行优先级最高的非空队列中的线程，同一个队列中轮转
+/// 2. 线程用完所在队列的时间片后降低一级，主动让出 CPU 不会重置已用的时间片
+/// 3. 阻塞后被唤醒的线程（等待键盘输入、睡眠）提升一级
+/// 4. 定期把所有线程提升到最高优先级的队列
+/// 线程的 nice 值不影响调度
+pub struct MlfqScheduler {
+    ready_queues: Vec<VecDeque<Arc<TaskControlBlock>>>,
+    /// 距离上次提升优先级的时钟中断数
+    boost_ticks: usize,
+}
+
+impl MlfqScheduler {
+    pub fn new() -> Self {
+        let mut ready_queues = Vec::new();
+        for _ in 0..MLFQ_LEVEL_COUNT {
+            ready_queues.push(VecDeque::new());
+        }
+        Self { ready_queues, boost_ticks: 0 }
+    }
+
+    /// 把所有线程移到最高优先级的队列
+    fn boost(&mut self, current: &Arc<TaskControlBlock>) {
+        for level in 1..MLFQ_LEVEL_COUNT {
+            while let Some(task) = self.ready_queues[level].pop_front() {
+                self.ready_queues[0].push_back(task);
+            }
+        }
+        for task in self.ready_queues[0].iter().chain(core::iter::once(current)) {
+            let mut task_inner = task.inner.lock();
+            task_inner.sched_entity.level = 0;
+            task_inner.sched_entity.ticks = 0;
+        }
+    }
+}
+
+impl Scheduler for MlfqScheduler {
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let level = task.inner.lock().sched_entity.level;
+        self.ready_queues[level].push_back(task);
+    }
+
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queues.iter_mut().find_map(|ready_queue| ready_queue.pop_front())
+    }
+
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) {
+        for ready_queue in self.ready_queues.iter_mut() {
+            ready_queue.retain(|t| !Arc::ptr_eq(t, task));
+        }
+    }
+
+    fn ready_count(&self) -> usize {
+        self.ready_queues.iter().map(|ready_queue| ready_queue.len()).sum()
+    }
+
+    fn wakeup(&mut self, task: Arc<TaskControlBlock>) {
+        {
+            let mut task_inner = task.inner.lock();
+            let sched_entity = &mut task_inner.sched_entity;
+            sched_entity.level = sched_entity.level.saturating_sub(1);
+            sched_entity.ticks = 0;
+        }
+        self.add(task);
+    }
+
+    fn tick(&mut self, current: &Arc<TaskControlBlock>) -> bool {
+        self.boost_ticks += 1;
+        if self.boost_ticks >= MLFQ_BOOST_TICKS {
+            self.boost_ticks = 0;
+            self.boost(current);
+        }
+        let mut task_inner = current.inner.lock();
+        let sched_entity = &mut task_inner.sched_entity;
+        sched_entity.ticks += 1;
+        if sched_entity.ticks >= MLFQ_TIME_SLICE[sched_entity.level] {
+            sched_entity.level = (sched_entity.level + 1).min(MLFQ_LEVEL_COUNT - 1);
+            sched_entity.ticks = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 用完所在队列的时间片或者需要提升所有线程的优先级时
+    fn ticks_until_resched(&self, current: &Arc<TaskControlBlock>) -> u64 {
+        let task_inner = current.inner.lock();
+        let sched_entity = &task_inner.sched_entity;
+        let slice_left = MLFQ_TIME_SLICE[sched_entity.level].saturating_sub(sched_entity.ticks);
+        slice_left.min(MLFQ_BOOST_TICKS - self.boost_ticks).max(1) as u64
+    }
+}
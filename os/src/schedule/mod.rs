@@ -2,35 +2,69 @@ use core::option::Option;
 use core::option::Option::Some;
 use core::option::Option::None;
 use core::mem::drop;
+use core::sync::atomic::Ordering;
 use alloc::{sync::Arc, task, vec::Vec};
 pub use manager::*;
 pub use processor::current_task;
 pub use processor::current_process;
-use processor::{schedule, take_current_task};
+pub use processor::{schedule, take_current_task};
 use spin::Mutex;
 use switch::__switch;
 
+use crate::arch::x86::Cr2;
+use crate::clock::{nohz, time_ns};
 use crate::config::*;
 use crate::intr::*;
 use crate::mm::*;
 use crate::process::KERNEL_PROCESS;
 use crate::{config::MEMORY_PAGE_SIZE, intr::IntrContext, mm::{MapArea, MapPermission, MemorySet, PageTable, PhysAddr, VPNRange, VirtAddr}, process::{ProcessControlBlock, ProcessControlBlockInner, TaskContext, TaskControlBlock, TaskControlBlockInner, TaskStatus}};
 use crate::programs::PROGRAMS;
+use crate::smp::cpu_id;
+use processor::switch_to_kernel_page_table;
 
 mod switch;
 mod manager;
 mod processor;
+mod oom;
+mod scheduler;
+mod rt;
+mod deadline;
+mod loadavg;
+#[cfg(feature = "sched_fifo")]
+mod fifo;
+#[cfg(all(feature = "sched_mlfq", not(feature = "sched_fifo")))]
+mod mlfq;
+#[cfg(not(any(feature = "sched_fifo", feature = "sched_mlfq")))]
+mod stride;
 
 pub use processor::run_tasks;
+pub use oom::*;
+pub use loadavg::{CPU_STAT, FSHIFT};
+pub use scheduler::{clamp_nice, SchedEntity, SchedParam, SchedPolicy, DEFAULT_NICE, MAX_RT_PRIORITY, MIN_RT_PRIORITY};
 
+/// 当前线程主动让出 CPU
 pub fn suspend_current_and_run_next() {
+    switch_current_and_run_next(true);
+}
+
+/// 当前线程被抢占，用完了时间片或者有更高优先级的线程就绪
+pub fn preempt_current_and_run_next() {
+    switch_current_and_run_next(false);
+}
+
+fn switch_current_and_run_next(voluntary: bool) {
     check_current_process_status();
     if let Some(task) = take_current_task() {
         let mut task_inner = task.inner.lock();
         let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
         task_inner.status = TaskStatus::Ready;
+        if voluntary {
+            task_inner.times.nvcsw += 1;
+        } else {
+            task_inner.times.nivcsw += 1;
+        }
         drop(task_inner);
-        add_task(task);
+        add_task_to_local(task);
         schedule(task_cx_ptr);
     } else {
         assert!(false);
@@ -42,6 +76,7 @@ pub fn block_current_and_run_next() {
         let mut task_inner = task.inner.lock();
         let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
         task_inner.status = TaskStatus::Block;
+        task_inner.times.nvcsw += 1;
         drop(task_inner);
         schedule(task_cx_ptr);
     } else {
@@ -53,7 +88,85 @@ pub fn wakeup_task(task: Arc<TaskControlBlock>) {
     let mut task_inner = task.inner.lock();
     task_inner.status = TaskStatus::Ready;
     drop(task_inner);
-    add_task(task);
+    add_woken_task(task);
+}
+
+/// 时钟中断时调用，user_mode 表示中断前 CPU 是否在用户态，ticks 是距离上次调用经过的时钟中断周期数，
+/// 周期性的时钟中断为 1，线程运行时按时间片设置的单次中断可能大于 1
+/// 返回内容：当前线程是否用完时间片或者有更高优先级的线程就绪，需要切换线程
+pub fn tick_current_task(user_mode: bool, ticks: u64) -> bool {
+    let task = current_task();
+    // 内核态的运行时间在进出内核时统计
+    if let Some(task) = task.as_ref().filter(|_| user_mode) {
+        task.inner.lock().times.utime += ticks;
+    }
+    {
+        let mut cpu_stat = CPU_STAT.lock();
+        if task.is_none() {
+            cpu_stat.tick_idle(ticks);
+        }
+        // 负载均值统计所有 CPU，只由 BSP 计算
+        if cpu_id() == 0 {
+            cpu_stat.update_loads(ticks, nr_running);
+        }
+    }
+    load_balance(ticks);
+    let mut manager = local_task_manager().lock();
+    if manager.has_stealable() {
+        nohz::kick_idle_cpu();
+    }
+    // 需要切换时不再统计剩下的时钟中断，新的时间片从切换后开始
+    (0..ticks).any(|_| manager.tick(task.as_ref()))
+}
+
+/// 正在运行的线程 task 最多再经过多少个时钟中断需要处理：可能需要切换线程、被限流的 Deadline 线程恢复运行或者均衡负载
+pub fn ticks_until_next_tick(task: &Arc<TaskControlBlock>) -> u64 {
+    let ticks = local_task_manager().lock().ticks_until_resched(task);
+    ticks.min(ticks_until_load_balance())
+}
+
+/// 从用户态陷入内核（中断、异常和系统调用）时调用
+pub fn account_kernel_entry() {
+    if let Some(task) = current_task() {
+        task.inner.lock().enter_kernel(time_ns());
+    }
+}
+
+/// 返回用户态前调用
+pub fn account_kernel_exit() {
+    if let Some(task) = current_task() {
+        task.inner.lock().leave_kernel(time_ns());
+    }
+}
+
+/// 时钟中断停止时的单次中断调用，更新 Deadline 调度类的时钟，被限流的线程到期后进入下一个周期
+pub fn update_idle_clock() {
+    local_task_manager().lock().tick(None);
+}
+
+/// 时钟中断停止期间当前 CPU 一直空闲，经过了 ticks 个时钟中断周期
+pub fn account_idle_ticks(ticks: u64) {
+    let mut cpu_stat = CPU_STAT.lock();
+    cpu_stat.tick_idle(ticks);
+    if cpu_id() == 0 {
+        cpu_stat.update_loads(ticks, nr_running);
+    }
+}
+
+/// 当前线程需要在中断返回前被抢占
+pub fn set_need_resched() {
+    local_task_manager().lock().set_need_resched();
+}
+
+/// 唤醒了更高优先级的线程时抢占当前线程，在中断和系统调用返回前调用
+pub fn preempt_if_need_resched() {
+    if current_task().is_none() {
+        return;
+    }
+    let need_resched = local_task_manager().lock().take_need_resched();
+    if need_resched {
+        preempt_current_and_run_next();
+    }
 }
 
 pub fn exit_current_and_run_next(exit_code: isize) -> ! {
@@ -68,7 +181,7 @@ pub fn exit_current_and_run_next(exit_code: isize) -> ! {
 
     let mut process_inner = process.inner.lock();
 
-    if tid == 0 {
+    if tid == process_inner.main_tid {
         let pid = process.get_pid();
         remove_from_pid2process(pid);
         process_inner.exit_code = Some(exit_code);
@@ -89,8 +202,9 @@ pub fn exit_current_and_run_next(exit_code: isize) -> ! {
             .for_each(|task_option| {
                 let task_option = task_option.as_ref();
                 if let Some(task) = task_option {
-                    let task_inner = task.inner.lock();
-                    if task_inner.status == TaskStatus::Ready {
+                    // 其他 CPU 加入就绪队列时会锁住线程，移除前先释放线程的锁
+                    let is_ready = task.inner.lock().status == TaskStatus::Ready;
+                    if is_ready {
                         remove_task(task.clone());
                     }
                 }
@@ -105,6 +219,10 @@ pub fn exit_current_and_run_next(exit_code: isize) -> ! {
             }) 
         }) {
         process_inner.is_zombie = true;
+        // 不必等父进程回收，提前释放用户空间的内存
+        process_inner.recycle_user_memory();
+        // 父进程可能在其他 CPU 上立即回收进程，释放页目录表前切换到内核的页表
+        switch_to_kernel_page_table();
     }
     
     drop(process_inner);
@@ -114,19 +232,39 @@ pub fn exit_current_and_run_next(exit_code: isize) -> ! {
     panic!("unreachable after sys_exit!");
 }
 
+/// 当前线程被其他线程的 exec 销毁时不再返回用户态，在进入和离开内核时调用；
+/// 线程已经从进程中移除，切换出去后在 run_tasks 中释放
+pub fn exit_current_if_killed() {
+    let is_killed = current_task().is_some_and(|task| task.killed.load(Ordering::SeqCst));
+    if !is_killed {
+        return;
+    }
+    drop(take_current_task());
+    let mut _unused = TaskContext::empty();
+    schedule(&mut _unused as *mut _);
+    panic!("unreachable after killed by exec!");
+}
+
 pub fn check_current_process_status() {
     let task = current_task().unwrap();
     let process = task.process.upgrade().unwrap();
     let process_inner = process.inner.lock();
 
-    if process_inner.exit_code.is_some() {
+    if let Some(exit_code) = process_inner.exit_code {
         drop(process_inner);
         drop(process);
         drop(task);
-        exit_current_and_run_next(0);
+        exit_current_and_run_next(exit_code);
     }
 }
 
+/// 缺页异常错误码：P 位为 1 表示访问的页存在，是权限错误
+const PAGE_FAULT_PRESENT: usize = 1;
+/// 缺页异常错误码：I/D 位为 1 表示取指令时发生的异常
+const PAGE_FAULT_INSTRUCTION_FETCH: usize = 1 << 4;
+/// 非法访问内存时进程的退出码
+pub const SEGMENTATION_FAULT_EXIT_CODE: isize = -11;
+
 fn page_fault_intr_handler(intr_context: &mut IntrContext) {
     let intr = intr_context.intr;
     let error_code = intr_context.error_code;
@@ -134,24 +272,50 @@ fn page_fault_intr_handler(intr_context: &mut IntrContext) {
     let cs = intr_context.cs;
     let esp = intr_context.esp;
     let ss = intr_context.ss;
-    let task = current_task().unwrap();
-    let process = task.process.upgrade().unwrap();
-    let pid = process.get_pid();
+    let pid = current_process().unwrap().get_pid();
     assert_ne!(eip, 0, "page_fault_intr_handler cs {:#x} eip {:#x} ss {:#x} esp {:#x} error code {} {} pid {}", cs, eip, ss, esp, error_code, IrqErrorCode(error_code), pid);
     // debug!("intr #{}({:#x}) error code {} {} eip {:#x} cs {:#x} esp {:#x} ss {:#x} ebp {:#x}", intr, intr, error_code, IrqErrorCode(error_code), eip, cs, esp, ss, intr_context.ebp);
+    let fault_va = VirtAddr(Cr2::read());
+    if error_code & PAGE_FAULT_PRESENT != 0 && error_code & PAGE_FAULT_INSTRUCTION_FETCH != 0 {
+        // 在不可执行的页上取指令，杀死进程
+        warn!("pid {} execute non-executable page at {:#x} eip {:#x}", pid, fault_va.0, eip);
+        let process = current_process().unwrap();
+        process.inner.lock().exit_code = Some(SEGMENTATION_FAULT_EXIT_CODE);
+        drop(process);
+        check_current_process_status();
+    }
+    // 物理页不足时杀死一个进程后重试。OOM killer 选中的可能就是当前进程：它的 exit_code 被设置、用户空间被释放，
+    // handle_out_of_memory 中的 check_current_process_status 让当前线程退出，不会返回；
+    // 其他线程杀死当前进程时，下一次循环开头的 check_current_process_status 同样让当前线程退出，不会对已经释放的用户空间重试
+    loop {
+        check_current_process_status();
+        match repair_current_page_fault(fault_va) {
+            Some(true) => break,
+            Some(false) => panic!("page fault at {:#x} pid {} not repaired", fault_va.0, pid),
+            None => {
+                if !handle_out_of_memory() {
+                    panic!("out of memory when repairing page fault at {:#x} pid {}", fault_va.0, pid);
+                }
+            }
+        }
+    }
+
+    assert_ne!(intr_context.eip, 0, "page_fault_intr_handler end with intr_context.eip=0");
+}
+
+/// 返回内容：是否有修复页表，None 表示物理页不足
+fn repair_current_page_fault(fault_va: VirtAddr) -> Option<bool> {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
     let mut process_inner = process.inner.lock();
-    let mut is_repaired = process_inner.repair_page_fault();
+    let mut is_repaired = process_inner.repair_page_fault(fault_va)?;
     
+    let stack_limit = process_inner.user_stack_limit;
     let memory_set = &mut process_inner.memory_set;
     let page_table = &mut memory_set.page_table;
     let mut task_inner = task.inner.lock();
-    is_repaired |= task_inner.repair_page_fault(page_table);
-    if !is_repaired {
-        // if no repair operation, something error, exit process
-        assert!(false);
-    }
-
-    assert_ne!(intr_context.eip, 0, "page_fault_intr_handler end with intr_context.eip=0");
+    is_repaired |= task_inner.repair_page_fault(page_table, fault_va, stack_limit)?;
+    Some(is_repaired)
 }
 
 pub fn init() {
@@ -160,6 +324,7 @@ pub fn init() {
         // 构建 0 号进程
         let kernel_process = &KERNEL_PROCESS;
         assert_eq!(kernel_process.get_pid(), 0);
+        processor::init_kernel_cr3(kernel_process.inner.lock().memory_set.page_table.cr3_address());
     }
     {
         // 构建 1 号进程
@@ -178,7 +343,7 @@ lazy_static! {
     static ref INITPROC_PROCESS: Arc<ProcessControlBlock> = {
         let programs = PROGRAMS.lock();
         let elf: &'static [u8] = programs.get("initproc").unwrap();
-        let process = ProcessControlBlock::from_elf_file(elf);
+        let process = ProcessControlBlock::from_elf_file(elf).unwrap();
         let mut inner = process.inner.lock();
         inner.elf_data = Some(elf);
         assert_eq!(process.get_pid(), 1);
@@ -220,19 +385,19 @@ pub fn test() {
     let app_1_data: &'static [u8] = programs.get("hello_world_a").unwrap();
     let app_2_data: &'static [u8] = programs.get("hello_world_b").unwrap();
 
-    let process0 = ProcessControlBlock::from_elf_file(app_0_data);
+    let process0 = ProcessControlBlock::from_elf_file(app_0_data).unwrap();
     let task0 = {
         let mut inner = process0.inner.lock();
         inner.elf_data = Some(app_0_data);
         inner.tasks[0].as_ref().map(|task| task.clone()).unwrap()
     };
-    let process1 = ProcessControlBlock::from_elf_file(app_1_data);
+    let process1 = ProcessControlBlock::from_elf_file(app_1_data).unwrap();
     let task1 = {
         let mut inner = process1.inner.lock();
         inner.elf_data = Some(app_1_data);
         inner.tasks[0].as_ref().map(|task| task.clone()).unwrap()
     };
-    let process2 = ProcessControlBlock::from_elf_file(app_2_data);
+    let process2 = ProcessControlBlock::from_elf_file(app_2_data).unwrap();
     let task2 = {
         let mut inner = process2.inner.lock();
         inner.elf_data = Some(app_2_data);
@@ -240,19 +405,19 @@ pub fn test() {
     };
 
     info!("thread_0 address {:#x}", thread_0 as usize);
-    let process3 = ProcessControlBlock::new_kernel_process(thread_0 as usize);
+    let process3 = ProcessControlBlock::new_kernel_process(thread_0 as usize).unwrap();
     let task3 = {
         let inner = process3.inner.lock();
         inner.tasks[0].as_ref().map(|task| task.clone()).unwrap()
     };
     info!("thread_1 address {:#x}", thread_1 as usize);
-    let process4 = ProcessControlBlock::new_kernel_process(thread_1 as usize);
+    let process4 = ProcessControlBlock::new_kernel_process(thread_1 as usize).unwrap();
     let task4 = {
         let inner = process4.inner.lock();
         inner.tasks[0].as_ref().map(|task| task.clone()).unwrap()
     };
     info!("do_nothing address {:#x}", do_nothing as usize);
-    let process5 = ProcessControlBlock::new_kernel_process(do_nothing as usize);
+    let process5 = ProcessControlBlock::new_kernel_process(do_nothing as usize).unwrap();
     let task5 = {
         let inner = process5.inner.lock();
         inner.tasks[0].as_ref().map(|task| task.clone()).unwrap()
@@ -0,0 +1,52 @@
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::process::ProcessControlBlock;
+use super::{check_current_process_status, INITPROC_PROCESS, PID2PCB};
+
+/// 被 OOM killer 杀死的进程的退出码
+pub const OOM_KILL_EXIT_CODE: isize = -9;
+
+/// 物理页和 swap 区都用完时，杀死驻留内存最多的进程，并立即释放它的用户空间
+/// 调用前需要释放持有的进程锁和线程锁
+/// 返回内容：是否有进程被杀死
+pub fn oom_kill() -> bool {
+    let processes: Vec<Arc<ProcessControlBlock>> = PID2PCB.lock().values().cloned().collect();
+    let mut victim: Option<(Arc<ProcessControlBlock>, usize)> = None;
+    warn!("out of memory, memory usage of processes:");
+    for process in processes {
+        let process_inner = process.inner.lock();
+        let usage = process_inner.memory_usage();
+        let (resident_page_count, swapped_page_count) = (usage.resident, usage.swapped);
+        let is_exiting = process_inner.exit_code.is_some();
+        drop(process_inner);
+        warn!("  pid {} resident {} pages swapped {} pages{}", process.get_pid(), resident_page_count, swapped_page_count, if is_exiting { " exiting" } else { "" });
+        // initproc 负责回收孤儿进程，不能被杀死
+        if is_exiting || resident_page_count == 0 || process.get_pid() == INITPROC_PROCESS.get_pid() {
+            continue;
+        }
+        if victim.as_ref().is_none_or(|(_, max_page_count)| resident_page_count > *max_page_count) {
+            victim = Some((process, resident_page_count));
+        }
+    }
+
+    if let Some((process, resident_page_count)) = victim {
+        warn!("oom kill pid {}, free {} pages", process.get_pid(), resident_page_count);
+        let mut process_inner = process.inner.lock();
+        process_inner.exit_code = Some(OOM_KILL_EXIT_CODE);
+        process_inner.recycle_user_memory();
+        true
+    } else {
+        false
+    }
+}
+
+/// 分配物理页失败时调用，杀死一个进程后调用者可以重试分配
+/// 当前进程被杀死时直接退出，不会返回，所以调用者不能持有进程或者线程的引用
+/// 返回内容：是否可以重试
+pub fn handle_out_of_memory() -> bool {
+    if !oom_kill() {
+        return false;
+    }
+    check_current_process_status();
+    true
+}
@@ -1,30 +1,37 @@
 use core::arch::asm;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use alloc::sync::Arc;
-use spin::Mutex;
 
-use crate::config::*;
+use crate::arch::x86::wait_for_intr;
+use crate::clock::{nohz, time_ns};
 use crate::intr;
 use crate::mm::PageTable;
 use crate::process::ProcessControlBlock;
 use crate::process::{TaskContext, TaskControlBlock, TaskStatus};
 use crate::mm::update_tss;
+use crate::percpu;
+use crate::smp::set_active_pdt;
 use super::DATA_SELECTOR;
 use super::{manager::fetch_task, switch::__switch};
 
 
 pub struct Processor {
     current: Option<Arc<TaskControlBlock>>,
+    /// 刚被切换下来的线程，回到空闲循环后才离开它的内核栈，之后其他 CPU 才能运行它
+    prev: Option<Arc<TaskControlBlock>>,
     idle_task_cx: TaskContext,
 }
 
 impl Processor {
-    pub fn new() -> Self {
-        Self { current: None, idle_task_cx: TaskContext::empty() }
+    pub const fn new() -> Self {
+        Self { current: None, prev: None, idle_task_cx: TaskContext::empty() }
     }
 
     pub fn take_current(&mut self) -> Option<Arc<TaskControlBlock>> {
-        self.current.take()
+        let current = self.current.take();
+        self.prev = current.clone();
+        current
     }
 
     pub fn current(&self) -> Option<Arc<TaskControlBlock>> {
@@ -36,20 +43,59 @@ impl Processor {
     }
 }
 
-lazy_static! {
-    pub static ref PROCESSOR: Arc<Mutex<Processor>> = Arc::new(Mutex::new(Processor::new()));
+percpu! {
+    /// 每个 CPU 一个 Processor，只有所在的 CPU 访问
+    static PROCESSOR: Processor = Processor::new();
 }
 
+/// 内核页表的 cr3，空闲循环中使用
+static KERNEL_CR3: AtomicUsize = AtomicUsize::new(0);
+
+pub fn init_kernel_cr3(cr3: usize) {
+    KERNEL_CR3.store(cr3, Ordering::Relaxed);
+}
+
+/// 切换到内核的页表，用户进程退出后它的页表可能被其他 CPU 释放
+pub fn switch_to_kernel_page_table() {
+    let cr3 = KERNEL_CR3.load(Ordering::Relaxed);
+    assert_ne!(cr3, 0);
+    unsafe {
+        asm!("mov cr3, {}", in(reg) cr3);
+    }
+    set_active_pdt(PageTable::pdt_ppn());
+}
+
+/// 每个 CPU 的空闲循环，选择就绪的线程运行
 pub fn run_tasks() {
     loop {
-        let mut processor = PROCESSOR.lock();
+        let prev = PROCESSOR.with_mut(|processor| processor.prev.take());
+        if let Some(prev) = prev {
+            switch_to_kernel_page_table();
+            prev.inner.lock().leave_kernel(time_ns());
+            prev.on_cpu.store(false, Ordering::Release);
+            // 线程已经退出时在这里释放它的内核栈
+            drop(prev);
+        }
         if let Some(task) = fetch_task() {
-            let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
+            nohz::start_task_tick(&task);
+            // 线程刚在其他 CPU 上被切换下来，等那个 CPU 离开它的内核栈
+            while task.on_cpu.load(Ordering::Acquire) {
+                core::hint::spin_loop();
+            }
+            task.on_cpu.store(true, Ordering::SeqCst);
+            // 先标记 on_cpu 再检查，和 exec 中先标记 killed 再检查 on_cpu 对应，
+            // exec 等待结束后被销毁的线程不会再运行，这里直接释放它
+            if task.killed.load(Ordering::SeqCst) {
+                task.on_cpu.store(false, Ordering::Release);
+                continue;
+            }
             let mut task_inner = task.inner.lock();
             let process = task.process.upgrade().unwrap();
             let process_inner = process.inner.lock();
             let next_task_cx_ptr = &task_inner.task_cx as *const TaskContext;
             task_inner.status = TaskStatus::Running;
+            // 切换到线程后先在内核态运行，返回用户态时结束统计
+            task_inner.enter_kernel(time_ns());
             // 更新 tss
             {
                 update_tss(DATA_SELECTOR as usize, task_inner.kernel_stack_top_address.0);
@@ -58,43 +104,46 @@ pub fn run_tasks() {
             let pdt_ppn = process_inner.memory_set.page_table.pdt_ppn;
             // 切换页表
             unsafe {
-                asm!("mov cr3, {}", in(reg) pdt_ppn.base_address().0);
+                asm!("mov cr3, {}", in(reg) process_inner.memory_set.page_table.cr3_address());
             }
             assert_eq!(pdt_ppn, PageTable::pdt_ppn());
+            set_active_pdt(pdt_ppn);
 
             assert_ne!(task_inner.intr_cx.eip, 0, "process {} intr_cx.eip == 0", process.get_pid());
             
             drop(process_inner);
             drop(task_inner);
-            processor.current = Some(task);
-            drop(processor);
+            let idle_task_cx_ptr = PROCESSOR.with_mut(|processor| {
+                processor.current = Some(task);
+                processor.get_idle_task_cx_ptr()
+            });
             unsafe {
                 __switch(idle_task_cx_ptr, next_task_cx_ptr);
             }
         } else {
-            println!("no tasks available in run_tasks");
+            // 没有就绪的线程，停止周期性的时钟中断，停机等待中断或者其他 CPU 的 IPI 唤醒线程
+            nohz::stop_idle_tick();
+            wait_for_intr();
         }
     }
 }
 
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.lock().take_current()
+    PROCESSOR.with_mut(|processor| processor.take_current())
 }
 
 pub fn current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.lock().current()
+    PROCESSOR.with(|processor| processor.current())
 }
 
 pub fn current_process() -> Option<Arc<ProcessControlBlock>> {
-    PROCESSOR.lock().current().map(|task| {
+    PROCESSOR.with(|processor| processor.current()).map(|task| {
         task.process.upgrade().unwrap()
     })
 }
 
 pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
-    let mut processor = PROCESSOR.lock();
-    let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
-    drop(processor);
+    let idle_task_cx_ptr = PROCESSOR.with_mut(|processor| processor.get_idle_task_cx_ptr());
     unsafe {
         __switch(switched_task_cx_ptr, idle_task_cx_ptr)
     }
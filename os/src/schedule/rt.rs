@@ -0,0 +1,82 @@
+use alloc::{collections::{BTreeMap, VecDeque}, sync::Arc};
+
+use crate::process::TaskControlBlock;
+use super::scheduler::{SchedPolicy, Scheduler};
+
+/// RoundRobin 线程的时间片，单位是时钟中断
+const RR_TIME_SLICE: usize = 10;
+
+/// 实时调度类：总是运行优先级最高的线程，同优先级的线程按加入的先后排队
+/// 1. Fifo 线程一直运行到阻塞、让出 CPU 或者有更高优先级的线程就绪
+/// 2. RoundRobin 线程用完时间片后排到同优先级的队尾
+pub struct RtScheduler {
+    /// 优先级到就绪队列的映射，不保留空队列
+    ready_queues: BTreeMap<usize, VecDeque<Arc<TaskControlBlock>>>,
+}
+
+impl RtScheduler {
+    pub fn new() -> Self {
+        Self { ready_queues: BTreeMap::new() }
+    }
+
+    /// 就绪线程中最高的优先级
+    pub fn highest_priority(&self) -> Option<usize> {
+        self.ready_queues.keys().next_back().copied()
+    }
+}
+
+impl Scheduler for RtScheduler {
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let priority = task.inner.lock().sched_entity.rt_priority;
+        self.ready_queues.entry(priority).or_default().push_back(task);
+    }
+
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let mut entry = self.ready_queues.last_entry()?;
+        let task = entry.get_mut().pop_front();
+        if entry.get().is_empty() {
+            entry.remove();
+        }
+        task
+    }
+
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) {
+        for ready_queue in self.ready_queues.values_mut() {
+            ready_queue.retain(|t| !Arc::ptr_eq(t, task));
+        }
+        self.ready_queues.retain(|_, ready_queue| !ready_queue.is_empty());
+    }
+
+    fn ready_count(&self) -> usize {
+        self.ready_queues.values().map(|ready_queue| ready_queue.len()).sum()
+    }
+
+    fn tick(&mut self, current: &Arc<TaskControlBlock>) -> bool {
+        let mut task_inner = current.inner.lock();
+        let sched_entity = &mut task_inner.sched_entity;
+        if self.highest_priority().is_some_and(|priority| priority > sched_entity.rt_priority) {
+            return true;
+        }
+        if sched_entity.policy == SchedPolicy::RoundRobin {
+            sched_entity.ticks += 1;
+            if sched_entity.ticks >= RR_TIME_SLICE {
+                sched_entity.ticks = 0;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Fifo 线程没有时间片，更高优先级的线程就绪时才需要切换
+    fn ticks_until_resched(&self, current: &Arc<TaskControlBlock>) -> u64 {
+        let task_inner = current.inner.lock();
+        let sched_entity = &task_inner.sched_entity;
+        if self.highest_priority().is_some_and(|priority| priority > sched_entity.rt_priority) {
+            return 1;
+        }
+        match sched_entity.policy {
+            SchedPolicy::RoundRobin => RR_TIME_SLICE.saturating_sub(sched_entity.ticks).max(1) as u64,
+            _ => u64::MAX,
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+
+use crate::process::TaskControlBlock;
+#[cfg(feature = "sched_fifo")]
+use super::fifo::FifoScheduler;
+#[cfg(all(feature = "sched_mlfq", not(feature = "sched_fifo")))]
+use super::mlfq::MlfqScheduler;
+#[cfg(not(any(feature = "sched_fifo", feature = "sched_mlfq")))]
+use super::stride::StrideScheduler;
+
+/// nice 值的范围，值越小优先级越高
+pub const MIN_NICE: isize = -20;
+pub const MAX_NICE: isize = 19;
+pub const DEFAULT_NICE: isize = 0;
+
+/// nice 值对应的权重，nice 每差 1 权重大约差 1.25 倍，和 Linux 的 sched_prio_to_weight 相同
+const NICE_TO_WEIGHT: [usize; 40] = [
+    /* -20 */ 88761, 71755, 56483, 46273, 36291,
+    /* -15 */ 29154, 23254, 18705, 14949, 11916,
+    /* -10 */ 9548, 7620, 6100, 4904, 3906,
+    /*  -5 */ 3121, 2501, 1991, 1586, 1277,
+    /*   0 */ 1024, 820, 655, 526, 423,
+    /*   5 */ 335, 272, 215, 172, 137,
+    /*  10 */ 110, 87, 70, 56, 45,
+    /*  15 */ 36, 29, 23, 18, 15,
+];
+
+/// 把 nice 值限制在 [MIN_NICE, MAX_NICE] 中
+pub fn clamp_nice(nice: isize) -> isize {
+    nice.clamp(MIN_NICE, MAX_NICE)
+}
+
+/// 默认的时间片，单位是时钟中断
+pub const DEFAULT_TIME_SLICE: usize = 5;
+
+/// 实时线程的优先级范围，值越大优先级越高
+pub const MIN_RT_PRIORITY: usize = 1;
+pub const MAX_RT_PRIORITY: usize = 99;
+
+/// 调度类，优先级从高到低是 Deadline、Fifo 和 RoundRobin、Normal，
+/// 高优先级的调度类中有就绪的线程时，低优先级的调度类中的线程不会运行
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SchedPolicy {
+    /// 普通线程，由 new_scheduler 创建的调度器调度
+    Normal = 0,
+    /// 固定优先级的实时线程，一直运行到阻塞、让出 CPU 或者有更高优先级的线程就绪
+    Fifo = 1,
+    /// 固定优先级的实时线程，同优先级的线程按时间片轮转
+    RoundRobin = 2,
+    /// 最早截止时间优先，每个周期最多运行 runtime 个时钟中断
+    Deadline = 6,
+}
+
+impl SchedPolicy {
+    pub fn from_usize(policy: usize) -> Option<Self> {
+        match policy {
+            0 => Some(Self::Normal),
+            1 => Some(Self::Fifo),
+            2 => Some(Self::RoundRobin),
+            6 => Some(Self::Deadline),
+            _ => None,
+        }
+    }
+}
+
+/// sched_setscheduler 的参数，和用户程序中的定义保持一致
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct SchedParam {
+    /// Fifo 和 RoundRobin 的优先级，范围是 [MIN_RT_PRIORITY, MAX_RT_PRIORITY]
+    pub priority: usize,
+    /// Deadline 每个周期可以运行的时间，单位是毫秒
+    pub runtime_ms: usize,
+    /// Deadline 的周期，截止时间是周期的结束时间，单位是毫秒
+    pub period_ms: usize,
+}
+
+/// 线程的调度信息，由调度器读写
+#[derive(Clone, Copy)]
+pub struct SchedEntity {
+    pub policy: SchedPolicy,
+    pub nice: isize,
+    /// Fifo 和 RoundRobin 的优先级
+    pub rt_priority: usize,
+    /// 步长调度中线程累计的行程
+    pub pass: u64,
+    /// 多级反馈队列中线程所在的队列，0 的优先级最高
+    pub level: usize,
+    /// 当前时间片剩下的时钟中断数
+    pub time_slice: usize,
+    /// 在当前队列中已经使用的时钟中断数，RoundRobin 也用它记录已经使用的时间片
+    pub ticks: usize,
+    /// Deadline 每个周期可以运行的时钟中断数
+    pub dl_runtime: u64,
+    /// Deadline 的周期，单位是时钟中断
+    pub dl_period: u64,
+    /// 当前周期的截止时间，单位是时钟中断
+    pub dl_deadline: u64,
+    /// 当前周期剩下的可以运行的时钟中断数
+    pub dl_budget: u64,
+    /// 最近一次加入的就绪队列所在的 CPU，唤醒时优先放回这个 CPU
+    pub cpu: usize,
+}
+
+impl SchedEntity {
+    pub fn new(nice: isize) -> Self {
+        Self {
+            policy: SchedPolicy::Normal,
+            nice: clamp_nice(nice),
+            rt_priority: 0,
+            pass: 0,
+            time_slice: DEFAULT_TIME_SLICE,
+            level: 0,
+            ticks: 0,
+            dl_runtime: 0,
+            dl_period: 0,
+            dl_deadline: 0,
+            dl_budget: 0,
+            cpu: 0,
+        }
+    }
+
+    pub fn weight(&self) -> usize {
+        NICE_TO_WEIGHT[(self.nice - MIN_NICE) as usize]
+    }
+}
+
+/// 调度策略，TaskManager 通过它管理就绪队列
+pub trait Scheduler: Send {
+    /// 线程变为就绪状态
+    fn add(&mut self, task: Arc<TaskControlBlock>);
+    /// 取出下一个要运行的线程
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>>;
+    /// 取出一个线程迁移到其他 CPU，默认和 fetch 相同
+    fn steal(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.fetch()
+    }
+    /// 从就绪队列中移除线程，调用者可能持有线程的锁
+    fn remove(&mut self, task: &Arc<TaskControlBlock>);
+    /// 就绪的线程数
+    fn ready_count(&self) -> usize;
+    /// 阻塞的线程被唤醒，例如等到了键盘输入或者睡眠结束
+    fn wakeup(&mut self, task: Arc<TaskControlBlock>) {
+        self.add(task);
+    }
+    /// 时钟中断时调用，current 是正在运行的线程
+    /// 返回内容：是否需要切换线程，默认在用完 DEFAULT_TIME_SLICE 个时钟中断后切换
+    fn tick(&mut self, current: &Arc<TaskControlBlock>) -> bool {
+        let mut task_inner = current.inner.lock();
+        let sched_entity = &mut task_inner.sched_entity;
+        sched_entity.time_slice = sched_entity.time_slice.saturating_sub(1);
+        if sched_entity.time_slice == 0 {
+            sched_entity.time_slice = DEFAULT_TIME_SLICE;
+            true
+        } else {
+            false
+        }
+    }
+    /// 正在运行的线程最多再经过多少个时钟中断，tick 可能返回需要切换；线程运行时按它设置单次中断
+    /// 返回内容：默认是当前时间片剩下的时钟中断数
+    fn ticks_until_resched(&self, current: &Arc<TaskControlBlock>) -> u64 {
+        current.inner.lock().sched_entity.time_slice as u64
+    }
+}
+
+/// 默认使用步长调度；开启 sched_fifo 特性时使用先来先服务的轮转调度，开启 sched_mlfq 特性时使用多级反馈队列
+#[cfg(not(any(feature = "sched_fifo", feature = "sched_mlfq")))]
+pub fn new_scheduler() -> Box<dyn Scheduler> {
+    Box::new(StrideScheduler::new())
+}
+
+#[cfg(feature = "sched_fifo")]
+pub fn new_scheduler() -> Box<dyn Scheduler> {
+    Box::new(FifoScheduler::new())
+}
+
+#[cfg(all(feature = "sched_mlfq", not(feature = "sched_fifo")))]
+pub fn new_scheduler() -> Box<dyn Scheduler> {
+    Box::new(MlfqScheduler::new())
+}
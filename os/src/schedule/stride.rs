@@ -0,0 +1,85 @@
+use core::cmp::Ordering;
+use alloc::{collections::BinaryHeap, sync::Arc};
+
+use crate::process::TaskControlBlock;
+use super::scheduler::Scheduler;
+
+/// 权重为 1 的线程每次运行增加的行程，nice 为 0 的线程步长为 BIG_STRIDE / 1024
+const BIG_STRIDE: u64 = 1 << 20;
+
+struct StrideEntry {
+    pass: u64,
+    /// 行程相同时先加入的线程先运行
+    seq: u64,
+    task: Arc<TaskControlBlock>,
+}
+
+impl PartialEq for StrideEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.pass == other.pass && self.seq == other.seq
+    }
+}
+impl Eq for StrideEntry {}
+impl PartialOrd for StrideEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// BinaryHeap 是大顶堆，行程小的排在前面
+impl Ord for StrideEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.pass, other.seq).cmp(&(self.pass, self.seq))
+    }
+}
+
+/// 步长调度：每次选择行程最小的线程运行，运行后行程增加 BIG_STRIDE / 权重，
+/// 权重大的线程获得更多的时间片，权重小的线程行程增长得慢，也不会被饿死
+pub struct StrideScheduler {
+    ready_queue: BinaryHeap<StrideEntry>,
+    /// 最近一次调度的线程的行程，新加入或者睡眠后唤醒的线程从这里开始，不能一直占用 CPU
+    min_pass: u64,
+    seq: u64,
+}
+
+impl StrideScheduler {
+    pub fn new() -> Self {
+        Self { ready_queue: BinaryHeap::new(), min_pass: 0, seq: 0 }
+    }
+}
+
+impl Scheduler for StrideScheduler {
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let pass = {
+            let mut task_inner = task.inner.lock();
+            let sched_entity = &mut task_inner.sched_entity;
+            sched_entity.pass = sched_entity.pass.max(self.min_pass);
+            sched_entity.pass
+        };
+        self.seq += 1;
+        self.ready_queue.push(StrideEntry { pass, seq: self.seq, task });
+    }
+
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let entry = self.ready_queue.pop()?;
+        self.min_pass = entry.pass;
+        let mut task_inner = entry.task.inner.lock();
+        let sched_entity = &mut task_inner.sched_entity;
+        sched_entity.pass = entry.pass + BIG_STRIDE / sched_entity.weight() as u64;
+        drop(task_inner);
+        Some(entry.task)
+    }
+
+    /// 迁移的线程没有在这个 CPU 上运行，不增加行程，也不更新 min_pass
+    fn steal(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queue.pop().map(|entry| entry.task)
+    }
+
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) {
+        self.ready_queue.retain(|entry| !Arc::ptr_eq(&entry.task, task));
+    }
+
+    fn ready_count(&self) -> usize {
+        self.ready_queue.len()
+    }
+}
@@ -0,0 +1,254 @@
+//! 多处理器：BSP 通过 INIT-SIPI-SIPI 启动其他 CPU（AP），AP 从实模式的启动代码进入内核后运行自己的空闲循环。
+//! CPU 编号从 0 开始，0 号是 BSP；每个 CPU 的 TSS 描述符在 GDT 中的下标是 TSS_GDT_INDEX + CPU 编号，
+//! 通过 str 指令就可以知道当前 CPU 的编号；每个 CPU 的数据区通过 gs 访问，见 percpu。
+//! CPU 之间通过 IPI 通知重新调度、转发时钟中断，通过 NMI 通知刷新 TLB。
+
+pub mod percpu;
+
+use core::arch::{asm, global_asm};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::arch::x86::{outb, Cr0, Cr4, Efer, PteFlags};
+use crate::clock::nohz;
+use crate::config::*;
+use crate::intr::{self, apic, IntrContext, INTR_HANDLER_TABLE};
+use crate::mm::{alloc_kernel_virt_frame, alloc_phys_frame, PageTable, PhysPageNum, VirtAddr, VirtPageNum};
+use crate::process::KERNEL_PROCESS;
+use crate::schedule::{self, set_need_resched, tick_current_task};
+
+global_asm!(include_str!("trampoline.S"));
+
+/// 通知 CPU 重新调度的 IPI，只需要打断空闲循环或者在中断返回前检查是否需要切换线程
+const IPI_RESCHEDULE_VECTOR: usize = 0x30;
+/// BSP 收到时钟中断后转发给其他 CPU，用于统计时间和切换线程
+const IPI_TIMER_VECTOR: usize = 0x31;
+
+/// 在线的 CPU，第 i 位对应 i 号 CPU
+static ONLINE_CPUS: AtomicUsize = AtomicUsize::new(1);
+/// 等待刷新 TLB 的 CPU，第 i 位对应 i 号 CPU
+static TLB_FLUSH_PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// 每个 CPU 的 APIC ID
+static CPU_APIC_IDS: [AtomicUsize; MAX_CPU_COUNT] = [const { AtomicUsize::new(0) }; MAX_CPU_COUNT];
+/// 每个 CPU 正在使用的页目录表的物理页号，只修改用户空间时只需要通知使用同一个页表的 CPU
+static ACTIVE_PDT: [AtomicUsize; MAX_CPU_COUNT] = [const { AtomicUsize::new(0) }; MAX_CPU_COUNT];
+
+/// 传给启动代码的参数，和 trampoline.S 中 ap_boot_params 的布局一致
+#[repr(C)]
+struct ApBootParams {
+    cr3: u32,
+    cr4: u32,
+    /// 为 0 时不设置 EFER
+    efer: u32,
+    cr0: u32,
+    stack_top: u32,
+    entry: u32,
+    cpu: u32,
+}
+
+/// 当前 CPU 的编号
+pub fn cpu_id() -> usize {
+    let selector: u16;
+    unsafe {
+        asm!("str ax", out("ax") selector);
+    }
+    // 加载 TSS 之前 str 读出 0，这时只有 BSP 在运行
+    ((selector >> 3) as usize).saturating_sub(TSS_GDT_INDEX as usize)
+}
+
+/// 在线的 CPU 的编号
+pub fn online_cpus() -> impl Iterator<Item = usize> {
+    let online = ONLINE_CPUS.load(Ordering::Acquire);
+    (0..MAX_CPU_COUNT).filter(move |cpu| online & (1 << cpu) != 0)
+}
+
+pub fn online_cpu_count() -> usize {
+    ONLINE_CPUS.load(Ordering::Acquire).count_ones() as usize
+}
+
+/// 切换页表后记录当前 CPU 正在使用的页目录表
+pub fn set_active_pdt(pdt_ppn: PhysPageNum) {
+    ACTIVE_PDT[cpu_id()].store(pdt_ppn.0, Ordering::Release);
+}
+
+fn apic_id(cpu: usize) -> u32 {
+    CPU_APIC_IDS[cpu].load(Ordering::Relaxed) as u32
+}
+
+/// 让 cpu 重新调度：打断它的空闲循环，或者在中断返回前检查是否需要切换线程
+pub fn send_reschedule(cpu: usize) {
+    apic::send_ipi(apic_id(cpu), IPI_RESCHEDULE_VECTOR);
+}
+
+/// BSP 的时钟中断处理函数调用，把时钟中断转发给其他 CPU
+pub fn send_timer_tick_to_others() {
+    let this_cpu = cpu_id();
+    for cpu in online_cpus().filter(|&cpu| cpu != this_cpu) {
+        apic::send_ipi(apic_id(cpu), IPI_TIMER_VECTOR);
+    }
+}
+
+/// 修改页表后通知其他 CPU 刷新 TLB，等所有 CPU 刷新完才返回。
+/// pdt_ppn 为 None 表示修改的是内核空间，所有 CPU 都需要刷新；否则只通知正在使用这个页表的 CPU。
+/// 等待的 CPU 可能关中断自旋，所以用 NMI 通知
+pub fn flush_tlb_others(pdt_ppn: Option<PhysPageNum>) {
+    let this_cpu = cpu_id();
+    let targets = online_cpus()
+        .filter(|&cpu| cpu != this_cpu)
+        .filter(|&cpu| pdt_ppn.is_none_or(|ppn| ACTIVE_PDT[cpu].load(Ordering::Acquire) == ppn.0))
+        .fold(0usize, |mask, cpu| mask | (1 << cpu));
+    if targets == 0 {
+        return;
+    }
+    TLB_FLUSH_PENDING.fetch_or(targets, Ordering::AcqRel);
+    for cpu in 0..MAX_CPU_COUNT {
+        if targets & (1 << cpu) != 0 {
+            apic::send_nmi(apic_id(cpu));
+        }
+    }
+    while TLB_FLUSH_PENDING.load(Ordering::Acquire) & targets != 0 {
+        core::hint::spin_loop();
+    }
+}
+
+/// NMI 处理函数，可能打断持有锁的代码，不能获取任何锁
+pub fn handle_nmi() {
+    let bit = 1 << cpu_id();
+    if TLB_FLUSH_PENDING.load(Ordering::Acquire) & bit != 0 {
+        // 先刷新再清除标记，发送方看到标记被清除时 TLB 已经刷新
+        PageTable::flush_tlb_all();
+        TLB_FLUSH_PENDING.fetch_and(!bit, Ordering::AcqRel);
+    }
+}
+
+/// 其他 CPU 加入了定时器时，按最早到期的定时器重新设置线程运行时的单次中断
+fn reschedule_ipi_handler(_intr_context: &mut IntrContext) {
+    nohz::reprogram_task_tick();
+    apic::send_eoi();
+}
+
+fn timer_ipi_handler(intr_context: &mut IntrContext) {
+    if tick_current_task(intr_context.cs & 0b11 == 0b11, 1) {
+        set_need_resched();
+    }
+    apic::send_eoi();
+}
+
+/// 等待大约 us 微秒，启动 AP 时还没有开中断，不能使用时钟中断计时；写 0x80 端口大约需要 1 微秒
+fn udelay(us: usize) {
+    for _ in 0..us {
+        outb(0, 0x80);
+    }
+}
+
+/// 启动固件表中列出的其他 CPU，没有 APIC 时只使用 BSP
+pub fn start_aps() {
+    if !apic::is_enabled() {
+        return;
+    }
+    extern "C" {
+        fn ap_trampoline_start();
+        fn ap_trampoline_end();
+        fn ap_boot_params();
+    }
+    let bsp_apic_id = apic::local_apic_id();
+    CPU_APIC_IDS[0].store(bsp_apic_id as usize, Ordering::Relaxed);
+    set_active_pdt(PageTable::pdt_ppn());
+    {
+        let mut intr_handler_table = INTR_HANDLER_TABLE.lock();
+        intr_handler_table[IPI_RESCHEDULE_VECTOR] = reschedule_ipi_handler;
+        intr_handler_table[IPI_TIMER_VECTOR] = timer_ipi_handler;
+    }
+    let ap_apic_ids: alloc::vec::Vec<u32> = apic::cpu_apic_ids().into_iter().filter(|&id| id != bsp_apic_id).collect();
+    if ap_apic_ids.is_empty() {
+        return;
+    }
+
+    // 启动代码复制到 1MiB 以内，AP 开启分页的那条指令之后仍然在低地址运行，需要临时的恒等映射
+    let trampoline_size = ap_trampoline_end as *const () as usize - ap_trampoline_start as *const () as usize;
+    assert!(trampoline_size <= MEMORY_PAGE_SIZE);
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            ap_trampoline_start as *const u8,
+            (HIGH_ADDRESS_BASE + AP_TRAMPOLINE_PHYS_ADDRESS) as *mut u8,
+            trampoline_size,
+        );
+    }
+    let trampoline_vpn = VirtPageNum::from(VirtAddr(AP_TRAMPOLINE_PHYS_ADDRESS));
+    let cr3 = {
+        let mut kernel_process_inner = KERNEL_PROCESS.inner.lock();
+        let page_table = &mut kernel_process_inner.memory_set.page_table;
+        page_table.map_with_create_pde(trampoline_vpn, PhysPageNum(trampoline_vpn.0), PteFlags::P | PteFlags::RW).unwrap();
+        page_table.cr3_address()
+    };
+    let params = (HIGH_ADDRESS_BASE + AP_TRAMPOLINE_PHYS_ADDRESS + (ap_boot_params as *const () as usize - ap_trampoline_start as *const () as usize)) as *mut ApBootParams;
+    #[cfg(feature = "pae")]
+    let efer = if crate::mm::is_nx_enabled() { Efer::read().bits() as u32 } else { 0 };
+    #[cfg(not(feature = "pae"))]
+    let efer = 0;
+
+    for (idx, &ap_apic_id) in ap_apic_ids.iter().enumerate() {
+        let cpu = idx + 1;
+        if cpu >= MAX_CPU_COUNT {
+            warn!("smp: more than {} cpus, ignore the rest", MAX_CPU_COUNT);
+            break;
+        }
+        CPU_APIC_IDS[cpu].store(ap_apic_id as usize, Ordering::Relaxed);
+        // AP 的空闲循环使用的内核栈，栈底留一个 guard page，AP 一直运行，栈不会释放
+        let stack_vstub = alloc_kernel_virt_frame(KERNEL_STACK_PAGE_SIZE + 1).unwrap();
+        let stack_pstub = alloc_phys_frame(KERNEL_STACK_PAGE_SIZE).unwrap();
+        for page in 0..KERNEL_STACK_PAGE_SIZE {
+            PageTable::static_map(VirtPageNum(stack_vstub.base_vpn.0 + 1 + page), PhysPageNum(stack_pstub.base_ppn.0 + page), PteFlags::P | PteFlags::RW);
+        }
+        let stack_top = VirtPageNum(stack_vstub.base_vpn.0 + 1 + KERNEL_STACK_PAGE_SIZE).base_address().0;
+        core::mem::forget(stack_vstub);
+        core::mem::forget(stack_pstub);
+        crate::mm::init_cpu_gdt(cpu);
+
+        unsafe {
+            params.write_volatile(ApBootParams {
+                cr3: cr3 as u32,
+                cr4: Cr4::read().bits(),
+                efer,
+                cr0: Cr0::read().bits(),
+                stack_top: stack_top as u32,
+                entry: ap_entry as *const () as usize as u32,
+                cpu: cpu as u32,
+            });
+        }
+        apic::send_init(ap_apic_id);
+        udelay(10_000);
+        for _ in 0..2 {
+            apic::send_startup(ap_apic_id, AP_TRAMPOLINE_PHYS_ADDRESS >> 12);
+            udelay(200);
+        }
+        let mut waited_us = 0;
+        while ONLINE_CPUS.load(Ordering::Acquire) & (1 << cpu) == 0 && waited_us < 100_000 {
+            udelay(100);
+            waited_us += 100;
+        }
+        if ONLINE_CPUS.load(Ordering::Acquire) & (1 << cpu) == 0 {
+            // 启动参数被这个 AP 占用，不再启动后面的 AP
+            warn!("smp: cpu {} (apic id {}) did not start", cpu, ap_apic_id);
+            break;
+        }
+    }
+
+    KERNEL_PROCESS.inner.lock().memory_set.page_table.unmap(trampoline_vpn);
+    info!("smp: {} cpus online", online_cpu_count());
+}
+
+/// AP 开启分页后从启动代码跳转到这里，运行在自己的内核栈上
+extern "C" fn ap_entry(cpu: usize) -> ! {
+    crate::mm::load_cpu_gdt(cpu);
+    intr::load_idt();
+    apic::init_ap();
+    crate::clock::init_ap();
+    assert_eq!(cpu_id(), cpu);
+    assert_eq!(apic::local_apic_id(), apic_id(cpu));
+    set_active_pdt(PageTable::pdt_ppn());
+    ONLINE_CPUS.fetch_or(1 << cpu, Ordering::AcqRel);
+    info!("smp: cpu {} online", cpu);
+    schedule::run_tasks();
+    panic!("unreachable after run_tasks!");
+}
@@ -0,0 +1,138 @@
+//! 每个 CPU 一份的变量。
+//! percpu! 定义的变量放在 .percpu 段中作为模板，每个 CPU 启动前复制一份模板作为自己的数据区。
+//! GDT 中 PERCPU_GDT_INDEX 处的数据段描述符的基址指向数据区，内核运行时 gs 总是加载 PERCPU_SELECTOR，
+//! 数据区开头保存数据区自己的线性地址，变量的地址 = 数据区地址 + 头部大小 + 变量在模板中的偏移。
+//! 模板本身不会被访问，变量只能在 mm::init 设置好 BSP 的数据区之后使用。
+
+use core::alloc::Layout;
+use core::arch::asm;
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::config::MAX_CPU_COUNT;
+use crate::intr::softirq::without_intr;
+
+/// 数据区的对齐，模板中的变量按照不超过它的对齐排列
+const PERCPU_AREA_ALIGN: usize = 64;
+
+/// 数据区的头部，gs:[0] 是数据区的线性地址
+#[repr(C, align(64))]
+struct PerCpuHeader {
+    self_address: usize,
+}
+
+/// 每个 CPU 的数据区的地址
+static AREA_ADDRESS: [AtomicUsize; MAX_CPU_COUNT] = [const { AtomicUsize::new(0) }; MAX_CPU_COUNT];
+
+/// 定义每个 CPU 一份的变量，类型为 PerCpu<T>，初始值需要是常量
+/// ```ignore
+/// percpu! {
+///     static COUNTER: usize = 0;
+/// }
+/// COUNTER.with_mut(|counter| *counter += 1);
+/// ```
+#[macro_export]
+macro_rules! percpu {
+    ($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $init:expr;)+) => {
+        $(
+            $(#[$attr])*
+            #[link_section = ".percpu"]
+            $vis static $name: $crate::smp::percpu::PerCpu<$ty> = $crate::smp::percpu::PerCpu::new($init);
+        )+
+    };
+}
+
+struct PerCpuCell<T> {
+    /// 大于 0 表示共享借用的次数，-1 表示可变借用，只在所在的 CPU 上关中断修改
+    borrow: isize,
+    value: T,
+}
+
+/// 每个 CPU 一份的变量，只能通过 percpu! 定义；
+/// 访问时关中断，中断处理函数不会同时访问，当前线程也不会被切换到其他 CPU
+pub struct PerCpu<T> {
+    template: UnsafeCell<PerCpuCell<T>>,
+}
+
+/// 每个 CPU 只访问自己的副本，值需要能在 CPU 之间转移
+unsafe impl<T: Send> Sync for PerCpu<T> {}
+
+impl<T> PerCpu<T> {
+    pub const fn new(value: T) -> Self {
+        Self { template: UnsafeCell::new(PerCpuCell { borrow: 0, value }) }
+    }
+
+    /// 副本在数据区 area 中的地址
+    fn cell_in(&self, area: usize) -> *mut PerCpuCell<T> {
+        extern "C" {
+            fn spercpu();
+        }
+        let offset = self.template.get() as usize - spercpu as *const () as usize;
+        (area + core::mem::size_of::<PerCpuHeader>() + offset) as *mut PerCpuCell<T>
+    }
+
+    /// 关中断共享借用当前 CPU 的副本
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        without_intr(|| {
+            let cell = self.cell_in(this_cpu_area());
+            unsafe {
+                assert!((*cell).borrow >= 0, "percpu variable already mutably borrowed");
+                (*cell).borrow += 1;
+                let result = f(&(*cell).value);
+                (*cell).borrow -= 1;
+                result
+            }
+        })
+    }
+
+    /// 关中断可变借用当前 CPU 的副本，同一个 CPU 上嵌套借用同一个变量会 panic
+    pub fn with_mut<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        without_intr(|| {
+            let cell = self.cell_in(this_cpu_area());
+            unsafe {
+                assert_eq!((*cell).borrow, 0, "percpu variable already borrowed");
+                (*cell).borrow = -1;
+                let result = f(&mut (*cell).value);
+                (*cell).borrow = 0;
+                result
+            }
+        })
+    }
+
+    /// cpu 的副本的地址，用于把副本的地址交给硬件，例如 TSS 描述符
+    pub fn as_ptr_on(&self, cpu: usize) -> *mut T {
+        let area = AREA_ADDRESS[cpu].load(Ordering::Acquire);
+        assert_ne!(area, 0, "percpu area of cpu {} is not initialized", cpu);
+        unsafe { core::ptr::addr_of_mut!((*self.cell_in(area)).value) }
+    }
+}
+
+/// 当前 CPU 的数据区的地址
+fn this_cpu_area() -> usize {
+    let area: usize;
+    unsafe {
+        asm!("mov {}, gs:[0]", out(reg) area);
+    }
+    area
+}
+
+/// 为 cpu 分配数据区并复制模板，返回内容：数据区的地址和大小，用于设置 GDT 中的描述符
+pub fn init_area(cpu: usize) -> (usize, usize) {
+    extern "C" {
+        fn spercpu();
+        fn epercpu();
+    }
+    assert_eq!(AREA_ADDRESS[cpu].load(Ordering::Acquire), 0);
+    let template_start = spercpu as *const () as usize;
+    let template_size = epercpu as *const () as usize - template_start;
+    let size = core::mem::size_of::<PerCpuHeader>() + template_size;
+    let layout = Layout::from_size_align(size, PERCPU_AREA_ALIGN).unwrap();
+    let area = unsafe { alloc::alloc::alloc(layout) } as usize;
+    assert_ne!(area, 0);
+    unsafe {
+        (area as *mut PerCpuHeader).write(PerCpuHeader { self_address: area });
+        core::ptr::copy_nonoverlapping(template_start as *const u8, (area + core::mem::size_of::<PerCpuHeader>()) as *mut u8, template_size);
+    }
+    AREA_ADDRESS[cpu].store(area, Ordering::Release);
+    (area, size)
+}
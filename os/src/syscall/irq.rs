@@ -0,0 +1,46 @@
+use alloc::vec::Vec;
+
+use crate::intr::irq::irq_stats;
+
+/// 中断线名字的最大长度，多个共享中断的设备名用逗号分隔，超出部分被截断
+pub const IRQ_NAME_LEN: usize = 32;
+
+/// 单条中断线的统计信息
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IrqInfo {
+    pub irq: usize,
+    /// 中断次数
+    pub count: u64,
+    /// 没有处理函数处理的中断次数
+    pub spurious_count: u64,
+    /// 注册的设备名，以 0 结尾
+    pub names: [u8; IRQ_NAME_LEN],
+}
+
+/// 功能：获取已经注册了处理函数的中断线的中断次数，按中断线从小到大排列。
+/// 参数：infos 表示保存中断统计的数组的地址，len 表示数组的长度。
+/// 返回值：写入数组的中断线数，多于 len 时只写入前 len 个；infos 为 0 时返回 -1 。
+/// syscall ID：1042
+pub fn sys_irqinfo(infos: *mut IrqInfo, len: usize) -> isize {
+    if infos.is_null() {
+        return -1;
+    }
+    let irq_infos: Vec<IrqInfo> = irq_stats().into_iter()
+        .filter(|stat| !stat.names.is_empty())
+        .take(len)
+        .map(|stat| {
+            let mut names = [0u8; IRQ_NAME_LEN];
+            let joined = stat.names.join(",");
+            let name_len = joined.len().min(IRQ_NAME_LEN - 1);
+            names[..name_len].copy_from_slice(&joined.as_bytes()[..name_len]);
+            IrqInfo { irq: stat.irq, count: stat.count, spurious_count: stat.spurious_count, names }
+        })
+        .collect();
+    // 写用户空间可能触发写时复制的缺页异常，写之前不能持有锁
+    let dst = unsafe {
+        core::slice::from_raw_parts_mut(infos, irq_infos.len())
+    };
+    dst.copy_from_slice(&irq_infos);
+    irq_infos.len() as isize
+}
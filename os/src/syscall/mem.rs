@@ -0,0 +1,97 @@
+use alloc::collections::BTreeSet;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use crate::config::*;
+use crate::mm::heap_allocator::heap_usage;
+use crate::mm::{phys_frame_usage, MemoryUsage};
+use crate::process::ProcessControlBlock;
+use crate::schedule::PID2PCB;
+
+/// 系统全局的内存使用情况
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct MemInfo {
+    /// 可以分配给内核和用户程序的物理页总数
+    pub total_frames: usize,
+    /// 空闲的物理页数
+    pub free_frames: usize,
+    /// 内核堆的总字节数
+    pub heap_total: usize,
+    /// 内核堆已经分配的字节数
+    pub heap_used: usize,
+    /// 页表占用的页数，包括内核空间预先分配的页表，fork 后共享的 pte page 只计算一次
+    pub page_table_pages: usize,
+}
+
+/// 单个进程的内存使用情况
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct ProcessInfo {
+    pub pid: usize,
+    /// 父进程的 PID，没有父进程时为 0
+    pub ppid: usize,
+    /// 没有退出的线程数
+    pub thread_count: usize,
+    /// 进程是否已经退出，等待父进程回收
+    pub is_zombie: usize,
+    pub nice: isize,
+    pub memory_usage: MemoryUsage,
+}
+
+/// 功能：获取系统全局的内存使用情况。
+/// 参数：info 表示保存内存使用情况的地址。
+/// 返回值：成功返回 0 ；info 为 0 时返回 -1 。
+/// syscall ID：1040
+pub fn sys_meminfo(info: *mut MemInfo) -> isize {
+    if info.is_null() {
+        return -1;
+    }
+    let (total_frames, free_frames) = phys_frame_usage();
+    let (heap_total, heap_used) = heap_usage();
+    let processes: Vec<Arc<ProcessControlBlock>> = PID2PCB.lock().values().cloned().collect();
+    let mut page_table_frames = BTreeSet::new();
+    for process in processes {
+        let process_inner = process.inner.lock();
+        page_table_frames.extend(process_inner.memory_set.page_table.page_table_frames().iter().map(|ppn| ppn.0));
+    }
+    let meminfo = MemInfo {
+        total_frames,
+        free_frames,
+        heap_total,
+        heap_used,
+        page_table_pages: KERNEL_PAGE_TABLE_DATA_SIZE / MEMORY_PAGE_SIZE + page_table_frames.len(),
+    };
+    // 写用户空间可能触发写时复制的缺页异常，写之前不能持有进程锁
+    unsafe {
+        *info = meminfo;
+    }
+    0
+}
+
+/// 功能：获取所有进程的内存使用情况，按 PID 从小到大排列。
+/// 参数：infos 表示保存进程信息的数组的地址，len 表示数组的长度。
+/// 返回值：写入数组的进程数，进程数多于 len 时只写入前 len 个；infos 为 0 时返回 -1 。
+/// syscall ID：1041
+pub fn sys_procinfo(infos: *mut ProcessInfo, len: usize) -> isize {
+    if infos.is_null() {
+        return -1;
+    }
+    let processes: Vec<Arc<ProcessControlBlock>> = PID2PCB.lock().values().take(len).cloned().collect();
+    let process_infos: Vec<ProcessInfo> = processes.iter().map(|process| {
+        let process_inner = process.inner.lock();
+        ProcessInfo {
+            pid: process.get_pid(),
+            ppid: process_inner.parent.as_ref().and_then(|parent| parent.upgrade()).map_or(0, |parent| parent.get_pid()),
+            thread_count: process_inner.tasks.iter().flatten().count(),
+            is_zombie: process_inner.is_zombie as usize,
+            nice: process_inner.nice,
+            memory_usage: process_inner.memory_usage(),
+        }
+    }).collect();
+    let dst = unsafe {
+        core::slice::from_raw_parts_mut(infos, process_infos.len())
+    };
+    dst.copy_from_slice(&process_infos);
+    process_infos.len() as isize
+}
@@ -3,15 +3,21 @@ mod process;
 mod thread;
 mod io;
 mod sync;
+mod shm;
+mod mem;
+mod irq;
 
 use define::*;
 use process::*;
 use thread::*;
 use io::*;
 use sync::*;
+use shm::*;
+use mem::*;
+use irq::*;
 
 use crate::{intr::{set_ldt_entry, IntrContext, INTR_HANDLER_TABLE}, schedule::current_task, timer::get_time_in_millisecond};
-use crate::schedule::check_current_process_status;
+use crate::schedule::{check_current_process_status, SchedParam};
 
 
 pub fn init() {
@@ -42,6 +48,10 @@ fn syscall_intr_handler(intr_context: &mut IntrContext) {
         SYSCALL_YIELD => sys_yield(),
         SYSCALL_GET_TIME => sys_get_time(),
         SYSCALL_GETPID => sys_getpid(),
+        SYSCALL_SHM_OPEN => sys_shm_open(param1 as *const u8, param2),
+        SYSCALL_SHM_UNLINK => sys_shm_unlink(param1 as *const u8),
+        SYSCALL_SHM_ATTACH => sys_shm_attach(param1, param2),
+        SYSCALL_SHM_DETACH => sys_shm_detach(param1),
         SYSCALL_FORK => sys_fork(),
         SYSCALL_EXEC => sys_exec(param1 as *const u8, param2 as *const usize, intr_context),
         SYSCALL_WAITPID => sys_waitpid(param1 as isize, param2 as *mut isize),
@@ -57,6 +67,16 @@ fn syscall_intr_handler(intr_context: &mut IntrContext) {
         SYSCALL_CONDVAR_CREATE => sys_condvar_create(),
         SYSCALL_CONDVAR_SIGNAL => sys_condvar_signal(param1),
         SYSCALL_CONDVAR_WAIT => sys_condvar_wait(param1, param2),
+        SYSCALL_SETPRIORITY => sys_setpriority(param1, param2 as isize),
+        SYSCALL_GETPRIORITY => sys_getpriority(param1),
+        SYSCALL_SCHED_SETSCHEDULER => sys_sched_setscheduler(param1, param2, param3 as *const SchedParam),
+        SYSCALL_SCHED_GETSCHEDULER => sys_sched_getscheduler(param1),
+        SYSCALL_MEMINFO => sys_meminfo(param1 as *mut MemInfo),
+        SYSCALL_PROCINFO => sys_procinfo(param1 as *mut ProcessInfo, param2),
+        SYSCALL_IRQINFO => sys_irqinfo(param1 as *mut IrqInfo, param2),
+        SYSCALL_TIMES => sys_times(param1 as *mut Tms),
+        SYSCALL_GETRUSAGE => sys_getrusage(param1 as isize, param2 as *mut RUsage),
+        SYSCALL_SYSINFO => sys_sysinfo(param1 as *mut SysInfo),
         _ => panic!("Unsupported syscall_id: {}", syscall_id),
     };
 
@@ -1,10 +1,62 @@
+use core::sync::atomic::Ordering;
 use alloc::sync::Arc;
 use alloc::string::String;
 use crate::intr::IntrContext;
 use crate::{process::fork, schedule::*};
+use crate::timer::{get_jiffies, ms_to_ticks, ticks_to_ms};
+use crate::smp::online_cpu_count;
 use crate::process;
+use crate::process::TaskTimes;
 use crate::programs::PROGRAMS;
 
+/// sys_times 的结果，单位是时钟中断
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Tms {
+    pub tms_utime: u64,
+    pub tms_stime: u64,
+    /// 已经被回收的子进程的用户态时间
+    pub tms_cutime: u64,
+    /// 已经被回收的子进程的内核态时间
+    pub tms_cstime: u64,
+}
+
+/// sys_getrusage 的结果，时间的单位是毫秒
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct RUsage {
+    pub utime_ms: u64,
+    pub stime_ms: u64,
+    /// 主动让出 CPU 的次数
+    pub nvcsw: u64,
+    /// 被抢占的次数
+    pub nivcsw: u64,
+}
+
+/// sys_sysinfo 的结果
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct SysInfo {
+    /// 启动后的时间
+    pub uptime_ms: u64,
+    /// 启动后没有线程运行的时间，所有 CPU 累加
+    pub idle_ms: u64,
+    /// 1 分钟、5 分钟、15 分钟的平均可运行线程数，定点数，1.0 表示为 1 << load_shift
+    pub loads: [usize; 3],
+    pub load_shift: usize,
+    /// 进程数
+    pub procs: usize,
+    /// 正在运行和就绪的线程数
+    pub nr_running: usize,
+    /// 在线的 CPU 数
+    pub cpus: usize,
+}
+
+/// sys_getrusage 的 who 参数
+const RUSAGE_SELF: isize = 0;
+const RUSAGE_CHILDREN: isize = -1;
+const RUSAGE_THREAD: isize = 1;
+
 pub fn sys_exit(exit_code: isize) -> ! {
     exit_current_and_run_next(exit_code)
 }
@@ -20,26 +72,32 @@ pub fn sys_getpid() -> isize {
     process.get_pid().try_into().unwrap()
 }
 
-/// 功能：当前进程 fork 出来一个子进程。
-/// 返回值：对于子进程返回 0，对于当前进程则返回子进程的 PID 。
+/// 功能：当前进程 fork 出来一个子进程，子进程中只有调用 fork 的线程。
+/// 返回值：对于子进程返回 0，对于当前进程则返回子进程的 PID ；内存不足时返回 -1 。
 /// syscall ID：220
 pub fn sys_fork() -> isize {
-    let task = current_task().unwrap();
-    let process = task.process.upgrade().unwrap();
-    let new_process = fork(process);
+    let tid = current_task().unwrap().tid;
+    let new_process = loop {
+        if let Some(new_process) = fork(current_process().unwrap(), tid) {
+            break new_process;
+        }
+        // 物理页不足，杀死一个进程后重试
+        if !handle_out_of_memory() {
+            return -1;
+        }
+    };
     {
         let new_process_inner = new_process.inner.lock();
-        if let Some(task_option) = new_process_inner.tasks.first() {
-            if let Some(task) = task_option {
-                add_task(task.clone());
-                insert_into_pid2process(new_process.get_pid(), new_process.clone());
-            }
+        if let Some(task) = new_process_inner.tasks[tid].as_ref() {
+            add_task(task.clone());
+            insert_into_pid2process(new_process.get_pid(), new_process.clone());
         }
     }
     new_process.get_pid() as isize
 }
 
 /// 功能：将当前进程的地址空间清空并加载一个特定的可执行文件，返回用户态后开始它的执行。
+/// 当前进程的其他线程全部被销毁。
 /// 参数：path 给出了要加载的可执行文件的名字；
 /// 返回值：如果出错的话（如找不到名字相符的可执行文件）则返回 -1。
 /// syscall ID：221
@@ -61,8 +119,12 @@ pub fn sys_exec(path: *const u8, mut args: *const usize, intr_cx: &mut IntrConte
         }
         path_address += 1;
     }
-    if let Some(elf_data) = programs.get(path_string.as_str()) {
-        process.exec(elf_data);
+    if let Some(&elf_data) = programs.get(path_string.as_str()) {
+        // exec 等待其他线程离开 CPU 时不能持有锁，其他 CPU 上的线程可能也在等待这个锁
+        drop(programs);
+        if !process.exec(elf_data, task.tid) {
+            return -1;
+        }
         let mut inner = process.inner.lock();
         inner.elf_data = Some(elf_data);
         let task_inner = task.inner.lock();
@@ -89,7 +151,9 @@ pub fn sys_waitpid(pid: isize, exit_code: *mut isize) -> isize {
 
     let info = process_inner.children.iter().enumerate().map(|(index, child)| {
         let child_inner = child.inner.lock();
-        (index, child.get_pid(), child_inner.is_zombie)
+        // 退出的线程可能还在其他 CPU 上使用内核栈，切换走之后才能回收
+        let is_zombie = child_inner.is_zombie && child_inner.tasks.iter().flatten().all(|task| !task.on_cpu.load(Ordering::Acquire));
+        (index, child.get_pid(), is_zombie)
     }).find(|(index, child_pid, is_zombie)| {
         if pid == -1 {
             *is_zombie
@@ -106,8 +170,14 @@ pub fn sys_waitpid(pid: isize, exit_code: *mut isize) -> isize {
         let child = process_inner.children.remove(child_index);
         let child_inner = child.inner.lock();
         assert!(child_inner.is_zombie);
+        let child_exit_code = child_inner.exit_code.as_ref().map_or(0, |code| *code);
+        let mut child_times = child_inner.times();
+        child_times.add(&child_inner.children_times);
+        drop(child_inner);
+        process_inner.children_times.add(&child_times);
+        drop(process_inner);
+        // 写用户空间可能触发写时复制的缺页异常，缺页处理需要锁住当前进程
         unsafe {
-            let child_exit_code = child_inner.exit_code.as_ref().map_or(0, |code| *code);
             exit_code.as_mut().map(| exit_code| *exit_code = child_exit_code);
         }
         child_pid.try_into().unwrap()
@@ -120,3 +190,165 @@ pub fn sys_waitpid(pid: isize, exit_code: *mut isize) -> isize {
         }
     }
 }
+
+/// 功能：设置进程的 nice 值，进程的所有线程都使用这个值，nice 越小优先级越高。
+/// 参数：pid 为 0 时表示当前进程；nice 超出 [-20, 19] 时被限制在范围内。
+/// 返回值：成功返回 0 ，进程不存在时返回 -1 。
+/// syscall ID：140
+pub fn sys_setpriority(pid: usize, nice: isize) -> isize {
+    let process = if pid == 0 { current_process() } else { pid2process(pid) };
+    if let Some(process) = process {
+        process.inner.lock().set_nice(nice);
+        0
+    } else {
+        -1
+    }
+}
+
+/// 功能：获取进程的 nice 值。
+/// 参数：pid 为 0 时表示当前进程。
+/// 返回值：返回 20 - nice，范围是 [1, 40] ；进程不存在时返回 -1 。
+/// syscall ID：141
+pub fn sys_getpriority(pid: usize) -> isize {
+    let process = if pid == 0 { current_process() } else { pid2process(pid) };
+    process.map_or(-1, |process| 20 - process.inner.lock().nice)
+}
+
+/// 功能：设置进程的所有线程的调度类，实时调度类和 Deadline 调度类的线程总是优先于普通线程运行。
+/// 参数：pid 为 0 时表示当前进程；policy 为 0 表示普通调度类，1 表示 SCHED_FIFO，2 表示 SCHED_RR，6 表示 SCHED_DEADLINE；
+/// param 表示调度参数的地址，SCHED_FIFO 和 SCHED_RR 使用其中的 priority，范围是 [1, 99]，
+/// SCHED_DEADLINE 使用 runtime_ms 和 period_ms，要求 0 < runtime_ms <= period_ms。
+/// 之后创建的线程使用普通调度类。
+/// 返回值：成功返回 0 ，进程不存在或者参数错误时返回 -1 。
+/// syscall ID：119
+pub fn sys_sched_setscheduler(pid: usize, policy: usize, param: *const SchedParam) -> isize {
+    if param.is_null() {
+        return -1;
+    }
+    // 读用户空间可能触发缺页异常，读之前不能持有进程锁
+    let param = unsafe { *param };
+    let policy = match SchedPolicy::from_usize(policy) {
+        Some(policy) => policy,
+        None => return -1,
+    };
+    let mut sched_entity = SchedEntity::new(DEFAULT_NICE);
+    sched_entity.policy = policy;
+    match policy {
+        SchedPolicy::Normal => {}
+        SchedPolicy::Fifo | SchedPolicy::RoundRobin => {
+            if param.priority < MIN_RT_PRIORITY || param.priority > MAX_RT_PRIORITY {
+                return -1;
+            }
+            sched_entity.rt_priority = param.priority;
+        }
+        SchedPolicy::Deadline => {
+            if param.runtime_ms == 0 || param.runtime_ms > param.period_ms {
+                return -1;
+            }
+            sched_entity.dl_runtime = ms_to_ticks(param.runtime_ms as u64);
+            sched_entity.dl_period = ms_to_ticks(param.period_ms as u64);
+        }
+    }
+    let process = if pid == 0 { current_process() } else { pid2process(pid) };
+    if let Some(process) = process {
+        process.inner.lock().set_scheduler(&sched_entity);
+        0
+    } else {
+        -1
+    }
+}
+
+/// 功能：获取进程的调度类。
+/// 参数：pid 为 0 时表示当前进程。
+/// 返回值：返回主线程的调度类，取值和 sys_sched_setscheduler 的 policy 相同；进程不存在时返回 -1 。
+/// syscall ID：120
+pub fn sys_sched_getscheduler(pid: usize) -> isize {
+    let process = if pid == 0 { current_process() } else { pid2process(pid) };
+    if let Some(process) = process {
+        let process_inner = process.inner.lock();
+        let main_tid = process_inner.main_tid;
+        process_inner.tasks[main_tid].as_ref().map_or(-1, |task| task.inner.lock().sched_entity.policy as isize)
+    } else {
+        -1
+    }
+}
+
+/// 功能：获取当前进程和已经被回收的子进程的运行时间。
+/// 参数：tms 表示保存运行时间的地址，单位是时钟中断。
+/// 返回值：启动后的时钟中断数；tms 为 0 时返回 -1 。
+/// syscall ID：153
+pub fn sys_times(tms: *mut Tms) -> isize {
+    if tms.is_null() {
+        return -1;
+    }
+    let process = current_process().unwrap();
+    let process_inner = process.inner.lock();
+    let times = process_inner.times();
+    let result = Tms {
+        tms_utime: times.utime,
+        tms_stime: times.stime,
+        tms_cutime: process_inner.children_times.utime,
+        tms_cstime: process_inner.children_times.stime,
+    };
+    drop(process_inner);
+    // 写用户空间可能触发写时复制的缺页异常，写之前不能持有进程锁
+    unsafe {
+        *tms = result;
+    }
+    get_jiffies() as isize
+}
+
+/// 功能：获取运行时间和上下文切换次数。
+/// 参数：who 为 0 时表示当前进程的所有线程，为 -1 时表示已经被回收的子进程，为 1 时表示当前线程；
+/// usage 表示保存结果的地址。
+/// 返回值：成功返回 0 ，参数错误时返回 -1 。
+/// syscall ID：165
+pub fn sys_getrusage(who: isize, usage: *mut RUsage) -> isize {
+    if usage.is_null() {
+        return -1;
+    }
+    let task = current_task().unwrap();
+    let times: TaskTimes = match who {
+        RUSAGE_SELF => task.process.upgrade().unwrap().inner.lock().times(),
+        RUSAGE_CHILDREN => task.process.upgrade().unwrap().inner.lock().children_times,
+        RUSAGE_THREAD => task.inner.lock().times,
+        _ => return -1,
+    };
+    let result = RUsage {
+        utime_ms: ticks_to_ms(times.utime),
+        stime_ms: ticks_to_ms(times.stime),
+        nvcsw: times.nvcsw,
+        nivcsw: times.nivcsw,
+    };
+    unsafe {
+        *usage = result;
+    }
+    0
+}
+
+/// 功能：获取系统的运行时间、空闲时间和负载均值。
+/// 参数：info 表示保存结果的地址。
+/// 返回值：成功返回 0 ；info 为 0 时返回 -1 。
+/// syscall ID：179
+pub fn sys_sysinfo(info: *mut SysInfo) -> isize {
+    if info.is_null() {
+        return -1;
+    }
+    let (idle_ticks, loads) = {
+        let cpu_stat = CPU_STAT.lock();
+        (cpu_stat.idle_ticks, cpu_stat.loads)
+    };
+    let result = SysInfo {
+        uptime_ms: ticks_to_ms(get_jiffies()),
+        idle_ms: ticks_to_ms(idle_ticks),
+        loads,
+        load_shift: FSHIFT,
+        procs: PID2PCB.lock().len(),
+        nr_running: nr_running(),
+        cpus: online_cpu_count(),
+    };
+    unsafe {
+        *info = result;
+    }
+    0
+}
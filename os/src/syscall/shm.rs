@@ -0,0 +1,132 @@
+use alloc::string::String;
+
+use crate::config::*;
+use crate::mm::shm::{shm_get, shm_open, shm_page_count, shm_unlink};
+use crate::mm::{MapArea, MapPermission, VirtAddr, VirtPageNum};
+use crate::schedule::current_process;
+
+/// 功能：打开名字为 name 的共享内存段，不存在时创建一个 size 字节的共享内存段，内容为 0。
+/// 参数：name 是以 \0 结尾的字符串；size 按页向上取整，打开已有的共享内存段时可以为 0。
+/// 返回值：共享内存段的 ID；如果已有的共享内存段小于 size 或者内存不足则返回 -1。
+/// syscall ID：194
+pub fn sys_shm_open(name: *const u8, size: usize) -> isize {
+    let Some(name_string) = read_shm_name(name) else {
+        return -1;
+    };
+    let page_count = size.div_ceil(MEMORY_PAGE_SIZE);
+    let process = current_process().unwrap();
+    let process_inner = process.inner.lock();
+    shm_open(name_string.as_str(), page_count, &process_inner.memory_set.page_table).map_or(-1, |id| id as isize)
+}
+
+/// 功能：删除名字为 name 的共享内存段，之后不能再打开或者映射它，已经映射的进程可以继续使用。
+/// 参数：name 是以 \0 结尾的字符串。
+/// 返回值：成功返回 0；如果共享内存段不存在则返回 -1。
+/// syscall ID：195
+pub fn sys_shm_unlink(name: *const u8) -> isize {
+    let Some(name_string) = read_shm_name(name) else {
+        return -1;
+    };
+    shm_unlink(name_string.as_str()).map_or(-1, |_| 0)
+}
+
+/// 返回内容：用户空间中以 \0 结尾的共享内存段名字，None 表示地址为空
+fn read_shm_name(name: *const u8) -> Option<String> {
+    let mut name_address = name as usize;
+    if name_address == 0 {
+        return None;
+    }
+    let mut name_string = String::new();
+    loop {
+        let ch: u8 = unsafe { *(name_address as *const u8) };
+        if ch == 0 {
+            break;
+        } else {
+            name_string.push(ch as char);
+        }
+        name_address += 1;
+    }
+    Some(name_string)
+}
+
+/// 功能：把共享内存段映射到当前进程的地址空间，可读可写，fork 出来的子进程继续共享。
+/// 参数：id 是 shm_open 返回的 ID；address 是映射的起始地址，必须按页对齐，为 0 时由内核选择。
+/// 返回值：映射的起始地址；如果 ID 不存在、地址已被使用或者内存不足则返回 -1。
+/// syscall ID：196
+pub fn sys_shm_attach(id: usize, address: usize) -> isize {
+    // 检查完参数再取得 stub
+    let page_count = if let Some(page_count) = shm_page_count(id) {
+        page_count
+    } else {
+        return -1;
+    };
+    let process = current_process().unwrap();
+    let mut process_inner = process.inner.lock();
+    let start_vpn = if address == 0 {
+        if let Some(start_vpn) = process_inner.memory_set.find_free_shm_range(page_count) {
+            start_vpn
+        } else {
+            return -1;
+        }
+    } else {
+        let start_va = VirtAddr(address);
+        let end_va = VirtAddr(address + page_count * MEMORY_PAGE_SIZE);
+        if !start_va.0.is_multiple_of(MEMORY_PAGE_SIZE) || end_va.0 > HIGH_ADDRESS_BASE || end_va.0 < start_va.0 {
+            return -1;
+        }
+        start_va.virt_page_num_floor()
+    };
+    let end_vpn = VirtPageNum(start_vpn.0 + page_count);
+    if !process_inner.memory_set.is_range_free(start_vpn, end_vpn) {
+        return -1;
+    }
+    // 不能和线程的用户栈（包括向下增长的部分）重叠
+    let is_overlap_stack = process_inner.tasks.iter().flatten().any(|task| {
+        let task_inner = task.inner.lock();
+        task_inner.user_stack_top_address.is_some_and(|stack_top_va| {
+            let stack_top_vpn = stack_top_va.virt_page_num_floor();
+            let stack_bottom_vpn = VirtAddr(stack_top_va.0 - USER_STACK_MAX_SIZE).virt_page_num_floor();
+            !(stack_top_vpn <= start_vpn || end_vpn <= stack_bottom_vpn)
+        })
+    });
+    if is_overlap_stack {
+        return -1;
+    }
+    // 检查期间共享内存段可能已经被删除，id 被新的共享内存段使用
+    let shm_stub = match shm_get(id) {
+        Some(shm_stub) if shm_stub.segment.page_count() == page_count => shm_stub,
+        _ => return -1,
+    };
+
+    let mut area = MapArea::new_shared(start_vpn, MapPermission::R | MapPermission::W | MapPermission::U, shm_stub);
+    if area.map_shared(&mut process_inner.memory_set.page_table).is_none() {
+        area.unmap(&mut process_inner.memory_set.page_table);
+        return -1;
+    }
+    process_inner.memory_set.areas.push(area);
+    start_vpn.base_address().0 as isize
+}
+
+/// 功能：解除当前进程对共享内存段的映射，所有进程都解除映射后共享内存段被删除。
+/// 参数：address 是 shm_attach 返回的地址。
+/// 返回值：成功返回 0；如果 address 不是共享内存段的起始地址或者内存不足则返回 -1。
+/// syscall ID：197
+pub fn sys_shm_detach(address: usize) -> isize {
+    let start_vpn = VirtAddr(address).virt_page_num_floor();
+    let process = current_process().unwrap();
+    let mut process_inner = process.inner.lock();
+    let process_inner = &mut *process_inner;
+    if let Some(index) = process_inner.memory_set.areas.iter().position(|area| area.is_shared() && area.vpn_range.start == start_vpn) {
+        // 页表页和 fork 出来的进程共享时先复制页表页，避免解除其他进程的映射
+        for vpn in process_inner.memory_set.areas[index].vpn_range.clone() {
+            if process_inner.memory_set.page_table.remap_pde_if_need(vpn).is_none() {
+                return -1;
+            }
+        }
+        let mut area = process_inner.memory_set.areas.remove(index);
+        area.unmap(&mut process_inner.memory_set.page_table);
+        0
+    } else {
+        -1
+    }
+}
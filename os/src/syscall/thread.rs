@@ -1,17 +1,27 @@
 use alloc::sync::Arc;
 
-use crate::schedule::add_task;
+use crate::schedule::{add_task, current_process, handle_out_of_memory};
 use crate::{process::TaskControlBlock, schedule::current_task};
 
 
 /// 功能：当前进程创建一个新的线程
 /// 参数：entry 表示线程的入口函数地址，arg 表示传给线程入口函数参数
-/// 返回值：创建的线程的 TID
+/// 返回值：创建的线程的 TID，内存不足或者线程数达到上限时返回 -1
 /// syscall ID: 1000
 pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
-    let current_task = current_task().unwrap();
-    let process = current_task.process.upgrade().unwrap();
-    let new_task = TaskControlBlock::new(process.clone(), entry, false, Some(arg));
+    let new_task = loop {
+        if let Some(new_task) = TaskControlBlock::new(current_process().unwrap(), entry, false, Some(arg)) {
+            break new_task;
+        }
+        if current_process().unwrap().inner.lock().tid_allocator.is_full() {
+            return -1;
+        }
+        // 物理页不足，杀死一个进程后重试
+        if !handle_out_of_memory() {
+            return -1;
+        }
+    };
+    let process = current_process().unwrap();
     let tid = new_task.tid;
     let new_task = Arc::new(new_task);
     process.add_task(new_task.clone());
@@ -49,6 +59,10 @@ pub fn sys_waittid(tid: usize) -> isize {
     if let Some(exit_code) = exit_code {
         // dealloc the exited thread
         let task = process_inner.tasks[tid].take();
+        if let Some(task) = task.as_ref() {
+            let times = task.inner.lock().times;
+            process_inner.exited_times.add(&times);
+        }
         drop(process_inner);
         exit_code
     } else {
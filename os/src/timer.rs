@@ -4,24 +4,25 @@ use core::cmp::PartialOrd;
 use core::cmp::Ordering;
 use spin::Mutex;
 
-use crate::{arch::x86::outb, drivers::{rdtsc, read_hour, read_minute, read_second}, process::TaskControlBlock, schedule::wakeup_task};
+use crate::clock;
+use crate::intr::softirq::{open_softirq, without_intr, SoftIrq};
+use crate::{drivers::{read_hour, read_minute, read_second}, process::TaskControlBlock, schedule::wakeup_task};
 
 
-const INPUT_FREQUENCY: usize = 1193180;
-const IRQ0_FREQUENCY: usize = 100;
-const COUNTER0_VALUE: usize = INPUT_FREQUENCY / IRQ0_FREQUENCY;
-const COUNTER0_PORT: u16 = 0x40;
-const PIT_CONTROL_PORT: u16 = 0x43;
+/// 每秒的时钟中断数
+pub const IRQ0_FREQUENCY: usize = 100;
 
 pub fn init() {
-    outb(0u8 << 6 | 3 << 4 | 2 << 1, PIT_CONTROL_PORT);
-    outb((COUNTER0_VALUE & 0xff) as u8, COUNTER0_PORT);
-    outb(((COUNTER0_VALUE >> 8) & 0xff) as u8, COUNTER0_PORT);
     _ = START_TIME.lock();
+    open_softirq(SoftIrq::Timer, check_timer);
+    clock::init();
 }
 
 lazy_static! {
+    /// 按时钟中断计数并用 RTC 校正的时间，没有高精度的时钟源时使用
     static ref ELAPSED_TIME: Arc<Mutex<u64>> = Arc::new(Mutex::new(0));
+    /// 启动后的时钟中断数
+    static ref JIFFIES: Arc<Mutex<u64>> = Arc::new(Mutex::new(0));
     static ref START_TIME: Arc<Mutex<u64>> = Arc::new(Mutex::new(
         current_timestamp()
     ));
@@ -34,7 +35,9 @@ fn current_timestamp() -> u64 {
     hours * 60 * 60 + minutes * 60 + seconds
 }
 
+/// BSP 每次时钟中断时调用
 pub fn update_time() {
+    *JIFFIES.lock() += 1;
     let tick = 1_000_000 / IRQ0_FREQUENCY as u64;
     let mut elasped_time = ELAPSED_TIME.lock();
     let start_time = START_TIME.lock();
@@ -46,11 +49,36 @@ pub fn update_time() {
     }
 }
 
+/// 空闲时时钟中断可能停止，有高精度的时钟源时按时间换算
+pub fn get_jiffies() -> u64 {
+    if clock::is_high_resolution() {
+        clock::time_ns() / clock::NSEC_PER_TICK
+    } else {
+        *JIFFIES.lock()
+    }
+}
+
+/// 把时钟中断数换算成毫秒
+pub fn ticks_to_ms(ticks: u64) -> u64 {
+    ticks * 1000 / IRQ0_FREQUENCY as u64
+}
+
+/// 把毫秒换算成时钟中断数，向上取整
+pub fn ms_to_ticks(ms: u64) -> u64 {
+    (ms * IRQ0_FREQUENCY as u64).div_ceil(1000)
+}
+
 pub fn get_time_in_millisecond() -> u64 {
     get_time_in_microsecond() / 1000
 }
 
+/// 时钟源的精度，使用 TSC 或者 HPET 时精确到微秒
 pub fn get_time_in_microsecond() -> u64 {
+    clock::time_ns() / 1000
+}
+
+/// 按时钟中断计数的时间，精度为一个时钟中断周期
+pub fn elapsed_time_in_microsecond() -> u64 {
     let elasped_time = ELAPSED_TIME.lock();
     *elasped_time
 }
@@ -87,17 +115,34 @@ lazy_static! {
 pub fn add_timer(expire_ms: u64, task: Arc<TaskControlBlock>) {
     let mut timers = TIMERS.lock();
     timers.push(TimerCondVar { expire_ms, task });
+    drop(timers);
+    clock::nohz::timer_added();
 }
 
-pub fn check_timer() {
+/// 最早到期的定时器的到期时间，BSP 空闲时按它设置单次中断
+pub fn next_timer_expire_ms() -> Option<u64> {
+    TIMERS.lock().peek().map(|timer| timer.expire_ms)
+}
+
+/// 线程被销毁时取消它的定时器
+pub fn remove_timer(task: &Arc<TaskControlBlock>) {
+    let mut timers = TIMERS.lock();
+    timers.retain(|timer| !Arc::ptr_eq(&timer.task, task));
+}
+
+/// 在软中断中开中断运行，每次关中断取出一个到期的定时器
+fn check_timer() {
     let current_ms = get_time_in_millisecond();
+    while let Some(task) = without_intr(|| pop_expired_timer(current_ms)) {
+        without_intr(|| wakeup_task(task));
+    }
+}
+
+fn pop_expired_timer(current_ms: u64) -> Option<Arc<TaskControlBlock>> {
     let mut timers = TIMERS.lock();
-    while let Some(timer) = timers.peek() {
-        if timer.expire_ms <= current_ms {
-            wakeup_task(timer.task.clone());
-            timers.pop();
-        } else {
-            break;
-        }
+    if timers.peek()?.expire_ms <= current_ms {
+        timers.pop().map(|timer| timer.task)
+    } else {
+        None
     }
 }
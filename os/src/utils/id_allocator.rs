@@ -36,6 +36,11 @@ impl<const N: usize> IdAllocator<{ N }> {
         None
     }
 
+    /// 所有 ID 都已经分配出去
+    pub fn is_full(&self) -> bool {
+        self.current == self.end && (self.begin..self.current).all(|idx| self.get_bitmap(idx))
+    }
+
     pub fn dealloc(&mut self, idx: usize) {
         let old_value = self.get_bitmap(idx);
         assert!(old_value, "Id in #{} is not used", idx);
@@ -0,0 +1,36 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{meminfo, MemInfo};
+
+const PAGE_SIZE_IN_KB: usize = 4;
+
+#[no_mangle]
+fn main() -> isize {
+    let mut info = MemInfo::default();
+    if meminfo(&mut info) != 0 {
+        println!("free: meminfo failed");
+        return -1;
+    }
+    let used_frames = info.total_frames - info.free_frames;
+    println!("{:<12}{:>12}{:>12}{:>12}", "", "total(KiB)", "used(KiB)", "free(KiB)");
+    println!(
+        "{:<12}{:>12}{:>12}{:>12}",
+        "frames:",
+        info.total_frames * PAGE_SIZE_IN_KB,
+        used_frames * PAGE_SIZE_IN_KB,
+        info.free_frames * PAGE_SIZE_IN_KB
+    );
+    println!(
+        "{:<12}{:>12}{:>12}{:>12}",
+        "heap:",
+        info.heap_total / 1024,
+        info.heap_used / 1024,
+        (info.heap_total - info.heap_used) / 1024
+    );
+    println!("page table: {} KiB", info.page_table_pages * PAGE_SIZE_IN_KB);
+    0
+}
@@ -19,6 +19,16 @@ static APPS: &[&str] = &[
     "condsync_sem",
     "condsync_condvar",
     "barrier_condvar",
+    "stack_grow",
+    "shm_producer_consumer",
+    "free",
+    "ps",
+    "thread_fork",
+    "priority",
+    "rt_sched",
+    "rusage",
+    "uptime",
+    "interrupts",
 ];
 
 #[no_mangle]
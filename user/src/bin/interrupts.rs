@@ -0,0 +1,26 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{irqinfo, IrqInfo};
+
+const MAX_IRQ_COUNT: usize = 16;
+
+#[no_mangle]
+fn main() -> isize {
+    let mut infos = [IrqInfo::default(); MAX_IRQ_COUNT];
+    let count = irqinfo(&mut infos);
+    if count < 0 {
+        println!("interrupts: irqinfo failed");
+        return -1;
+    }
+    println!("{:>4}{:>12}{:>10}  {}", "IRQ", "COUNT", "SPURIOUS", "DEVICE");
+    for info in &infos[..count as usize] {
+        let name_len = info.names.iter().position(|&b| b == 0).unwrap_or(info.names.len());
+        let name = core::str::from_utf8(&info.names[..name_len]).unwrap_or("?");
+        println!("{:>4}{:>12}{:>10}  {}", info.irq, info.count, info.spurious_count, name);
+    }
+    0
+}
@@ -0,0 +1,39 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::*;
+
+const NICE_LIST: [isize; 4] = [0, 5, 10, 15];
+const RUN_TIME_MS: isize = 2000;
+
+/// 在 RUN_TIME_MS 内一直计数，nice 越小计数越多
+fn count(nice: isize) -> usize {
+    setpriority(0, nice);
+    let start = get_time();
+    let mut count: usize = 0;
+    while get_time() - start < RUN_TIME_MS {
+        count = core::hint::black_box(count + 1);
+    }
+    count
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    for nice in NICE_LIST {
+        let pid = fork();
+        if pid == 0 {
+            let count = count(nice);
+            println!("pid {} nice {} count {}", getpid(), nice, count);
+            exit(0);
+        }
+    }
+    let mut exit_code: isize = 0;
+    for _ in NICE_LIST {
+        wait(&mut exit_code);
+    }
+    println!("priority test passed!");
+    0
+}
@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{procinfo, ProcessInfo};
+
+const MAX_PROCESS_COUNT: usize = 32;
+
+#[no_mangle]
+fn main() -> isize {
+    let mut infos = [ProcessInfo::default(); MAX_PROCESS_COUNT];
+    let count = procinfo(&mut infos);
+    if count < 0 {
+        println!("ps: procinfo failed");
+        return -1;
+    }
+    // 内存使用的单位是页
+    println!(
+        "{:>5}{:>6}{:>4}{:>6}{:>4} {:>6}{:>6}{:>6}{:>6}{:>6}{:>6}{:>6}{:>6}",
+        "PID", "PPID", "THR", "STAT", "NI", "RSS", "SWAP", "COW", "CODE", "DATA", "STACK", "SHM", "PT"
+    );
+    for info in &infos[..count as usize] {
+        let usage = &info.memory_usage;
+        println!(
+            "{:>5}{:>6}{:>4}{:>6}{:>4} {:>6}{:>6}{:>6}{:>6}{:>6}{:>6}{:>6}{:>6}",
+            info.pid,
+            info.ppid,
+            info.thread_count,
+            if info.is_zombie != 0 { "Z" } else { "R" },
+            info.nice,
+            usage.resident,
+            usage.swapped,
+            usage.cow_shared,
+            usage.code,
+            usage.data,
+            usage.stack,
+            usage.shm,
+            usage.page_table
+        );
+    }
+    0
+}
@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::*;
+
+const RUN_TIME_MS: isize = 1000;
+
+/// 设置调度类后在 RUN_TIME_MS 内一直计数
+fn count(policy: usize, param: &SchedParam) -> usize {
+    assert_eq!(sched_setscheduler(0, policy, param), 0);
+    assert_eq!(sched_getscheduler(0), policy as isize);
+    let start = get_time();
+    let mut count: usize = 0;
+    while get_time() - start < RUN_TIME_MS {
+        count = core::hint::black_box(count + 1);
+    }
+    count
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 参数错误
+    let invalid_param = SchedParam { priority: 100, runtime_ms: 0, period_ms: 0 };
+    assert_eq!(sched_setscheduler(0, SCHED_RR, &invalid_param), -1);
+    assert_eq!(sched_setscheduler(0, SCHED_DEADLINE, &invalid_param), -1);
+    assert_eq!(sched_setscheduler(0, 3, &invalid_param), -1);
+    assert_eq!(sched_getscheduler(0), SCHED_NORMAL as isize);
+
+    // 普通线程最后创建，实时线程先运行；Deadline 线程每 500ms 运行 100ms，优先于实时线程
+    let tests = [
+        ("normal", SCHED_NORMAL, SchedParam::default()),
+        ("deadline", SCHED_DEADLINE, SchedParam { priority: 0, runtime_ms: 100, period_ms: 500 }),
+        ("rr", SCHED_RR, SchedParam { priority: 10, runtime_ms: 0, period_ms: 0 }),
+    ];
+    for (name, policy, param) in tests.iter() {
+        let pid = fork();
+        if pid == 0 {
+            let count = count(*policy, param);
+            println!("pid {} {} count {}", getpid(), name, count);
+            exit(0);
+        }
+    }
+    let mut exit_code: isize = 0;
+    for _ in tests.iter() {
+        wait(&mut exit_code);
+    }
+    println!("rt_sched test passed!");
+    0
+}
@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::*;
+
+const RUN_TIME_MS: isize = 500;
+const YIELD_COUNT: usize = 100;
+
+fn usage_of(who: isize) -> RUsage {
+    let mut usage = RUsage::default();
+    assert_eq!(getrusage(who, &mut usage), 0);
+    usage
+}
+
+fn print_rusage(name: &str, who: isize) {
+    let usage = usage_of(who);
+    println!(
+        "{}: user {} ms, system {} ms, voluntary switches {}, involuntary switches {}",
+        name, usage.utime_ms, usage.stime_ms, usage.nvcsw, usage.nivcsw
+    );
+}
+
+/// 在用户态计算 RUN_TIME_MS
+fn busy_loop() {
+    let start = get_time();
+    let mut count: usize = 0;
+    while get_time() - start < RUN_TIME_MS {
+        count = core::hint::black_box(count + 1);
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut usage = RUsage::default();
+    assert_eq!(getrusage(2, &mut usage), -1);
+
+    for _ in 0..YIELD_COUNT {
+        yield_();
+    }
+    print_rusage("after yield", RUSAGE_SELF);
+    assert!(usage_of(RUSAGE_SELF).nvcsw >= YIELD_COUNT as u64);
+
+    let pid = fork();
+    if pid == 0 {
+        busy_loop();
+        print_rusage("child", RUSAGE_SELF);
+        exit(0);
+    }
+    let mut exit_code: isize = 0;
+    waitpid(pid as usize, &mut exit_code);
+    print_rusage("children", RUSAGE_CHILDREN);
+
+    let mut tms = Tms::default();
+    let ticks = times(&mut tms);
+    println!(
+        "times: {} ticks since boot, utime {} stime {} cutime {} cstime {}",
+        ticks, tms.tms_utime, tms.tms_stime, tms.tms_cutime, tms.tms_cstime
+    );
+    assert!(tms.tms_cutime + tms.tms_cstime > 0);
+    println!("rusage test passed!");
+    0
+}
@@ -0,0 +1,75 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::ptr::{read_volatile, write_volatile};
+use user_lib::{exit, fork, waitpid};
+use user_lib::{semaphore_create, semaphore_down, semaphore_up};
+use user_lib::{shm_attach, shm_detach, shm_open, shm_unlink};
+
+const SHM_NAME: &str = "shm_producer_consumer\0";
+const UNUSED_SHM_NAME: &str = "shm_producer_consumer_unused\0";
+const SEM_EMPTY: usize = 0;
+const SEM_AVAIL: usize = 1;
+const BUFFER_SIZE: usize = 16;
+const NUMBER_COUNT: usize = 1000;
+
+#[repr(C)]
+struct SharedBuffer {
+    buffer: [usize; BUFFER_SIZE],
+}
+
+fn attach() -> &'static mut SharedBuffer {
+    // 子进程通过名字重新打开共享内存段
+    let id = shm_open(SHM_NAME, 0);
+    assert!(id >= 0);
+    let address = shm_attach(id as usize, 0);
+    assert_ne!(address, -1);
+    unsafe { &mut *(address as usize as *mut SharedBuffer) }
+}
+
+fn consumer() -> ! {
+    let shared = attach();
+    let mut sum = 0;
+    for idx in 0..NUMBER_COUNT {
+        semaphore_down(SEM_AVAIL);
+        sum += unsafe { read_volatile(&shared.buffer[idx % BUFFER_SIZE]) };
+        semaphore_up(SEM_EMPTY);
+    }
+    assert_eq!(shm_detach(shared as *mut _ as usize), 0);
+    exit(if sum == NUMBER_COUNT * (NUMBER_COUNT - 1) / 2 { 0 } else { -1 })
+}
+
+#[no_mangle]
+pub fn main() -> isize {
+    // 没有映射过的共享内存段只能由 shm_unlink 删除
+    assert!(shm_open(UNUSED_SHM_NAME, 1) >= 0);
+    assert_eq!(shm_unlink(UNUSED_SHM_NAME), 0);
+    assert_eq!(shm_open(UNUSED_SHM_NAME, 0), -1);
+    assert_eq!(shm_unlink(UNUSED_SHM_NAME), -1);
+
+    let id = shm_open(SHM_NAME, core::mem::size_of::<SharedBuffer>());
+    assert!(id >= 0);
+    // fork 后信号量在父子进程之间共享
+    assert_eq!(semaphore_create(BUFFER_SIZE) as usize, SEM_EMPTY);
+    assert_eq!(semaphore_create(0) as usize, SEM_AVAIL);
+    let pid = fork();
+    if pid == 0 {
+        consumer();
+    }
+
+    let shared = attach();
+    for idx in 0..NUMBER_COUNT {
+        semaphore_down(SEM_EMPTY);
+        unsafe { write_volatile(&mut shared.buffer[idx % BUFFER_SIZE], idx) };
+        semaphore_up(SEM_AVAIL);
+    }
+    let mut exit_code: isize = 0;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, 0);
+    assert_eq!(shm_detach(shared as *mut _ as usize), 0);
+    println!("shm_producer_consumer passed!");
+    0
+}
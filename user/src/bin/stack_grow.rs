@@ -0,0 +1,29 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+const DEPTH: usize = 256;
+
+/// 每层递归在栈上占用 1KiB，总共需要 256KiB 以上的用户栈
+fn recursion(depth: usize) -> usize {
+    let mut buffer = [0u8; 1024];
+    buffer[depth % 1024] = depth as u8;
+    let buffer = core::hint::black_box(buffer);
+    if depth == 0 {
+        buffer[0] as usize
+    } else {
+        recursion(depth - 1) + buffer[depth % 1024] as usize
+    }
+}
+
+#[no_mangle]
+pub fn main() -> isize {
+    println!("recursion depth {}", DEPTH);
+    let sum = recursion(DEPTH);
+    let expect: usize = (0..=DEPTH).map(|depth| depth as u8 as usize).sum();
+    assert_eq!(sum, expect);
+    println!("stack_grow pass.");
+    0
+}
@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::*;
+
+/// 子线程中 fork，子进程里只有这一个线程
+extern "C" fn thread_fork(_arg: usize) {
+    let tid = gettid();
+    let pid = fork();
+    if pid == 0 {
+        println!("child process pid {} tid {}", getpid(), gettid());
+        assert_eq!(gettid(), tid);
+        exit(7);
+    }
+    let mut exit_code: isize = 0;
+    let wait_pid = waitpid(pid as usize, &mut exit_code);
+    assert_eq!(wait_pid, pid);
+    assert_eq!(exit_code, 7);
+    println!("thread#{} forked child pid {} exited with code {}", tid, pid, exit_code);
+    exit(0)
+}
+
+/// 子线程中 exec，其他线程被销毁
+extern "C" fn thread_exec(_arg: usize) {
+    println!("thread#{} exec hello_world", gettid());
+    exec("hello_world\0", &[]);
+    panic!("unreachable after exec!");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let tid = thread_create(thread_fork as usize, 0);
+    let exit_code = waittid(tid as usize);
+    assert_eq!(exit_code, 0);
+    thread_create(thread_exec as usize, 0);
+    // 主线程一直等待，直到被 exec 销毁
+    loop {
+        yield_();
+    }
+}
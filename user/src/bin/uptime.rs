@@ -0,0 +1,36 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{sysinfo, SysInfo};
+
+/// 把定点数的负载均值格式化为两位小数
+fn load_to_hundredths(load: usize, shift: usize) -> (usize, usize) {
+    let hundredths = (load * 100 + (1 << shift) / 2) >> shift;
+    (hundredths / 100, hundredths % 100)
+}
+
+#[no_mangle]
+fn main() -> isize {
+    let mut info = SysInfo::default();
+    if sysinfo(&mut info) != 0 {
+        println!("uptime: sysinfo failed");
+        return -1;
+    }
+    let seconds = info.uptime_ms / 1000;
+    // idle_ms 是所有 CPU 空闲时间的和
+    let total_ms = info.uptime_ms * info.cpus.max(1) as u64;
+    let busy_ms = total_ms - info.idle_ms.min(total_ms);
+    let cpu_usage = if total_ms == 0 { 0 } else { busy_ms * 100 / total_ms };
+    let loads = info.loads.map(|load| load_to_hundredths(load, info.load_shift));
+    println!(
+        "up {}:{:02}:{:02}, {} processes, {} running, load average: {}.{:02}, {}.{:02}, {}.{:02}",
+        seconds / 3600, seconds / 60 % 60, seconds % 60,
+        info.procs, info.nr_running,
+        loads[0].0, loads[0].1, loads[1].0, loads[1].1, loads[2].0, loads[2].1
+    );
+    println!("{} cpus, cpu usage {}%, idle {} ms", info.cpus, cpu_usage, info.idle_ms);
+    0
+}
@@ -16,6 +16,124 @@ mod syscall;
 
 use syscall::*;
 
+/// 系统全局的内存使用情况，和内核中的定义保持一致
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct MemInfo {
+    pub total_frames: usize,
+    pub free_frames: usize,
+    pub heap_total: usize,
+    pub heap_used: usize,
+    pub page_table_pages: usize,
+}
+
+/// 调度类，取值和内核中的 SchedPolicy 保持一致
+pub const SCHED_NORMAL: usize = 0;
+pub const SCHED_FIFO: usize = 1;
+pub const SCHED_RR: usize = 2;
+pub const SCHED_DEADLINE: usize = 6;
+
+/// sched_setscheduler 的参数，和内核中的定义保持一致
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct SchedParam {
+    /// SCHED_FIFO 和 SCHED_RR 的优先级，范围是 [1, 99]，值越大优先级越高
+    pub priority: usize,
+    /// SCHED_DEADLINE 每个周期可以运行的时间，单位是毫秒
+    pub runtime_ms: usize,
+    /// SCHED_DEADLINE 的周期，单位是毫秒
+    pub period_ms: usize,
+}
+
+/// times 的结果，单位是时钟中断，和内核中的定义保持一致
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Tms {
+    pub tms_utime: u64,
+    pub tms_stime: u64,
+    /// 已经被回收的子进程的用户态时间
+    pub tms_cutime: u64,
+    /// 已经被回收的子进程的内核态时间
+    pub tms_cstime: u64,
+}
+
+/// sysinfo 的结果，和内核中的定义保持一致
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct SysInfo {
+    /// 启动后的时间
+    pub uptime_ms: u64,
+    /// 启动后没有线程运行的时间，所有 CPU 累加
+    pub idle_ms: u64,
+    /// 1 分钟、5 分钟、15 分钟的平均可运行线程数，定点数，1.0 表示为 1 << load_shift
+    pub loads: [usize; 3],
+    pub load_shift: usize,
+    /// 进程数
+    pub procs: usize,
+    /// 正在运行和就绪的线程数
+    pub nr_running: usize,
+    /// 在线的 CPU 数
+    pub cpus: usize,
+}
+
+/// 中断线名字的最大长度
+pub const IRQ_NAME_LEN: usize = 32;
+
+/// 单条中断线的统计信息，和内核中的定义保持一致
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct IrqInfo {
+    pub irq: usize,
+    /// 中断次数
+    pub count: u64,
+    /// 没有处理函数处理的中断次数
+    pub spurious_count: u64,
+    /// 注册的设备名，多个共享中断的设备名用逗号分隔，以 0 结尾
+    pub names: [u8; IRQ_NAME_LEN],
+}
+
+/// getrusage 的 who 参数
+pub const RUSAGE_SELF: isize = 0;
+pub const RUSAGE_CHILDREN: isize = -1;
+pub const RUSAGE_THREAD: isize = 1;
+
+/// getrusage 的结果，时间的单位是毫秒，和内核中的定义保持一致
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct RUsage {
+    pub utime_ms: u64,
+    pub stime_ms: u64,
+    /// 主动让出 CPU 的次数
+    pub nvcsw: u64,
+    /// 被抢占的次数
+    pub nivcsw: u64,
+}
+
+/// 进程内存使用情况的统计，单位是页
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct MemoryUsage {
+    pub resident: usize,
+    pub swapped: usize,
+    pub cow_shared: usize,
+    pub code: usize,
+    pub data: usize,
+    pub stack: usize,
+    pub shm: usize,
+    pub page_table: usize,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct ProcessInfo {
+    pub pid: usize,
+    pub ppid: usize,
+    pub thread_count: usize,
+    pub is_zombie: usize,
+    pub nice: isize,
+    pub memory_usage: MemoryUsage,
+}
+
 const USER_HEAP_SIZE: usize = 0x1000;
 
 static mut HEAP_SPACE: [u8; USER_HEAP_SIZE] = [0; USER_HEAP_SIZE];
@@ -63,6 +181,32 @@ pub fn get_time() -> isize { sys_get_time() }
 pub fn getpid() -> isize { sys_getpid() }
 pub fn fork() -> isize { sys_fork() }
 pub fn exec(path: &str, args: &[*const u8]) -> isize { sys_exec(path, args) }
+pub fn shm_open(name: &str, size: usize) -> isize { sys_shm_open(name, size) }
+pub fn shm_unlink(name: &str) -> isize { sys_shm_unlink(name) }
+pub fn shm_attach(id: usize, address: usize) -> isize { sys_shm_attach(id, address) }
+pub fn shm_detach(address: usize) -> isize { sys_shm_detach(address) }
+pub fn setpriority(pid: usize, nice: isize) -> isize { sys_setpriority(pid, nice) }
+/// 返回内容：进程的 nice 值，进程不存在时返回 None
+pub fn getpriority(pid: usize) -> Option<isize> {
+    match sys_getpriority(pid) {
+        -1 => None,
+        priority => Some(20 - priority),
+    }
+}
+/// 当前进程的 nice 值增加 inc，返回新的 nice 值
+pub fn nice(inc: isize) -> isize {
+    let nice = getpriority(0).unwrap() + inc;
+    setpriority(0, nice);
+    getpriority(0).unwrap()
+}
+pub fn sched_setscheduler(pid: usize, policy: usize, param: &SchedParam) -> isize { sys_sched_setscheduler(pid, policy, param) }
+pub fn sched_getscheduler(pid: usize) -> isize { sys_sched_getscheduler(pid) }
+pub fn times(tms: &mut Tms) -> isize { sys_times(tms) }
+pub fn getrusage(who: isize, usage: &mut RUsage) -> isize { sys_getrusage(who, usage) }
+pub fn sysinfo(info: &mut SysInfo) -> isize { sys_sysinfo(info) }
+pub fn meminfo(info: &mut MemInfo) -> isize { sys_meminfo(info) }
+pub fn procinfo(infos: &mut [ProcessInfo]) -> isize { sys_procinfo(infos) }
+pub fn irqinfo(infos: &mut [IrqInfo]) -> isize { sys_irqinfo(infos) }
 pub fn wait(exit_code: &mut isize) -> isize {
     loop {
         match sys_waitpid(-1, exit_code as *mut _) {
@@ -6,13 +6,24 @@ pub const SYSCALL_READ: usize = 63;
 pub const SYSCALL_WRITE: usize = 64;
 pub const SYSCALL_EXIT: usize = 93;
 pub const SYSCALL_SLEEP: usize = 101;
+pub const SYSCALL_SCHED_SETSCHEDULER: usize = 119;
+pub const SYSCALL_SCHED_GETSCHEDULER: usize = 120;
 pub const SYSCALL_YIELD: usize = 124;
 pub const SYSCALL_KILL: usize = 129;
 pub const SYSCALL_SIGACTION: usize = 134;
 pub const SYSCALL_SIGPROCMASK: usize = 135;
 pub const SYSCALL_SIGRETURN: usize = 139;
+pub const SYSCALL_SETPRIORITY: usize = 140;
+pub const SYSCALL_GETPRIORITY: usize = 141;
+pub const SYSCALL_TIMES: usize = 153;
+pub const SYSCALL_GETRUSAGE: usize = 165;
 pub const SYSCALL_GET_TIME: usize = 169;
 pub const SYSCALL_GETPID: usize = 172;
+pub const SYSCALL_SYSINFO: usize = 179;
+pub const SYSCALL_SHM_OPEN: usize = 194;
+pub const SYSCALL_SHM_UNLINK: usize = 195;
+pub const SYSCALL_SHM_ATTACH: usize = 196;
+pub const SYSCALL_SHM_DETACH: usize = 197;
 pub const SYSCALL_FORK: usize = 220;
 pub const SYSCALL_EXEC: usize = 221;
 pub const SYSCALL_WAITPID: usize = 260;
@@ -28,3 +39,6 @@ pub const SYSCALL_SEMAPHORE_DOWN: usize = 1022;
 pub const SYSCALL_CONDVAR_CREATE: usize = 1030;
 pub const SYSCALL_CONDVAR_SIGNAL: usize = 1031;
 pub const SYSCALL_CONDVAR_WAIT: usize = 1032;
+pub const SYSCALL_MEMINFO: usize = 1040;
+pub const SYSCALL_PROCINFO: usize = 1041;
+pub const SYSCALL_IRQINFO: usize = 1042;
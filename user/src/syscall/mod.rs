@@ -2,6 +2,8 @@ mod define;
 
 use define::*;
 
+use crate::{IrqInfo, MemInfo, ProcessInfo, RUsage, SchedParam, SysInfo, Tms};
+
 use core::arch::asm;
 fn syscall(id: usize, args: [usize; 3]) -> isize {
     let mut ret: isize;
@@ -46,6 +48,38 @@ pub fn sys_getpid() -> isize {
     syscall(SYSCALL_GETPID, [0, 0, 0])
 }
 
+/// 功能：打开名字为 name 的共享内存段，不存在时创建一个 size 字节的共享内存段，内容为 0。
+/// 参数：name 是以 \0 结尾的字符串；size 按页向上取整，打开已有的共享内存段时可以为 0。
+/// 返回值：共享内存段的 ID；如果已有的共享内存段小于 size 或者内存不足则返回 -1。
+/// syscall ID：194
+pub fn sys_shm_open(name: &str, size: usize) -> isize {
+    syscall(SYSCALL_SHM_OPEN, [name.as_ptr() as usize, size, 0])
+}
+
+/// 功能：删除名字为 name 的共享内存段，之后不能再打开或者映射它，已经映射的进程可以继续使用。
+/// 参数：name 是以 \0 结尾的字符串。
+/// 返回值：成功返回 0；如果共享内存段不存在则返回 -1。
+/// syscall ID：195
+pub fn sys_shm_unlink(name: &str) -> isize {
+    syscall(SYSCALL_SHM_UNLINK, [name.as_ptr() as usize, 0, 0])
+}
+
+/// 功能：把共享内存段映射到当前进程的地址空间，可读可写，fork 出来的子进程继续共享。
+/// 参数：id 是 shm_open 返回的 ID；address 是映射的起始地址，必须按页对齐，为 0 时由内核选择。
+/// 返回值：映射的起始地址；如果 ID 不存在、地址已被使用或者内存不足则返回 -1。
+/// syscall ID：196
+pub fn sys_shm_attach(id: usize, address: usize) -> isize {
+    syscall(SYSCALL_SHM_ATTACH, [id, address, 0])
+}
+
+/// 功能：解除当前进程对共享内存段的映射，所有进程都解除映射后共享内存段被删除。
+/// 参数：address 是 shm_attach 返回的地址。
+/// 返回值：成功返回 0；如果 address 不是共享内存段的起始地址或者内存不足则返回 -1。
+/// syscall ID：197
+pub fn sys_shm_detach(address: usize) -> isize {
+    syscall(SYSCALL_SHM_DETACH, [address, 0, 0])
+}
+
 /// 功能：当前进程 fork 出来一个子进程。
 /// 返回值：对于子进程返回 0，对于当前进程则返回子进程的 PID 。
 /// syscall ID：220
@@ -169,3 +203,87 @@ pub fn sys_condvar_signal(condvar_id: usize) -> isize {
 pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
     syscall(SYSCALL_CONDVAR_WAIT, [condvar_id, mutex_id, 0])
 }
+
+
+/// 功能：设置进程的 nice 值，进程的所有线程都使用这个值，nice 越小优先级越高。
+/// 参数：pid 为 0 时表示当前进程；nice 超出 [-20, 19] 时被限制在范围内。
+/// 返回值：成功返回 0 ，进程不存在时返回 -1 。
+/// syscall ID : 140
+pub fn sys_setpriority(pid: usize, nice: isize) -> isize {
+    syscall(SYSCALL_SETPRIORITY, [pid, nice as usize, 0])
+}
+
+/// 功能：获取进程的 nice 值。
+/// 参数：pid 为 0 时表示当前进程。
+/// 返回值：返回 20 - nice，范围是 [1, 40] ；进程不存在时返回 -1 。
+/// syscall ID : 141
+pub fn sys_getpriority(pid: usize) -> isize {
+    syscall(SYSCALL_GETPRIORITY, [pid, 0, 0])
+}
+
+/// 功能：设置进程的所有线程的调度类，实时调度类和 Deadline 调度类的线程总是优先于普通线程运行。
+/// 参数：pid 为 0 时表示当前进程；policy 为 SCHED_NORMAL、SCHED_FIFO、SCHED_RR 或 SCHED_DEADLINE；
+/// param 表示调度参数，SCHED_FIFO 和 SCHED_RR 使用其中的 priority，范围是 [1, 99]，
+/// SCHED_DEADLINE 使用 runtime_ms 和 period_ms，要求 0 < runtime_ms <= period_ms。
+/// 返回值：成功返回 0 ，进程不存在或者参数错误时返回 -1 。
+/// syscall ID : 119
+pub fn sys_sched_setscheduler(pid: usize, policy: usize, param: &SchedParam) -> isize {
+    syscall(SYSCALL_SCHED_SETSCHEDULER, [pid, policy, param as *const _ as usize])
+}
+
+/// 功能：获取进程的调度类。
+/// 参数：pid 为 0 时表示当前进程。
+/// 返回值：返回调度类；进程不存在时返回 -1 。
+/// syscall ID : 120
+pub fn sys_sched_getscheduler(pid: usize) -> isize {
+    syscall(SYSCALL_SCHED_GETSCHEDULER, [pid, 0, 0])
+}
+
+/// 功能：获取当前进程和已经被回收的子进程的运行时间。
+/// 参数：tms 表示保存运行时间的地址，单位是时钟中断。
+/// 返回值：启动后的时钟中断数。
+/// syscall ID : 153
+pub fn sys_times(tms: &mut Tms) -> isize {
+    syscall(SYSCALL_TIMES, [tms as *mut _ as usize, 0, 0])
+}
+
+/// 功能：获取运行时间和上下文切换次数。
+/// 参数：who 为 RUSAGE_SELF 时表示当前进程的所有线程，为 RUSAGE_CHILDREN 时表示已经被回收的子进程，
+/// 为 RUSAGE_THREAD 时表示当前线程；usage 表示保存结果的地址。
+/// 返回值：成功返回 0 ，参数错误时返回 -1 。
+/// syscall ID : 165
+pub fn sys_getrusage(who: isize, usage: &mut RUsage) -> isize {
+    syscall(SYSCALL_GETRUSAGE, [who as usize, usage as *mut _ as usize, 0])
+}
+
+/// 功能：获取系统的运行时间、空闲时间和负载均值。
+/// 参数：info 表示保存结果的地址。
+/// 返回值：成功返回 0 。
+/// syscall ID : 179
+pub fn sys_sysinfo(info: &mut SysInfo) -> isize {
+    syscall(SYSCALL_SYSINFO, [info as *mut _ as usize, 0, 0])
+}
+
+/// 功能：获取系统全局的内存使用情况。
+/// 参数：info 表示保存内存使用情况的地址。
+/// 返回值：成功返回 0 。
+/// syscall ID : 1040
+pub fn sys_meminfo(info: &mut MemInfo) -> isize {
+    syscall(SYSCALL_MEMINFO, [info as *mut _ as usize, 0, 0])
+}
+
+/// 功能：获取所有进程的内存使用情况，按 PID 从小到大排列。
+/// 参数：infos 表示保存进程信息的数组。
+/// 返回值：写入数组的进程数，进程数多于数组长度时只写入数组长度个。
+/// syscall ID : 1041
+pub fn sys_procinfo(infos: &mut [ProcessInfo]) -> isize {
+    syscall(SYSCALL_PROCINFO, [infos.as_mut_ptr() as usize, infos.len(), 0])
+}
+
+/// 功能：获取已经注册了处理函数的中断线的中断次数，按中断线从小到大排列。
+/// 参数：infos 表示保存中断统计的数组。
+/// 返回值：写入数组的中断线数，多于数组长度时只写入数组长度个。
+/// syscall ID : 1042
+pub fn sys_irqinfo(infos: &mut [IrqInfo]) -> isize {
+    syscall(SYSCALL_IRQINFO, [infos.as_mut_ptr() as usize, infos.len(), 0])
+}